@@ -1,5 +1,5 @@
 import * as Chart from "chart.js";
-import { DataPoint } from "../../model";
+import { DataPoint, ProviderPoint } from "../../model";
 
 const colors = ["#3366cc", "#dc3912", "#ff9900", "#109618", "#990099", "#0099c6", "#dd4477", "#66aa00", "#b82e2e", "#316395", "#994499", "#22aa99", "#aaaa11", "#6633cc", "#e67300", "#8b0707", "#651067", "#329262", "#5574a6", "#3b3eac"];
 
@@ -363,6 +363,59 @@ export function totalCalls (el: HTMLCanvasElement, dataPoints: DataPoint[]) {
   return totalChart;
 }
 
+// the length and limit of a provider and what was waiting on it, as each bucket closed
+export class ProviderChart {
+  private chart: Chart;
+
+  public constructor (el: HTMLCanvasElement, points: ProviderPoint[]) {
+    const chartDataSets = new ChartDataSets();
+    for (const { time, stats } of points) {
+      chartDataSets.setPoint("length", time, stats.len);
+      chartDataSets.setPoint("limit", time, stats.limit, { fill: false });
+      chartDataSets.setPoint("receivers", time, stats.receiverCount, { fill: false, hidden: true });
+      chartDataSets.setPoint("senders", time, stats.senderCount, { fill: false, hidden: true });
+      chartDataSets.setPoint("waiting receivers", time, stats.waitingReceiverCount || 0);
+      chartDataSets.setPoint("waiting senders", time, stats.waitingSenderCount || 0);
+    }
+
+    const datasets = chartDataSets.getDataSets();
+    this.chart = new Chart.Chart(el, {
+      type: "line",
+      data: { datasets },
+      options: {
+        scales: {
+          yAxes: [{
+            ticks: <Chart.TickOptions> {
+              precision: 0,
+              autoSkip: true
+            },
+            type: "linear",
+            scaleLabel: {
+              display: true,
+              labelString: "Count"
+            }
+          }],
+          xAxes: [{
+            type: "time",
+            time: {
+              unit: "second"
+            },
+            ticks: {
+              autoSkip: true,
+              source: "data"
+            }
+          }]
+        },
+        tooltips: {
+          callbacks: {
+            label: ({ yLabel, datasetIndex }) => `${yLabel} ${datasets[datasetIndex || 0].label}`
+          }
+        }
+      }
+    });
+  }
+}
+
   // Default logartihmic graph y axis ticks overlap and do not look good.
   // This function is applied to the chart config, and modifies the y axis ticks after the chart is created.
   const afterBuildTicks = (chart: any) => {
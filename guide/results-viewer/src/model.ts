@@ -302,9 +302,28 @@ interface TimeBucketEntry {
   testErrors: Record<string, number> | undefined;
 }
 
+// the state of a provider when a bucket closed. Older stats files don't have the waiting counts
+export interface ProviderStats {
+  len: number;
+  limit: number;
+  receiverCount: number;
+  senderCount: number;
+  onDemandCount: number;
+  waitingReceiverCount?: number;
+  waitingSenderCount?: number;
+}
+
+export interface ProviderPoint {
+  readonly time: Date;
+  readonly stats: ProviderStats;
+}
+
+export type ParsedProviders = Record<string, ProviderPoint[]>;
+
 interface Buckets {
   time: number;
   entries: Record<string, TimeBucketEntry>;
+  providers?: Record<string, ProviderStats>;
 }
 
 function isTags (tags: unknown): tags is Tags {
@@ -378,9 +397,10 @@ function checkNewJsonEntry (entry: unknown): entry is Header | Tags | Buckets {
     || isBuckets(entry);
 }
 
-export function processNewJson (jsons: unknown[]): [ParsedFileEntry[], string?] {
+export function processNewJson (jsons: unknown[]): [ParsedFileEntry[], string | undefined, ParsedProviders] {
   const tags: BucketId[] = [];
   const data: DataPointPreProcessed[][] = [];
+  const providers: ParsedProviders = {};
   let bucketSize = 0;
   let testName;
   for (const json of jsons) {
@@ -396,6 +416,10 @@ export function processNewJson (jsons: unknown[]): [ParsedFileEntry[], string?]
       tags[json.index] = json.tags;
     } else {
       const time = json.time;
+      for (const [name, stats] of Object.entries(json.providers || {})) {
+        providers[name] = providers[name] || [];
+        providers[name].push({ time: new Date(time * 1000), stats });
+      }
       for (const [index, values] of Object.entries(json.entries)) {
         const dppp: DataPointPreProcessed = {
           time,
@@ -422,5 +446,19 @@ export function processNewJson (jsons: unknown[]): [ParsedFileEntry[], string?]
 
   const statsFile: StatsFile = { buckets };
 
-  return [processJson(statsFile), testName];
+  return [processJson(statsFile), testName, providers];
+}
+
+// combine the providers from multiple stats files, keeping each provider's points in time order
+export function mergeProviders (...allProviders: ParsedProviders[]): ParsedProviders {
+  const merged: ParsedProviders = {};
+  for (const providers of allProviders) {
+    for (const [name, points] of Object.entries(providers)) {
+      merged[name] = (merged[name] || []).concat(points);
+    }
+  }
+  for (const points of Object.values(merged)) {
+    points.sort((a, b) => Number(a.time) - Number(b.time));
+  }
+  return merged;
 }
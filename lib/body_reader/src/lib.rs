@@ -4,8 +4,10 @@ use libflate::non_blocking::{deflate, gzip};
 
 use std::{
     cmp,
-    io::{self, Read},
-    iter,
+    error::Error as StdError,
+    fmt,
+    io::{self, Read, Write},
+    iter, mem,
 };
 
 // a reader to help us in getting the bytes out of a response body
@@ -37,35 +39,62 @@ pub enum Compression {
     Deflate,
     Gzip,
     None,
+    Zstd,
 }
 
 impl Compression {
-    // used to determine the typeof compression from the value specified in "Content-Encoding" header
+    // used to determine the typeof compression from a single coding specified in "Content-Encoding" header
     pub fn try_from(ce: &str) -> Option<Compression> {
-        match ce {
-            "br" => Compression::Brotli.into(),
-            "deflate" => Compression::Deflate.into(),
-            "gzip" => Compression::Gzip.into(),
-            "" => Compression::None.into(),
-            _ => None,
+        let ce = ce.trim();
+        if ce.eq_ignore_ascii_case("br") {
+            Compression::Brotli.into()
+        } else if ce.eq_ignore_ascii_case("deflate") {
+            Compression::Deflate.into()
+        } else if ce.eq_ignore_ascii_case("gzip") || ce.eq_ignore_ascii_case("x-gzip") {
+            Compression::Gzip.into()
+        } else if ce.eq_ignore_ascii_case("zstd") {
+            Compression::Zstd.into()
+        } else if ce.is_empty() || ce.eq_ignore_ascii_case("identity") {
+            Compression::None.into()
+        } else {
+            None
         }
     }
+
+    // used to parse the full value of a "Content-Encoding" header, which lists the codings in the order they were applied
+    pub fn list_from(ce: &str) -> Result<Vec<Compression>, UnsupportedEncoding> {
+        ce.split(',')
+            .filter_map(|coding| match Compression::try_from(coding) {
+                Some(Compression::None) => None,
+                Some(c) => Some(Ok(c)),
+                None => Some(Err(UnsupportedEncoding(coding.trim().into()))),
+            })
+            .collect()
+    }
+}
+
+// the error returned when a "Content-Encoding" header lists a coding we cannot decode
+#[derive(Debug)]
+pub struct UnsupportedEncoding(pub String);
+
+impl fmt::Display for UnsupportedEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported content-encoding `{}`", self.0)
+    }
 }
 
+impl StdError for UnsupportedEncoding {}
+
 enum Inner {
     Brotli(Box<brotli::Decompressor<BytesReader>>),
     Deflate(Box<deflate::Decoder<BytesReader>>),
     Gzip(Box<gzip::Decoder<BytesReader>>),
     None,
+    Zstd(Box<zstd::stream::write::Decoder<'static, Vec<u8>>>),
 }
 
-pub struct BodyReader {
-    buffer: BytesMut,
-    inner: Inner,
-}
-
-impl BodyReader {
-    pub fn new(c: Compression) -> Self {
+impl Inner {
+    fn new(c: Compression) -> Result<Self, io::Error> {
         let inner = match c {
             Compression::Brotli => {
                 Inner::Brotli(brotli::Decompressor::new(BytesReader::new(), 8192).into())
@@ -75,50 +104,173 @@ impl BodyReader {
             }
             Compression::Gzip => Inner::Gzip(gzip::Decoder::new(BytesReader::new()).into()),
             Compression::None => Inner::None,
+            Compression::Zstd => Inner::Zstd(zstd::stream::write::Decoder::new(Vec::new())?.into()),
         };
-        let mut buffer = BytesMut::with_capacity(8192);
-        buffer.extend(iter::repeat(0).take(8192));
-        BodyReader { buffer, inner }
+        Ok(inner)
     }
 
-    // used to decompress incoming bytes. The bytes to decompress are passed in as `in_bytes` and the decompressed bytes are written to `out_bytes`
-    pub fn decode(&mut self, in_bytes: Bytes, out_bytes: &mut BytesMut) -> Result<(), io::Error> {
-        match &mut self.inner {
+    fn decode(
+        &mut self,
+        in_bytes: Bytes,
+        buffer: &mut [u8],
+        out_bytes: &mut BytesMut,
+    ) -> Result<(), io::Error> {
+        let r: &mut dyn Read = match self {
             Inner::Brotli(r) => {
                 r.get_mut().0.extend(in_bytes);
-                loop {
-                    match r.read(&mut self.buffer) {
-                        Ok(n) if n == 0 => break,
-                        Ok(n) => out_bytes.extend_from_slice(&self.buffer[0..n]),
-                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                        Err(e) => return Err(e),
-                    }
-                }
+                r
             }
             Inner::Deflate(r) => {
                 r.as_inner_mut().0.extend(in_bytes);
-                loop {
-                    match r.read(&mut self.buffer) {
-                        Ok(n) if n == 0 => break,
-                        Ok(n) => out_bytes.extend_from_slice(&self.buffer[0..n]),
-                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                        Err(e) => return Err(e),
-                    }
-                }
+                r
             }
             Inner::Gzip(r) => {
                 r.as_inner_mut().0.extend(in_bytes);
-                loop {
-                    match r.read(&mut self.buffer) {
-                        Ok(n) if n == 0 => break,
-                        Ok(n) => out_bytes.extend_from_slice(&self.buffer[0..n]),
-                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                        Err(e) => return Err(e),
-                    }
-                }
-            }
-            Inner::None => out_bytes.extend(in_bytes),
+                r
+            }
+            Inner::None => {
+                out_bytes.extend(in_bytes);
+                return Ok(());
+            }
+            Inner::Zstd(w) => {
+                w.write_all(&in_bytes)?;
+                w.flush()?;
+                out_bytes.extend_from_slice(w.get_ref());
+                w.get_mut().clear();
+                return Ok(());
+            }
+        };
+        loop {
+            match r.read(buffer) {
+                Ok(n) if n == 0 => break,
+                Ok(n) => out_bytes.extend_from_slice(&buffer[0..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct BodyReader {
+    buffer: BytesMut,
+    // the decoders, in the order they need to be applied
+    inners: Vec<Inner>,
+}
+
+impl BodyReader {
+    pub fn new(c: Compression) -> Self {
+        let inner = Inner::new(c).expect("should be able to create decoder");
+        BodyReader::with_inners(vec![inner])
+    }
+
+    // creates a reader for the value of a "Content-Encoding" header, which can list multiple codings (ex: "gzip, br")
+    pub fn from_content_encoding(ce: &str) -> Result<Self, io::Error> {
+        let inners = Compression::list_from(ce)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .into_iter()
+            .rev()
+            .map(Inner::new)
+            .collect::<Result<_, _>>()?;
+        Ok(BodyReader::with_inners(inners))
+    }
+
+    fn with_inners(inners: Vec<Inner>) -> Self {
+        let mut buffer = BytesMut::with_capacity(8192);
+        buffer.extend(iter::repeat(0).take(8192));
+        BodyReader { buffer, inners }
+    }
+
+    // used to decompress incoming bytes. The bytes to decompress are passed in as `in_bytes` and the decompressed bytes are written to `out_bytes`
+    pub fn decode(&mut self, in_bytes: Bytes, out_bytes: &mut BytesMut) -> Result<(), io::Error> {
+        let mut in_bytes = in_bytes;
+        let last = self.inners.len().saturating_sub(1);
+        for (i, inner) in self.inners.iter_mut().enumerate() {
+            if i == last {
+                return inner.decode(in_bytes, &mut self.buffer, out_bytes);
+            }
+            let mut intermediate = BytesMut::new();
+            inner.decode(in_bytes, &mut self.buffer, &mut intermediate)?;
+            in_bytes = intermediate.freeze();
+        }
+        // no codings means the body was not compressed
+        out_bytes.extend(in_bytes);
+        Ok(())
+    }
+}
+
+enum WriterInner {
+    Brotli(Box<::brotli::CompressorWriter<Vec<u8>>>),
+    Deflate(Box<libflate::deflate::Encoder<Vec<u8>>>),
+    Gzip(Box<libflate::gzip::Encoder<Vec<u8>>>),
+    None,
+    Zstd(Box<zstd::stream::write::Encoder<'static, Vec<u8>>>),
+}
+
+// the counterpart to `BodyReader`, used to compress an outgoing body
+pub struct BodyWriter {
+    inner: WriterInner,
+}
+
+impl BodyWriter {
+    pub fn new(c: Compression) -> Result<Self, io::Error> {
+        let inner = match c {
+            Compression::Brotli => {
+                WriterInner::Brotli(::brotli::CompressorWriter::new(Vec::new(), 8192, 5, 22).into())
+            }
+            Compression::Deflate => {
+                WriterInner::Deflate(libflate::deflate::Encoder::new(Vec::new()).into())
+            }
+            Compression::Gzip => {
+                WriterInner::Gzip(libflate::gzip::Encoder::new(Vec::new())?.into())
+            }
+            Compression::None => WriterInner::None,
+            Compression::Zstd => {
+                WriterInner::Zstd(zstd::stream::write::Encoder::new(Vec::new(), 0)?.into())
+            }
+        };
+        Ok(BodyWriter { inner })
+    }
+
+    // used to compress outgoing bytes. The bytes to compress are passed in as `in_bytes` and any compressed bytes ready to be sent are written to `out_bytes`
+    pub fn encode(&mut self, in_bytes: &[u8], out_bytes: &mut BytesMut) -> Result<(), io::Error> {
+        let buffer = match &mut self.inner {
+            WriterInner::Brotli(w) => {
+                w.write_all(in_bytes)?;
+                w.get_mut()
+            }
+            WriterInner::Deflate(w) => {
+                w.write_all(in_bytes)?;
+                w.as_inner_mut()
+            }
+            WriterInner::Gzip(w) => {
+                w.write_all(in_bytes)?;
+                w.as_inner_mut()
+            }
+            WriterInner::None => {
+                out_bytes.extend_from_slice(in_bytes);
+                return Ok(());
+            }
+            WriterInner::Zstd(w) => {
+                w.write_all(in_bytes)?;
+                w.get_mut()
+            }
+        };
+        out_bytes.extend_from_slice(buffer);
+        buffer.clear();
+        Ok(())
+    }
+
+    // finishes the compressed stream, writing any remaining bytes to `out_bytes`. Any calls to `encode` after this will pass the bytes through uncompressed
+    pub fn finish(&mut self, out_bytes: &mut BytesMut) -> Result<(), io::Error> {
+        let buffer = match mem::replace(&mut self.inner, WriterInner::None) {
+            WriterInner::Brotli(w) => w.into_inner(),
+            WriterInner::Deflate(w) => w.finish().into_result()?,
+            WriterInner::Gzip(w) => w.finish().into_result()?,
+            WriterInner::None => return Ok(()),
+            WriterInner::Zstd(w) => w.finish()?,
         };
+        out_bytes.extend_from_slice(&buffer);
         Ok(())
     }
 }
@@ -152,6 +304,12 @@ mod tests {
         }
     }
 
+    impl IntoInner for zstd::stream::write::Encoder<'static, Vec<u8>> {
+        fn into_inner(self: Box<Self>) -> Vec<u8> {
+            self.finish().unwrap()
+        }
+    }
+
     impl IntoInner for Vec<u8> {
         fn into_inner(self: Box<Self>) -> Vec<u8> {
             *self
@@ -163,11 +321,13 @@ mod tests {
         let brotli = ::brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
         let gzip = libflate::gzip::Encoder::new(Vec::new()).unwrap();
         let deflate = libflate::deflate::Encoder::new(Vec::new());
+        let zstd = zstd::stream::write::Encoder::new(Vec::new(), 0).unwrap();
 
         let flavors: Vec<(Box<dyn IntoInner>, &str)> = vec![
             (Box::new(brotli), "br"),
             (Box::new(gzip), "gzip"),
             (Box::new(deflate), "deflate"),
+            (Box::new(zstd), "zstd"),
             (Box::new(Vec::new()), ""),
         ];
 
@@ -190,4 +350,57 @@ mod tests {
             assert_eq!(left, TRUTH, "index {}", i);
         }
     }
+
+    #[test]
+    fn body_writer_works() {
+        for compression in &["br", "gzip", "deflate", "zstd", ""] {
+            let mut writer = BodyWriter::new(Compression::try_from(compression).unwrap()).unwrap();
+            let mut encoded_bytes = BytesMut::new();
+            for chunk in TRUTH.as_bytes().chunks(7) {
+                writer.encode(chunk, &mut encoded_bytes).unwrap();
+            }
+            writer.finish(&mut encoded_bytes).unwrap();
+
+            let mut reader = BodyReader::new(Compression::try_from(compression).unwrap());
+            let mut decoded_bytes = BytesMut::new();
+            reader
+                .decode(encoded_bytes.freeze(), &mut decoded_bytes)
+                .unwrap();
+            let left = std::str::from_utf8(&decoded_bytes).unwrap();
+            assert_eq!(left, TRUTH, "compression {}", compression);
+        }
+    }
+
+    #[test]
+    fn body_reader_handles_multiple_codings() {
+        // codings are listed in the order they were applied
+        let mut input = BytesMut::new();
+        let mut gzip = BodyWriter::new(Compression::Gzip).unwrap();
+        gzip.encode(TRUTH.as_bytes(), &mut input).unwrap();
+        gzip.finish(&mut input).unwrap();
+        let mut encoded_bytes = BytesMut::new();
+        let mut zstd = BodyWriter::new(Compression::Zstd).unwrap();
+        zstd.encode(&input, &mut encoded_bytes).unwrap();
+        zstd.finish(&mut encoded_bytes).unwrap();
+        let encoded_bytes = encoded_bytes.freeze();
+
+        let mut reader = BodyReader::from_content_encoding("gzip, identity, ZSTD").unwrap();
+        let mut decoded_bytes = BytesMut::new();
+        for n in (0..encoded_bytes.len()).step_by(4) {
+            let slice = encoded_bytes.slice(n..cmp::min(n + 4, encoded_bytes.len()));
+            reader.decode(slice, &mut decoded_bytes).unwrap();
+        }
+        let left = std::str::from_utf8(&decoded_bytes).unwrap();
+        assert_eq!(left, TRUTH);
+    }
+
+    #[test]
+    fn unsupported_encodings_error() {
+        for ce in &["compress", "gzip, foo", "br,,lz4"] {
+            let err = BodyReader::from_content_encoding(ce).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", ce);
+        }
+        let err = Compression::list_from("gzip, foo").err().unwrap();
+        assert_eq!(err.to_string(), "unsupported content-encoding `foo`");
+    }
 }
@@ -56,6 +56,8 @@ struct Channel<T: Serialize> {
     on_demand_count: AtomicUsize,
     receiver_count: AtomicUsize,
     sender_count: AtomicUsize,
+    waiting_receiver_count: AtomicUsize,
+    waiting_sender_count: AtomicUsize,
     unique: Option<HashSet>,
 }
 
@@ -75,6 +77,8 @@ impl<T: Serialize> Channel<T> {
             on_demand_count: AtomicUsize::new(1),
             receiver_count: AtomicUsize::new(1),
             sender_count: AtomicUsize::new(1),
+            waiting_receiver_count: AtomicUsize::new(0),
+            waiting_sender_count: AtomicUsize::new(0),
             unique,
         }
     }
@@ -231,6 +235,34 @@ impl<T: Serialize> Channel<T> {
     fn decrement_receiver_count(&self) -> usize {
         self.receiver_count.fetch_sub(1, Ordering::Release) - 1
     }
+
+    // get the number of senders waiting for room in the channel
+    fn waiting_sender_count(&self) -> usize {
+        self.waiting_sender_count.load(Ordering::Acquire)
+    }
+
+    // get the number of receivers waiting for a value in the channel
+    fn waiting_receiver_count(&self) -> usize {
+        self.waiting_receiver_count.load(Ordering::Acquire)
+    }
+}
+
+// adjust a waiting count when a `Sender` or `Receiver` starts or stops listening for an event
+fn update_waiting_count(
+    count: &AtomicUsize,
+    listener: &mut Option<EventListener>,
+    new_listener: Option<EventListener>,
+) {
+    match (listener.is_some(), new_listener.is_some()) {
+        (false, true) => {
+            count.fetch_add(1, Ordering::Release);
+        }
+        (true, false) => {
+            count.fetch_sub(1, Ordering::Release);
+        }
+        _ => (),
+    }
+    *listener = new_listener;
 }
 
 pub struct Sender<T: Serialize> {
@@ -253,6 +285,15 @@ impl<T> SendState<T> {
 
 #[allow(clippy::len_without_is_empty)]
 impl<T: Serialize> Sender<T> {
+    // set or clear the listener for room in the channel, keeping the waiting sender count
+    fn set_listener(&mut self, listener: Option<EventListener>) {
+        update_waiting_count(
+            &self.channel.waiting_sender_count,
+            &mut self.listener,
+            listener,
+        );
+    }
+
     // get how many items are in the underlying channel
     pub fn len(&self) -> usize {
         self.channel.len()
@@ -304,6 +345,7 @@ impl<T: Serialize> Clone for Sender<T> {
 // `Sender`s, notify all `Receiver`s that are waiting for data.
 impl<T: Serialize> Drop for Sender<T> {
     fn drop(&mut self) {
+        self.set_listener(None);
         if self.channel.decrement_sender_count() == 0 {
             self.channel.notify_all_receivers();
         }
@@ -379,22 +421,23 @@ impl<T: Serialize> Sink<T> for Sender<T> {
     fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
         loop {
             if self.no_receivers() {
-                self.listener = None;
+                self.set_listener(None);
                 return Poll::Ready(Err(ChannelClosed::new()));
             }
 
             if let Some(listener) = self.listener.as_mut() {
                 match Pin::new(listener).poll(cx) {
-                    Poll::Ready(()) => self.listener = None,
+                    Poll::Ready(()) => self.set_listener(None),
                     _ => return Poll::Pending,
                 }
             }
 
             if self.channel.len() < self.channel.limit() {
-                self.listener = None;
+                self.set_listener(None);
                 return Poll::Ready(Ok(()));
             } else if self.listener.is_none() {
-                self.listener = Some(self.channel.sender_listen());
+                let listener = self.channel.sender_listen();
+                self.set_listener(Some(listener));
             }
         }
     }
@@ -441,6 +484,8 @@ impl<T: Serialize> ChannelStatsReader<T> {
             receiver_count: self.channel.receiver_count(),
             sender_count: self.channel.sender_count(),
             on_demand_count: self.channel.on_demand_count(),
+            waiting_receiver_count: self.channel.waiting_receiver_count(),
+            waiting_sender_count: self.channel.waiting_sender_count(),
         }
     }
 }
@@ -457,6 +502,8 @@ pub struct ChannelStats<'a> {
     pub receiver_count: usize,
     pub sender_count: usize,
     pub on_demand_count: usize,
+    pub waiting_receiver_count: usize,
+    pub waiting_sender_count: usize,
 }
 
 pub struct Receiver<T: Serialize> {
@@ -464,6 +511,17 @@ pub struct Receiver<T: Serialize> {
     listener: Option<EventListener>,
 }
 
+impl<T: Serialize> Receiver<T> {
+    // set or clear the listener for a value in the channel, keeping the waiting receiver count
+    fn set_listener(&mut self, listener: Option<EventListener>) {
+        update_waiting_count(
+            &self.channel.waiting_receiver_count,
+            &mut self.listener,
+            listener,
+        );
+    }
+}
+
 // whenever a `Receiver` is cloned, be sure to increment the receiver count
 impl<T: Serialize> Clone for Receiver<T> {
     fn clone(&self) -> Self {
@@ -479,6 +537,7 @@ impl<T: Serialize> Clone for Receiver<T> {
 // `Sender`s, notify all `Senders`s that are waiting to send.
 impl<T: Serialize> Drop for Receiver<T> {
     fn drop(&mut self) {
+        self.set_listener(None);
         if self.channel.decrement_receiver_count() == 0 {
             // notify all senders so they will see there are no more receivers
             // and stop awaiting
@@ -495,20 +554,21 @@ impl<T: Serialize> Stream for Receiver<T> {
         loop {
             if let Some(listener) = self.listener.as_mut() {
                 match Pin::new(listener).poll(cx) {
-                    Poll::Ready(()) => self.listener = None,
+                    Poll::Ready(()) => self.set_listener(None),
                     Poll::Pending => return Poll::Pending,
                 }
             }
 
             let msg = self.channel.recv();
             if msg.is_some() {
-                self.listener = None;
+                self.set_listener(None);
                 return Poll::Ready(msg);
             } else if self.channel.sender_count() == 0 {
-                self.listener = None;
+                self.set_listener(None);
                 return Poll::Ready(None);
             } else if self.listener.is_none() {
-                self.listener = Some(self.channel.receiver_listen());
+                let listener = self.channel.receiver_listen();
+                self.set_listener(Some(listener));
             }
         }
     }
@@ -616,6 +676,33 @@ mod tests {
         assert_eq!(left, right);
     }
 
+    #[test]
+    fn channel_counts_waiting_senders_and_receivers() {
+        let (mut tx, mut rx) = channel::<bool>(Limit::Static(1), false);
+        let stats = ChannelStatsReader::new("test".into(), &rx);
+
+        // the receiver waits while the channel is empty
+        assert_eq!(rx.next().now_or_never(), None);
+        assert_eq!(stats.get_stats(0).waiting_receiver_count, 1);
+
+        // the sender waits while the channel is full
+        assert_eq!(tx.send(true).now_or_never(), Some(Ok(())));
+        assert_eq!(tx.send(true).now_or_never(), None);
+        assert_eq!(stats.get_stats(0).waiting_sender_count, 1);
+
+        assert_eq!(rx.next().now_or_never(), Some(Some(true)));
+        assert_eq!(stats.get_stats(0).waiting_receiver_count, 0);
+
+        assert_eq!(tx.send(true).now_or_never(), Some(Ok(())));
+        assert_eq!(stats.get_stats(0).waiting_sender_count, 0);
+
+        // a sender which is dropped while waiting is no longer counted
+        assert_eq!(tx.send(true).now_or_never(), None);
+        assert_eq!(stats.get_stats(0).waiting_sender_count, 1);
+        drop(tx);
+        assert_eq!(stats.get_stats(0).waiting_sender_count, 0);
+    }
+
     #[test]
     fn unique_channel_works() {
         let cap = 8; // how many unique values we'll put into the channel
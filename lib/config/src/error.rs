@@ -46,10 +46,18 @@ impl From<ExecutingExpressionError> for Error {
 #[derive(Clone, Debug)]
 pub enum Error {
     ExpressionErr(CreatingExpressionError),
+    InvalidBackoff(String, Marker),
+    InvalidBandwidth(String, Marker),
+    InvalidByteSize(String, Marker),
     InvalidDuration(String, Marker),
+    InvalidHistogramSignificantDigits(usize, Marker),
     InvalidLoadPattern(Marker),
+    InvalidMethod(String, Marker),
+    InvalidMetric(String, Marker),
     InvalidPeakLoad(String, Marker),
     InvalidPercent(String, Marker),
+    InvalidStatsSink(String, Marker),
+    InvalidThreshold(String, Marker),
     InvalidYaml(ScanError),
     MissingEnvironmentVariable(String, Marker),
     MissingForEach(Marker),
@@ -57,6 +65,9 @@ pub enum Error {
     MissingLoadPattern(Marker),
     MissingYamlField(&'static str, Marker),
     RecursiveForEachReference(Marker),
+    RetryOnTimeoutWithoutPerTryTimeout(Marker),
+    RetryWithStreamedBody(Marker),
+    UnknownCheck(String, Marker),
     UnknownLogger(String, Marker),
     UnrecognizedKey(String, Option<String>, Marker),
     YamlDeserialize(Option<String>, Marker),
@@ -141,10 +152,18 @@ impl fmt::Display for Error {
         use Error::*;
         match self {
             ExpressionErr(e) => e.fmt(f),
+            InvalidBackoff(b, m) => write!(f, "invalid backoff `{}` at line {} column {}", b, m.line(), m.col()),
+            InvalidBandwidth(b, m) => write!(f, "invalid bandwidth `{}` at line {} column {}", b, m.line(), m.col()),
+            InvalidByteSize(s, m) => write!(f, "invalid size `{}` at line {} column {}", s, m.line(), m.col()),
             InvalidDuration(d, m) => write!(f, "invalid duration `{}` at line {} column {}", d, m.line(), m.col()),
+            InvalidHistogramSignificantDigits(d, m) => write!(f, "invalid histogram_significant_digits `{}`, expected a number from 1 to 5 at line {} column {}", d, m.line(), m.col()),
             InvalidLoadPattern(m) => write!(f, "invalid load_pattern at line {} column {}", m.line(), m.col()),
+            InvalidMethod(s, m) => write!(f, "invalid method `{}` at line {} column {}", s, m.line(), m.col()),
+            InvalidMetric(s, m) => write!(f, "invalid metric `{}`, expected `counter(..)`, `gauge(..)` or `histogram(..)` at line {} column {}", s, m.line(), m.col()),
             InvalidPeakLoad(p, m) => write!(f, "invalid peak_load `{}` at line {} column {}", p, m.line(), m.col()),
             InvalidPercent(p, m) => write!(f, "invalid percent `{}` at line {} column {}", p, m.line(), m.col()),
+            InvalidStatsSink(s, m) => write!(f, "invalid stats sink, {} at line {} column {}", s, m.line(), m.col()),
+            InvalidThreshold(t, m) => write!(f, "invalid threshold `{}` at line {} column {}", t, m.line(), m.col()),
             InvalidYaml(e) => write!(f, "yaml syntax error:\n\t{}", e),
             MissingEnvironmentVariable(v, m) => write!(f, "undefined environment variable `{}` at line {} column {}", v, m.line(), m.col()),
             MissingForEach(m) => write!(f, "missing `for_each` at line {} column {}", m.line(), m.col()),
@@ -155,6 +174,9 @@ impl fmt::Display for Error {
             ),
             MissingYamlField(field, m) => write!(f, "missing field `{}` at line {} column {}", field, m.line(), m.col()),
             RecursiveForEachReference(m) => write!(f, "recursive `for_each` reference at line {} column {}", m.line(), m.col()),
+            RetryOnTimeoutWithoutPerTryTimeout(m) => write!(f, "`retry` on `timeout` needs a `per_try_timeout`, otherwise the first attempt uses the whole `request_timeout`, at line {} column {}", m.line(), m.col()),
+            RetryWithStreamedBody(m) => write!(f, "`retry` cannot be used with a file, generated or multipart file body, which are streamed, at line {} column {}", m.line(), m.col()),
+            UnknownCheck(c, m) => write!(f, "unknown check `{}` at line {} column {}", c, m.line(), m.col()),
             UnknownLogger(l, m) => write!(f, "unknown logger `{}` at line {} column {}", l, m.line(), m.col()),
             UnrecognizedKey(k, Some(name), m) => write!(f, "unrecognized key `{}` in `{}` at line {} column {}", k, name, m.line(), m.col()),
             UnrecognizedKey(k, None, m) => write!(f, "unrecognized key `{}` at line {} column {}", k, m.line(), m.col()),
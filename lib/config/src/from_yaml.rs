@@ -147,6 +147,16 @@ impl FromYaml for i64 {
     }
 }
 
+impl FromYaml for f64 {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        event
+            .as_x()
+            .map(|i| (i, marker))
+            .ok_or(Error::YamlDeserialize(None, marker))
+    }
+}
+
 impl FromYaml for usize {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
         let (event, marker) = decoder.next()?;
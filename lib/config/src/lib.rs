@@ -44,25 +44,86 @@ fn map_yaml_deserialize_err(name: String) -> impl FnOnce(Error) -> Error {
     }
 }
 
+const STANDARD_METHODS: [&str; 9] = [
+    "CONNECT", "DELETE", "GET", "HEAD", "OPTIONS", "PATCH", "POST", "PUT", "TRACE",
+];
+
+// parse an HTTP method. Any valid token is allowed, so extension methods (PROPFIND, PURGE, etc) can
+// be used
+pub fn method_from_str(s: &str) -> Option<Method> {
+    Method::from_bytes(s.trim().as_bytes()).ok()
+}
+
+// the standard method which an extension method looks like a misspelling of, such as `get` or `GIT`
+fn similar_standard_method(method: &Method) -> Option<&'static str> {
+    let s = method.as_str();
+    if STANDARD_METHODS.contains(&s) {
+        return None;
+    }
+    let upper = s.to_ascii_uppercase();
+    STANDARD_METHODS
+        .iter()
+        .copied()
+        .find(|m| within_one_edit(m, &upper))
+}
+
+// whether the strings are the same but for one added, removed or changed character
+fn within_one_edit(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if long.len() - short.len() > 1 {
+        return false;
+    }
+    let prefix = short.iter().zip(long).take_while(|(a, b)| a == b).count();
+    if prefix == short.len() {
+        true
+    } else if short.len() == long.len() {
+        short[prefix + 1..] == long[prefix + 1..]
+    } else {
+        short[prefix..] == long[prefix + 1..]
+    }
+}
+
 impl FromYaml for Method {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
         let (event, marker) = decoder.next()?;
-        let method = match event.as_str().map(|s| s.trim()) {
-            Some("POST") => Method::POST,
-            Some("GET") => Method::GET,
-            Some("PUT") => Method::PUT,
-            Some("HEAD") => Method::HEAD,
-            Some("DELETE") => Method::DELETE,
-            Some("OPTIONS") => Method::OPTIONS,
-            Some("CONNECT") => Method::CONNECT,
-            Some("PATCH") => Method::PATCH,
-            Some("TRACE") => Method::TRACE,
-            _ => return Err(Error::YamlDeserialize(None, marker)),
-        };
+        let method = event
+            .as_str()
+            .and_then(method_from_str)
+            .ok_or(Error::YamlDeserialize(None, marker))?;
         Ok((method, marker))
     }
 }
 
+// a method which is either known when the config is parsed or is a template
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+enum PreMethod {
+    Static(Method),
+    Template(PreTemplate),
+}
+
+impl Default for PreMethod {
+    fn default() -> Self {
+        PreMethod::Static(Method::GET)
+    }
+}
+
+impl FromYaml for PreMethod {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, _) = decoder.peek()?;
+        match event {
+            YamlEvent::Scalar(s, ..) if s.contains("${") => {
+                let (t, marker) = FromYaml::parse(decoder)?;
+                Ok((PreMethod::Template(t), marker))
+            }
+            _ => {
+                let (m, marker) = FromYaml::parse(decoder)?;
+                Ok((PreMethod::Static(m), marker))
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum Limit {
     Dynamic(usize),
@@ -119,6 +180,15 @@ pub enum HitsPer {
     Minute(f32),
 }
 
+impl HitsPer {
+    pub fn per_second(&self) -> f64 {
+        match self {
+            HitsPer::Second(s) => *s as f64,
+            HitsPer::Minute(m) => *m as f64 / 60.0,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct LinearBuilder {
     pub pieces: Vec<LinearBuilderPiece>,
@@ -144,6 +214,43 @@ impl LinearBuilder {
     pub fn duration(&self) -> Duration {
         self.duration
     }
+
+    // the percent of the peak load at the given point in the load pattern, or `None` once the
+    // load pattern has ended
+    pub fn percent_at(&self, elapsed: Duration) -> Option<f64> {
+        let mut start = Duration::from_secs(0);
+        for piece in &self.pieces {
+            let end = start + piece.duration;
+            if elapsed < end {
+                let progress = (elapsed - start).as_secs_f64() / piece.duration.as_secs_f64();
+                let percent =
+                    piece.start_percent + (piece.end_percent - piece.start_percent) * progress;
+                return Some(percent);
+            }
+            start = end;
+        }
+        None
+    }
+
+    // the area under the load pattern between two points in it, in percent-seconds. Multiplied by
+    // the peak load in hits per second this is how many hits the load pattern calls for
+    pub fn percent_seconds_between(&self, from: Duration, to: Duration) -> f64 {
+        let mut total = 0.0;
+        let mut start = Duration::from_secs(0);
+        for piece in &self.pieces {
+            let end = start + piece.duration;
+            let (a, b) = (from.max(start), to.min(end));
+            if a < b {
+                let percent_at = |d: Duration| {
+                    let progress = (d - start).as_secs_f64() / piece.duration.as_secs_f64();
+                    piece.start_percent + (piece.end_percent - piece.start_percent) * progress
+                };
+                total += (percent_at(a) + percent_at(b)) / 2.0 * (b - a).as_secs_f64();
+            }
+            start = end;
+        }
+        total
+    }
 }
 
 #[derive(Clone)]
@@ -1175,13 +1282,112 @@ impl FromYaml for LogsPreProcessed {
     }
 }
 
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct MetricPreProcessed {
+    // the kind of metric wrapping an expression, such as `histogram(response.body.items.length)`
+    select: WithMarker<String>,
+    where_clause: Option<WithMarker<String>>,
+}
+
+impl MetricPreProcessed {
+    fn evaluate(
+        self,
+        static_vars: &BTreeMap<String, json::Value>,
+        required_providers: &mut RequiredProviders,
+    ) -> Result<(MetricKind, Select), Error> {
+        let (select, marker) = self.select.destruct();
+        let re = Regex::new(r"(?s)^\s*(counter|gauge|histogram)\s*\((.+)\)\s*$")
+            .expect("should be a valid regex");
+        let captures = re
+            .captures(&select)
+            .ok_or_else(|| Error::InvalidMetric(select.clone(), marker))?;
+        let kind = match &captures[1] {
+            "counter" => MetricKind::Counter,
+            "gauge" => MetricKind::Gauge,
+            _ => MetricKind::Histogram,
+        };
+        let value = EndpointProvidesPreProcessed {
+            for_each: Default::default(),
+            select: WithMarker::new(captures[2].into(), marker),
+            send: None,
+            where_clause: self.where_clause,
+        };
+        let select = Select::new(value, static_vars, required_providers, false)?;
+        Ok((kind, select))
+    }
+}
+
+impl FromYaml for MetricPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        // a metric can be just the kind and expression
+        if let (YamlEvent::Scalar(..), _) = decoder.peek()? {
+            let (select, marker) = FromYaml::parse(decoder)?;
+            let metric = MetricPreProcessed {
+                select,
+                where_clause: None,
+            };
+            return Ok((metric, marker));
+        }
+        let mut select = None;
+        let mut where_clause = None;
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "select" => {
+                        let r =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        select = Some(r);
+                    }
+                    "where" => {
+                        let v =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        where_clause = Some(v);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, Some("metrics".into()), marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let select = select.ok_or(Error::MissingYamlField("select", marker))?;
+        let metric = MetricPreProcessed {
+            select,
+            where_clause,
+        };
+        Ok((metric, marker))
+    }
+}
+
 #[cfg_attr(debug_assertions, derive(Debug))]
 struct EndpointPreProcessed {
     declare: BTreeMap<String, PreValueOrExpression>,
     headers: TupleVec<String, Nullable<PreTemplate>>,
     body: Option<Body>,
+    checks: TupleVec<String, WithMarker<String>>,
+    compress: Option<BodyCompression>,
     load_pattern: Option<PreLoadPattern>,
-    method: Method,
+    method: PreMethod,
     on_demand: bool,
     peak_load: Option<PreHitsPer>,
     tags: BTreeMap<String, PreTemplate>,
@@ -1189,8 +1395,12 @@ struct EndpointPreProcessed {
     provides: TupleVec<String, EndpointProvidesPreProcessed>,
     logs: TupleVec<String, LogsPreProcessed>,
     max_parallel_requests: Option<NonZeroUsize>,
+    metrics: TupleVec<String, MetricPreProcessed>,
     no_auto_returns: bool,
     request_timeout: Option<PreDuration>,
+    retry: Option<RetryPreProcessed>,
+    sla: Option<BTreeMap<String, PreDuration>>,
+    throttle: Option<ThrottlePreProcessed>,
     marker: Marker,
 }
 
@@ -1200,6 +1410,8 @@ impl PartialEq for EndpointPreProcessed {
         self.declare == other.declare
             && self.headers == other.headers
             && self.body == other.body
+            && self.checks == other.checks
+            && self.compress == other.compress
             && self.load_pattern == other.load_pattern
             && self.method == other.method
             && self.on_demand == other.on_demand
@@ -1209,8 +1421,12 @@ impl PartialEq for EndpointPreProcessed {
             && self.provides == other.provides
             && self.logs == other.logs
             && self.max_parallel_requests == other.max_parallel_requests
+            && self.metrics == other.metrics
             && self.no_auto_returns == other.no_auto_returns
             && self.request_timeout == other.request_timeout
+            && self.retry == other.retry
+            && self.sla == other.sla
+            && self.throttle == other.throttle
     }
 }
 
@@ -1219,6 +1435,8 @@ impl FromYaml for EndpointPreProcessed {
         let mut declare = None;
         let mut headers = None;
         let mut body = None;
+        let mut checks = None;
+        let mut compress = None;
         let mut load_pattern = None;
         let mut method = None;
         let mut on_demand = None;
@@ -1228,8 +1446,12 @@ impl FromYaml for EndpointPreProcessed {
         let mut provides = None;
         let mut logs = None;
         let mut max_parallel_requests = None;
+        let mut metrics = None;
         let mut no_auto_returns = None;
         let mut request_timeout = None;
+        let mut retry = None;
+        let mut sla = None;
+        let mut throttle = None;
 
         let mut first_marker = None;
         let mut saw_opening = false;
@@ -1271,6 +1493,16 @@ impl FromYaml for EndpointPreProcessed {
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
                         body = Some(a);
                     }
+                    "checks" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        checks = Some(a);
+                    }
+                    "compress" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        compress = Some(a);
+                    }
                     "load_pattern" => {
                         let a =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
@@ -1317,6 +1549,11 @@ impl FromYaml for EndpointPreProcessed {
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
                         max_parallel_requests = Some(a);
                     }
+                    "metrics" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        metrics = Some(a);
+                    }
                     "no_auto_returns" => {
                         let a =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
@@ -1327,6 +1564,21 @@ impl FromYaml for EndpointPreProcessed {
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
                         request_timeout = Some(a);
                     }
+                    "retry" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        retry = Some(a);
+                    }
+                    "sla" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        sla = Some(a);
+                    }
+                    "throttle" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        throttle = Some(a);
+                    }
                     _ => return Err(Error::UnrecognizedKey(s, None, marker)),
                 },
             }
@@ -1334,17 +1586,21 @@ impl FromYaml for EndpointPreProcessed {
         let marker = first_marker.expect("should have a marker");
         let declare = declare.unwrap_or_default();
         let headers = headers.unwrap_or_default();
+        let checks = checks.unwrap_or_default();
         let method = method.unwrap_or_default();
         let on_demand = on_demand.unwrap_or_default();
         let tags = tags.unwrap_or_default();
         let url = url.ok_or(Error::MissingYamlField("url", marker))?;
         let provides = provides.unwrap_or_default();
         let logs = logs.unwrap_or_default();
+        let metrics = metrics.unwrap_or_default();
         let no_auto_returns = no_auto_returns.unwrap_or_default();
         let ret = Self {
             declare,
             headers,
             body,
+            checks,
+            compress,
             load_pattern,
             method,
             on_demand,
@@ -1354,8 +1610,12 @@ impl FromYaml for EndpointPreProcessed {
             provides,
             logs,
             max_parallel_requests,
+            metrics,
             no_auto_returns,
             request_timeout,
+            retry,
+            sla,
+            throttle,
             marker,
         };
         Ok((ret, marker))
@@ -1365,7 +1625,11 @@ impl FromYaml for EndpointPreProcessed {
 #[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
 enum Body {
     String(PreTemplate),
+    Bytes(WithMarker<json::Value>),
     File(PreTemplate),
+    Form(TupleVec<String, PreTemplate>),
+    Generate(GeneratePreProcessed),
+    Json(WithMarker<json::Value>),
     Multipart(TupleVec<String, BodyMultipartPiece>),
 }
 
@@ -1400,6 +1664,22 @@ impl FromYaml for Body {
                 let (file, marker) = FromYaml::parse(decoder)?;
                 (Body::File(file), marker)
             }
+            Ok(s) if s.as_str() == "bytes" => {
+                let (select, marker) = FromYaml::parse(decoder)?;
+                (Body::Bytes(select), marker)
+            }
+            Ok(s) if s.as_str() == "form" => {
+                let (form, marker) = FromYaml::parse(decoder)?;
+                (Body::Form(form), marker)
+            }
+            Ok(s) if s.as_str() == "generate" => {
+                let (generate, marker) = FromYaml::parse(decoder)?;
+                (Body::Generate(generate), marker)
+            }
+            Ok(s) if s.as_str() == "json" => {
+                let (select, marker) = FromYaml::parse(decoder)?;
+                (Body::Json(select), marker)
+            }
             Ok(s) if s.as_str() == "multipart" => {
                 let (multipart, marker) = FromYaml::parse(decoder)?;
                 (Body::Multipart(multipart), marker)
@@ -1518,37 +1798,40 @@ impl FromYaml for BodyMultipartPieceBody {
     }
 }
 
+// the compression applied to a request body before it is sent
 #[cfg_attr(debug_assertions, derive(Debug))]
 #[derive(Copy, Clone, PartialEq)]
-pub enum EndpointProvidesSendOptions {
-    Block,
-    Force,
-    IfNotFull,
-}
-
-impl EndpointProvidesSendOptions {
-    pub fn is_block(self) -> bool {
-        matches!(self, EndpointProvidesSendOptions::Block)
-    }
+pub enum BodyCompression {
+    Brotli,
+    Deflate,
+    Gzip,
+    Zstd,
 }
 
-impl Default for EndpointProvidesSendOptions {
-    fn default() -> Self {
-        EndpointProvidesSendOptions::Block
+impl BodyCompression {
+    // the value used in the "Content-Encoding" header
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BodyCompression::Brotli => "br",
+            BodyCompression::Deflate => "deflate",
+            BodyCompression::Gzip => "gzip",
+            BodyCompression::Zstd => "zstd",
+        }
     }
 }
 
-impl FromYaml for EndpointProvidesSendOptions {
+impl FromYaml for BodyCompression {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
         let (event, marker) = decoder.next()?;
         if let Ok(s) = event.into_string() {
-            let send = match s.as_ref() {
-                "block" => EndpointProvidesSendOptions::Block,
-                "force" => EndpointProvidesSendOptions::Force,
-                "if_not_full" => EndpointProvidesSendOptions::IfNotFull,
+            let compress = match s.as_ref() {
+                "br" => BodyCompression::Brotli,
+                "deflate" => BodyCompression::Deflate,
+                "gzip" => BodyCompression::Gzip,
+                "zstd" => BodyCompression::Zstd,
                 _ => return Err(Error::YamlDeserialize(None, marker)),
             };
-            Ok((send, marker))
+            Ok((compress, marker))
         } else {
             Err(Error::YamlDeserialize(None, marker))
         }
@@ -1556,19 +1839,16 @@ impl FromYaml for EndpointProvidesSendOptions {
 }
 
 #[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
-pub(crate) struct EndpointProvidesPreProcessed {
-    for_each: Vec<WithMarker<String>>,
-    select: WithMarker<json::Value>,
-    send: Option<EndpointProvidesSendOptions>,
-    where_clause: Option<WithMarker<String>>,
+struct GeneratePreProcessed {
+    pattern: BodyPattern,
+    size: PreByteSize,
 }
 
-impl FromYaml for EndpointProvidesPreProcessed {
+impl FromYaml for GeneratePreProcessed {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
-        let mut send = None;
-        let mut select = None;
-        let mut for_each = None;
-        let mut where_clause = None;
+        let mut pattern = None;
+        let mut size = None;
+
         let mut first_marker = None;
         let mut saw_opening = false;
         loop {
@@ -1594,71 +1874,111 @@ impl FromYaml for EndpointProvidesPreProcessed {
                     unreachable!("shouldn't see sequence end");
                 }
                 YamlEvent::Scalar(s, ..) => match s.as_str() {
-                    "send" => {
-                        let r =
-                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        send = Some(r);
-                    }
-                    "select" => {
-                        let r =
-                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        select = Some(r);
-                    }
-                    "for_each" => {
-                        let r =
+                    "pattern" => {
+                        let p =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        for_each = Some(r);
+                        pattern = Some(p);
                     }
-                    "where" => {
-                        let v =
+                    "size" => {
+                        let s =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        where_clause = Some(v);
+                        size = Some(s);
                     }
-                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                    _ => return Err(Error::UnrecognizedKey(s, Some("generate".into()), marker)),
                 },
             }
         }
         let marker = first_marker.expect("should have a marker");
-        let select = select.ok_or(Error::MissingYamlField("select", marker))?;
-        let for_each = for_each.unwrap_or_default();
+        let size = size.ok_or(Error::MissingYamlField("size", marker))?;
         let ret = Self {
-            for_each,
-            select,
-            send,
-            where_clause,
+            pattern: pattern.unwrap_or_default(),
+            size,
         };
         Ok((ret, marker))
     }
 }
 
-fn default_keepalive(marker: Marker) -> PreDuration {
-    PreDuration(PreTemplate::new(WithMarker::new("90s".into(), marker)))
-}
-
-fn default_request_timeout(marker: Marker) -> PreDuration {
-    PreDuration(PreTemplate::new(WithMarker::new("60s".into(), marker)))
+// the bytes which make up a generated body
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, PartialEq)]
+pub enum BodyPattern {
+    #[default]
+    Random,
+    Repeat(String),
+    Zeros,
 }
 
-fn default_bucket_size(marker: Marker) -> PreDuration {
-    PreDuration(PreTemplate::new(WithMarker::new("60s".into(), marker)))
+impl FromYaml for BodyPattern {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        match event {
+            YamlEvent::Scalar(s, ..) => match s.as_str() {
+                "random" => Ok((BodyPattern::Random, marker)),
+                "zeros" => Ok((BodyPattern::Zeros, marker)),
+                _ => Err(Error::YamlDeserialize(None, marker)),
+            },
+            YamlEvent::MappingStart => {
+                let (event, marker2) = decoder.next()?;
+                match event.into_string() {
+                    Ok(s) if s.as_str() == "repeat" => (),
+                    Ok(s) => {
+                        return Err(Error::UnrecognizedKey(s, Some("pattern".into()), marker2))
+                    }
+                    Err(_) => return Err(Error::YamlDeserialize(None, marker2)),
+                }
+                let (repeat, marker2): (String, _) =
+                    FromYaml::parse(decoder).map_err(map_yaml_deserialize_err("repeat".into()))?;
+                if repeat.is_empty() {
+                    return Err(Error::YamlDeserialize(Some("repeat".into()), marker2));
+                }
+                let (event, marker2) = decoder.next()?;
+                if !matches!(event, YamlEvent::MappingEnd) {
+                    return Err(Error::YamlDeserialize(None, marker2));
+                }
+                Ok((BodyPattern::Repeat(repeat), marker))
+            }
+            _ => Err(Error::YamlDeserialize(None, marker)),
+        }
+    }
 }
 
-pub fn default_auto_buffer_start_size() -> usize {
-    5
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct ThrottlePreProcessed {
+    download: Option<PreBandwidth>,
+    slow_read: Option<PreDuration>,
+    upload: Option<PreBandwidth>,
 }
 
-#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
-struct ClientConfigPreProcessed {
-    headers: TupleVec<String, PreTemplate>,
-    keepalive: PreDuration,
-    request_timeout: PreDuration,
+impl ThrottlePreProcessed {
+    fn evaluate(&self, static_vars: &BTreeMap<String, json::Value>) -> Result<Throttle, Error> {
+        let download = self
+            .download
+            .as_ref()
+            .map(|b| b.evaluate(static_vars))
+            .transpose()?;
+        let slow_read = self
+            .slow_read
+            .as_ref()
+            .map(|d| d.evaluate(static_vars))
+            .transpose()?;
+        let upload = self
+            .upload
+            .as_ref()
+            .map(|b| b.evaluate(static_vars))
+            .transpose()?;
+        Ok(Throttle {
+            download,
+            slow_read,
+            upload,
+        })
+    }
 }
 
-impl FromYaml for ClientConfigPreProcessed {
+impl FromYaml for ThrottlePreProcessed {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
-        let mut request_timeout = None;
-        let mut headers = None;
-        let mut keepalive = None;
+        let mut download = None;
+        let mut slow_read = None;
+        let mut upload = None;
 
         let mut first_marker = None;
         let mut saw_opening = false;
@@ -1685,65 +2005,804 @@ impl FromYaml for ClientConfigPreProcessed {
                     unreachable!("shouldn't see sequence end");
                 }
                 YamlEvent::Scalar(s, ..) => match s.as_str() {
-                    "request_timeout" => {
-                        let c =
+                    "download" => {
+                        let a =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        request_timeout = Some(c);
+                        download = Some(a);
                     }
-                    "keepalive" => {
+                    "slow_read" => {
                         let a =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        keepalive = Some(a);
+                        slow_read = Some(a);
                     }
-                    "headers" => {
-                        let b =
+                    "upload" => {
+                        let a =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        headers = Some(b);
+                        upload = Some(a);
                     }
-                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                    _ => return Err(Error::UnrecognizedKey(s, Some("throttle".into()), marker)),
                 },
             }
         }
         let marker = first_marker.expect("should have a marker");
-        let request_timeout = request_timeout.unwrap_or_else(|| default_request_timeout(marker));
-        let keepalive = keepalive.unwrap_or_else(|| default_keepalive(marker));
-        let headers = headers.unwrap_or_default();
         let ret = Self {
-            headers,
-            keepalive,
-            request_timeout,
+            download,
+            slow_read,
+            upload,
         };
         Ok((ret, marker))
     }
 }
 
-pub struct ClientConfig {
-    pub request_timeout: Duration,
-    pub keepalive: Duration,
-}
-
-impl DefaultWithMarker for ClientConfigPreProcessed {
-    fn default(marker: Marker) -> Self {
-        ClientConfigPreProcessed {
-            request_timeout: default_request_timeout(marker),
-            headers: Default::default(),
-            keepalive: default_keepalive(marker),
-        }
-    }
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct RetryPreProcessed {
+    backoff: Option<PreBackoff>,
+    max: usize,
+    on: Option<Vec<RetryOn>>,
+    per_try_timeout: Option<PreDuration>,
+}
+
+impl RetryPreProcessed {
+    fn evaluate(
+        self,
+        static_vars: &BTreeMap<String, json::Value>,
+        marker: Marker,
+    ) -> Result<Retry, Error> {
+        let backoff = self
+            .backoff
+            .map(|b| b.evaluate(static_vars))
+            .transpose()?
+            .unwrap_or(Backoff::Constant(Duration::from_secs(0)));
+        let per_try_timeout = self
+            .per_try_timeout
+            .map(|d| d.evaluate_millis(static_vars))
+            .transpose()?;
+        // without a per try timeout the first attempt uses the whole request timeout, so a timeout
+        // could never be retried
+        let on = match self.on {
+            Some(on) if per_try_timeout.is_none() && on.contains(&RetryOn::Timeout) => {
+                return Err(Error::RetryOnTimeoutWithoutPerTryTimeout(marker));
+            }
+            Some(on) => on,
+            None if per_try_timeout.is_some() => vec![RetryOn::ConnectionError, RetryOn::Timeout],
+            None => vec![RetryOn::ConnectionError],
+        };
+        Ok(Retry {
+            backoff,
+            max: self.max,
+            on,
+            per_try_timeout,
+        })
+    }
+}
+
+impl FromYaml for RetryPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut backoff = None;
+        let mut max = None;
+        let mut on = None;
+        let mut per_try_timeout = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "backoff" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        backoff = Some(a);
+                    }
+                    "max" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        max = Some(a);
+                    }
+                    "on" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        on = Some(a);
+                    }
+                    "per_try_timeout" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        per_try_timeout = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, Some("retry".into()), marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let max = max.ok_or(Error::MissingYamlField("max", marker))?;
+        let ret = Self {
+            backoff,
+            max,
+            on,
+            per_try_timeout,
+        };
+        Ok((ret, marker))
+    }
+}
+
+impl FromYaml for RetryOn {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        let on = match event.as_str().map(str::trim) {
+            Some("connection_error") => RetryOn::ConnectionError,
+            Some("timeout") => RetryOn::Timeout,
+            Some(s) => match s.parse() {
+                Ok(status) if (100..600).contains(&status) => RetryOn::Status(status),
+                _ => return Err(Error::YamlDeserialize(None, marker)),
+            },
+            None => return Err(Error::YamlDeserialize(None, marker)),
+        };
+        Ok((on, marker))
+    }
+}
+
+// a backoff, such as `exponential(100ms, 2s)` or `constant(500ms)`
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct PreBackoff(PreTemplate);
+
+impl PreBackoff {
+    fn evaluate(&self, static_vars: &BTreeMap<String, json::Value>) -> Result<Backoff, Error> {
+        let string = self
+            .0
+            .evaluate(static_vars, &mut RequiredProviders::new())?;
+        let marker = (self.0).0.marker;
+        let re = Regex::new(r"^\s*(constant|exponential)\s*\(([^,)]+)(?:,([^,)]+))?\)\s*$")
+            .expect("should be a valid regex");
+        let invalid = || Error::InvalidBackoff(string.clone(), marker);
+        let captures = re.captures(&string).ok_or_else(invalid)?;
+        let duration = |i| {
+            captures
+                .get(i)
+                .map(|m| millis_duration_from_string2(m.as_str().trim().into(), marker))
+                .transpose()
+        };
+        let first = duration(2)?.expect("should have capture group");
+        let second = duration(3)?;
+        match (&captures[1], second) {
+            ("constant", None) => Ok(Backoff::Constant(first)),
+            ("exponential", Some(max)) if first <= max => Ok(Backoff::Exponential(first, max)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl FromYaml for PreBackoff {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (p, marker) = FromYaml::parse(decoder)?;
+        Ok((Self(p), marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct ThresholdPreProcessed {
+    abort_on_fail: bool,
+    condition: PreThreshold,
+    tags: BTreeMap<String, String>,
+}
+
+impl ThresholdPreProcessed {
+    fn evaluate(self, static_vars: &BTreeMap<String, json::Value>) -> Result<Threshold, Error> {
+        let (condition, metric, op, value) = self.condition.evaluate(static_vars)?;
+        // tag values can use `*` as a wildcard
+        let tag_patterns = self
+            .tags
+            .iter()
+            .map(|(key, value)| {
+                let pattern = value
+                    .split('*')
+                    .map(regex::escape)
+                    .collect::<Vec<_>>()
+                    .join(".*?");
+                let re = Regex::new(&format!("^{}$", pattern)).expect("should be a valid regex");
+                (key.clone(), re)
+            })
+            .collect();
+        Ok(Threshold {
+            abort_on_fail: self.abort_on_fail,
+            condition,
+            metric,
+            op,
+            tag_patterns,
+            tags: self.tags,
+            value,
+        })
+    }
+}
+
+impl FromYaml for ThresholdPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        // a threshold can be just the condition
+        if let (YamlEvent::Scalar(..), _) = decoder.peek()? {
+            let (condition, marker) = FromYaml::parse(decoder)?;
+            let threshold = ThresholdPreProcessed {
+                abort_on_fail: false,
+                condition,
+                tags: Default::default(),
+            };
+            return Ok((threshold, marker));
+        }
+        let mut abort_on_fail = None;
+        let mut condition = None;
+        let mut tags = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "abort_on_fail" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        abort_on_fail = Some(a);
+                    }
+                    "condition" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        condition = Some(a);
+                    }
+                    "tags" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        tags = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, Some("thresholds".into()), marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let condition = condition.ok_or(Error::MissingYamlField("condition", marker))?;
+        let threshold = ThresholdPreProcessed {
+            abort_on_fail: abort_on_fail.unwrap_or_default(),
+            condition,
+            tags: tags.unwrap_or_default(),
+        };
+        Ok((threshold, marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct PreThreshold(PreTemplate);
+
+impl PreThreshold {
+    fn evaluate(
+        &self,
+        static_vars: &BTreeMap<String, json::Value>,
+    ) -> Result<(String, ThresholdMetric, ThresholdOp, f64), Error> {
+        let string = self
+            .0
+            .evaluate(static_vars, &mut RequiredProviders::new())?;
+        let marker = (self.0).0.marker;
+        let re = Regex::new(r"^\s*(\S+?)\s*(<=|>=|==|!=|<|>)\s*(\d+(?:\.\d+)?)\s*(ms|s|%)?\s*$")
+            .expect("should be a valid regex");
+        let invalid = || Error::InvalidThreshold(string.clone(), marker);
+        let captures = re.captures(&string).ok_or_else(invalid)?;
+        let metric = match &captures[1] {
+            "min" => ThresholdMetric::Min,
+            "max" => ThresholdMetric::Max,
+            "mean" | "avg" => ThresholdMetric::Mean,
+            "stddev" => ThresholdMetric::StdDev,
+            "calls" => ThresholdMetric::Calls,
+            "errors" => ThresholdMetric::Errors,
+            "error_rate" => ThresholdMetric::ErrorRate,
+            "retries" => ThresholdMetric::Retries,
+            "timeouts" => ThresholdMetric::Timeouts,
+            m if m.starts_with("checks.") && m.len() > 7 => {
+                ThresholdMetric::Check(m[7..].to_string())
+            }
+            m if m.starts_with('p') => match m[1..].parse::<f64>() {
+                Ok(p) if p > 0.0 && p <= 100.0 => ThresholdMetric::Percentile(p),
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        };
+        let op = match &captures[2] {
+            "<" => ThresholdOp::Lt,
+            "<=" => ThresholdOp::Le,
+            ">" => ThresholdOp::Gt,
+            ">=" => ThresholdOp::Ge,
+            "==" => ThresholdOp::Eq,
+            _ => ThresholdOp::Ne,
+        };
+        let value: f64 = captures[3].parse().map_err(|_| invalid())?;
+        // the value is stored in the metric's unit, so a unit must match the type of metric
+        let value = match (metric.unit(), captures.get(4).map(|m| m.as_str())) {
+            ("ms", Some("s")) => value * 1000.0,
+            ("ms", Some("ms")) | ("ms", None) => value,
+            ("%", Some("%")) | ("%", None) => value,
+            ("", None) => value,
+            _ => return Err(invalid()),
+        };
+        Ok((string.trim().to_string(), metric, op, value))
+    }
+}
+
+impl FromYaml for PreThreshold {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (p, marker) = FromYaml::parse(decoder)?;
+        Ok((Self(p), marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Copy, Clone, PartialEq)]
+pub enum EndpointProvidesSendOptions {
+    Block,
+    Force,
+    IfNotFull,
+}
+
+impl EndpointProvidesSendOptions {
+    pub fn is_block(self) -> bool {
+        matches!(self, EndpointProvidesSendOptions::Block)
+    }
+}
+
+impl Default for EndpointProvidesSendOptions {
+    fn default() -> Self {
+        EndpointProvidesSendOptions::Block
+    }
+}
+
+impl FromYaml for EndpointProvidesSendOptions {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        if let Ok(s) = event.into_string() {
+            let send = match s.as_ref() {
+                "block" => EndpointProvidesSendOptions::Block,
+                "force" => EndpointProvidesSendOptions::Force,
+                "if_not_full" => EndpointProvidesSendOptions::IfNotFull,
+                _ => return Err(Error::YamlDeserialize(None, marker)),
+            };
+            Ok((send, marker))
+        } else {
+            Err(Error::YamlDeserialize(None, marker))
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+pub(crate) struct EndpointProvidesPreProcessed {
+    for_each: Vec<WithMarker<String>>,
+    select: WithMarker<json::Value>,
+    send: Option<EndpointProvidesSendOptions>,
+    where_clause: Option<WithMarker<String>>,
+}
+
+impl FromYaml for EndpointProvidesPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut send = None;
+        let mut select = None;
+        let mut for_each = None;
+        let mut where_clause = None;
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "send" => {
+                        let r =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        send = Some(r);
+                    }
+                    "select" => {
+                        let r =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        select = Some(r);
+                    }
+                    "for_each" => {
+                        let r =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        for_each = Some(r);
+                    }
+                    "where" => {
+                        let v =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        where_clause = Some(v);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let select = select.ok_or(Error::MissingYamlField("select", marker))?;
+        let for_each = for_each.unwrap_or_default();
+        let ret = Self {
+            for_each,
+            select,
+            send,
+            where_clause,
+        };
+        Ok((ret, marker))
+    }
+}
+
+impl EndpointProvidesPreProcessed {
+    // a lone `select`, as used by the `json` and `bytes` bodies
+    fn from_select(select: WithMarker<json::Value>) -> Self {
+        EndpointProvidesPreProcessed {
+            for_each: Default::default(),
+            select,
+            send: None,
+            where_clause: None,
+        }
+    }
+
+    // a check is an expression which gets selected and then evaluated for truthiness
+    fn from_check(check: WithMarker<String>) -> Self {
+        let (check, marker) = check.destruct();
+        EndpointProvidesPreProcessed::from_select(WithMarker::new(check.into(), marker))
+    }
+}
+
+fn default_keepalive(marker: Marker) -> PreDuration {
+    PreDuration(PreTemplate::new(WithMarker::new("90s".into(), marker)))
+}
+
+fn default_request_timeout(marker: Marker) -> PreDuration {
+    PreDuration(PreTemplate::new(WithMarker::new("60s".into(), marker)))
+}
+
+fn default_bucket_size(marker: Marker) -> PreDuration {
+    PreDuration(PreTemplate::new(WithMarker::new("60s".into(), marker)))
+}
+
+pub fn default_auto_buffer_start_size() -> usize {
+    5
+}
+
+pub fn default_percentiles() -> Vec<f64> {
+    vec![50.0, 90.0, 95.0, 99.0, 99.9]
+}
+
+pub fn default_histogram_significant_digits() -> u8 {
+    3
+}
+
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct ClientConfigPreProcessed {
+    headers: TupleVec<String, PreTemplate>,
+    keepalive: PreDuration,
+    request_timeout: PreDuration,
+}
+
+impl FromYaml for ClientConfigPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut request_timeout = None;
+        let mut headers = None;
+        let mut keepalive = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "request_timeout" => {
+                        let c =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        request_timeout = Some(c);
+                    }
+                    "keepalive" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        keepalive = Some(a);
+                    }
+                    "headers" => {
+                        let b =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        headers = Some(b);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let request_timeout = request_timeout.unwrap_or_else(|| default_request_timeout(marker));
+        let keepalive = keepalive.unwrap_or_else(|| default_keepalive(marker));
+        let headers = headers.unwrap_or_default();
+        let ret = Self {
+            headers,
+            keepalive,
+            request_timeout,
+        };
+        Ok((ret, marker))
+    }
+}
+
+pub struct ClientConfig {
+    pub request_timeout: Duration,
+    pub keepalive: Duration,
+}
+
+impl DefaultWithMarker for ClientConfigPreProcessed {
+    fn default(marker: Marker) -> Self {
+        ClientConfigPreProcessed {
+            request_timeout: default_request_timeout(marker),
+            headers: Default::default(),
+            keepalive: default_keepalive(marker),
+        }
+    }
 }
 
 pub struct GeneralConfig {
     pub auto_buffer_start_size: usize,
     pub bucket_size: Duration,
+    // the precision of the response time histograms
+    pub histogram_significant_digits: u8,
     pub log_provider_stats: Option<Duration>,
+    // the percentiles of the response times shown in the summaries
+    pub percentiles: Vec<f64>,
+    pub stats_sinks: Vec<StatsSink>,
     pub watch_transition_time: Option<Duration>,
 }
 
+// where, and in which format, stats are pushed as a test runs
+#[derive(Clone)]
+pub struct StatsSink {
+    pub format: StatsSinkFormat,
+    pub prefix: String,
+    pub protocol: StatsSinkProtocol,
+    // when set, each response is sent, sampled at this rate, instead of each bucket
+    pub sample_rate: Option<f64>,
+    pub to: String,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum StatsSinkFormat {
+    Influx,
+    Otlp,
+    StatsD,
+}
+
+impl FromYaml for StatsSinkFormat {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        let format = match event.as_str() {
+            Some("influx") => StatsSinkFormat::Influx,
+            Some("otlp") => StatsSinkFormat::Otlp,
+            Some("statsd") => StatsSinkFormat::StatsD,
+            _ => return Err(Error::YamlDeserialize(None, marker)),
+        };
+        Ok((format, marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum StatsSinkProtocol {
+    Tcp,
+    Udp,
+}
+
+impl FromYaml for StatsSinkProtocol {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        let protocol = match event.as_str() {
+            Some("tcp") => StatsSinkProtocol::Tcp,
+            Some("udp") => StatsSinkProtocol::Udp,
+            _ => return Err(Error::YamlDeserialize(None, marker)),
+        };
+        Ok((protocol, marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct StatsSinkPreProcessed {
+    format: StatsSinkFormat,
+    prefix: Option<String>,
+    protocol: Option<StatsSinkProtocol>,
+    sample_rate: Option<f64>,
+    to: PreTemplate,
+}
+
+impl StatsSinkPreProcessed {
+    fn evaluate(self, static_vars: &BTreeMap<String, json::Value>) -> Result<StatsSink, Error> {
+        let to = self
+            .to
+            .evaluate(static_vars, &mut RequiredProviders::new())?;
+        Ok(StatsSink {
+            format: self.format,
+            prefix: self.prefix.unwrap_or_else(|| "pewpew".into()),
+            protocol: self.protocol.unwrap_or(StatsSinkProtocol::Udp),
+            sample_rate: self.sample_rate,
+            to,
+        })
+    }
+}
+
+impl FromYaml for StatsSinkPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut format = None;
+        let mut prefix = None;
+        let mut protocol = None;
+        let mut sample_rate = None;
+        let mut to = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "format" => {
+                        let f =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        format = Some(f);
+                    }
+                    "prefix" => {
+                        let p =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        prefix = Some(p);
+                    }
+                    "protocol" => {
+                        let p =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        protocol = Some(p);
+                    }
+                    "sample_rate" => {
+                        let r =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        sample_rate = Some(r);
+                    }
+                    "to" => {
+                        let t =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        to = Some(t);
+                    }
+                    _ => {
+                        return Err(Error::UnrecognizedKey(
+                            s,
+                            Some("stats_sinks".into()),
+                            marker,
+                        ))
+                    }
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let format = format.ok_or(Error::MissingYamlField("format", marker))?;
+        let to = to.ok_or(Error::MissingYamlField("to", marker))?;
+        if let Some(rate) = sample_rate {
+            if !(rate > 0.0 && rate <= 1.0) {
+                let e = format!(
+                    "`sample_rate` must be greater than 0 and at most 1, got {}",
+                    rate
+                );
+                return Err(Error::InvalidStatsSink(e, marker));
+            }
+        }
+        if format == StatsSinkFormat::Otlp {
+            if protocol.is_some() {
+                let e = "`protocol` can't be used with the `otlp` format".to_string();
+                return Err(Error::InvalidStatsSink(e, marker));
+            }
+            if sample_rate.is_some() {
+                let e = "`sample_rate` can't be used with the `otlp` format".to_string();
+                return Err(Error::InvalidStatsSink(e, marker));
+            }
+        }
+        let ret = Self {
+            format,
+            prefix,
+            protocol,
+            sample_rate,
+            to,
+        };
+        Ok((ret, marker))
+    }
+}
+
 #[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
 struct GeneralConfigPreProcessed {
     auto_buffer_start_size: usize,
     bucket_size: PreDuration,
+    histogram_significant_digits: u8,
     log_provider_stats: Option<PreDuration>,
+    percentiles: Vec<f64>,
+    stats_sinks: Vec<StatsSinkPreProcessed>,
     watch_transition_time: Option<PreDuration>,
 }
 
@@ -1752,7 +2811,10 @@ impl DefaultWithMarker for GeneralConfigPreProcessed {
         GeneralConfigPreProcessed {
             auto_buffer_start_size: default_auto_buffer_start_size(),
             bucket_size: default_bucket_size(marker),
+            histogram_significant_digits: default_histogram_significant_digits(),
             log_provider_stats: None,
+            percentiles: default_percentiles(),
+            stats_sinks: Vec::new(),
             watch_transition_time: None,
         }
     }
@@ -1762,7 +2824,10 @@ impl FromYaml for GeneralConfigPreProcessed {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
         let mut auto_buffer_start_size = default_auto_buffer_start_size();
         let mut bucket_size = None;
+        let mut histogram_significant_digits = default_histogram_significant_digits();
         let mut log_provider_stats = None;
+        let mut percentiles = default_percentiles();
+        let mut stats_sinks = Vec::new();
         let mut watch_transition_time = None;
 
         let mut first_marker = None;
@@ -1800,11 +2865,33 @@ impl FromYaml for GeneralConfigPreProcessed {
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
                         bucket_size = Some(a);
                     }
+                    "histogram_significant_digits" => {
+                        let (d, marker): (usize, _) =
+                            FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        // the most precision the histograms support
+                        if d == 0 || d > 5 {
+                            return Err(Error::InvalidHistogramSignificantDigits(d, marker));
+                        }
+                        histogram_significant_digits = d as u8;
+                    }
                     "log_provider_stats" => {
                         let b =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
                         log_provider_stats = Some(b);
                     }
+                    "percentiles" => {
+                        let (p, marker): (Vec<f64>, _) =
+                            FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        if let Some(p) = p.iter().find(|p| !(**p > 0.0 && **p <= 100.0)) {
+                            return Err(Error::InvalidPercent(p.to_string(), marker));
+                        }
+                        percentiles = p;
+                    }
+                    "stats_sinks" => {
+                        let s2 =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        stats_sinks = s2;
+                    }
                     "watch_transition_time" => {
                         let b =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
@@ -1819,7 +2906,10 @@ impl FromYaml for GeneralConfigPreProcessed {
         let ret = Self {
             auto_buffer_start_size,
             bucket_size,
+            histogram_significant_digits,
             log_provider_stats,
+            percentiles,
+            stats_sinks,
             watch_transition_time,
         };
         Ok((ret, marker))
@@ -1900,6 +2990,7 @@ struct LoadTestPreProcessed {
     load_pattern: Option<PreLoadPattern>,
     providers: BTreeMap<String, ProviderPreProcessed>,
     loggers: BTreeMap<String, LoggerPreProcessed>,
+    thresholds: Vec<ThresholdPreProcessed>,
     vars: BTreeMap<String, PreVar>,
 }
 
@@ -1910,6 +3001,7 @@ impl FromYaml for LoadTestPreProcessed {
         let mut load_pattern = None;
         let mut providers = None;
         let mut loggers = None;
+        let mut thresholds = None;
         let mut vars = None;
         let mut first_marker = None;
         let mut saw_opening = false;
@@ -1961,6 +3053,11 @@ impl FromYaml for LoadTestPreProcessed {
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
                         loggers = Some(v);
                     }
+                    "thresholds" => {
+                        let v =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        thresholds = Some(v);
+                    }
                     "vars" => {
                         let v =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
@@ -1975,6 +3072,7 @@ impl FromYaml for LoadTestPreProcessed {
         let endpoints = endpoints.ok_or(Error::MissingYamlField("endpoints", marker))?;
         let providers = providers.unwrap_or_default();
         let loggers = loggers.unwrap_or_default();
+        let thresholds = thresholds.unwrap_or_default();
         let vars = vars.unwrap_or_default();
         let ret = Self {
             config,
@@ -1982,6 +3080,7 @@ impl FromYaml for LoadTestPreProcessed {
             load_pattern,
             providers,
             loggers,
+            thresholds,
             vars,
         };
         Ok((ret, marker))
@@ -2160,14 +3259,33 @@ pub fn duration_from_string(dur: String) -> Result<Duration, Error> {
 }
 
 fn duration_from_string2(dur: String, marker: Marker) -> Result<Duration, Error> {
-    let base_re = r"(?i)(\d+)\s*(d|h|m|s|days?|hrs?|mins?|secs?|hours?|minutes?|seconds?)";
+    parse_duration(dur, marker, false)
+}
+
+// durations which need to be finer than a second, such as backoffs and SLA limits, can also use
+// milliseconds
+fn millis_duration_from_string2(dur: String, marker: Marker) -> Result<Duration, Error> {
+    parse_duration(dur, marker, true)
+}
+
+fn parse_duration(dur: String, marker: Marker, allow_millis: bool) -> Result<Duration, Error> {
+    // the millisecond units come first so "ms" is not matched as minutes
+    let millis_units = if allow_millis {
+        "ms|millis?|milliseconds?|"
+    } else {
+        ""
+    };
+    let base_re = format!(
+        r"(?i)(\d+)\s*({}d|h|m|s|days?|hrs?|mins?|secs?|hours?|minutes?|seconds?)",
+        millis_units
+    );
     let sanity_re =
         Regex::new(&format!(r"^(?:{}\s*)+$", base_re)).expect("should be a valid regex");
     if !sanity_re.is_match(&dur) {
         return Err(Error::InvalidDuration(dur, marker));
     }
-    let mut total_secs = 0;
-    let re = Regex::new(base_re).expect("should be a valid regex");
+    let mut total_millis = 0;
+    let re = Regex::new(&base_re).expect("should be a valid regex");
     for captures in re.captures_iter(&dur) {
         let n: u64 = captures
             .get(1)
@@ -2175,19 +3293,23 @@ fn duration_from_string2(dur: String, marker: Marker) -> Result<Duration, Error>
             .as_str()
             .parse()
             .expect("should parse into u64 for duration");
-        let unit = &captures.get(2).expect("should have capture group").as_str()[0..1];
-        let secs = if unit.eq_ignore_ascii_case("d") {
-            n * 60 * 60 * 24 // days
-        } else if unit.eq_ignore_ascii_case("h") {
-            n * 60 * 60 // hours
-        } else if unit.eq_ignore_ascii_case("m") {
-            n * 60 // minutes
+        let unit = captures.get(2).expect("should have capture group").as_str();
+        let millis = if unit.len() > 1 && unit[0..2].eq_ignore_ascii_case("ms")
+            || unit.len() > 2 && unit[0..3].eq_ignore_ascii_case("mil")
+        {
+            n // milliseconds
+        } else if unit[0..1].eq_ignore_ascii_case("d") {
+            n * 60 * 60 * 24 * 1000 // days
+        } else if unit[0..1].eq_ignore_ascii_case("h") {
+            n * 60 * 60 * 1000 // hours
+        } else if unit[0..1].eq_ignore_ascii_case("m") {
+            n * 60 * 1000 // minutes
         } else {
-            n // seconds
+            n * 1000 // seconds
         };
-        total_secs += secs;
+        total_millis += millis;
     }
-    Ok(Duration::from_secs(total_secs))
+    Ok(Duration::from_millis(total_millis))
 }
 
 #[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
@@ -2200,6 +3322,17 @@ impl PreDuration {
             .evaluate(static_vars, &mut RequiredProviders::new())?;
         duration_from_string2(dur, (self.0).0.marker)
     }
+
+    // like `evaluate`, but milliseconds can also be used
+    fn evaluate_millis(
+        &self,
+        static_vars: &BTreeMap<String, json::Value>,
+    ) -> Result<Duration, Error> {
+        let dur = self
+            .0
+            .evaluate(static_vars, &mut RequiredProviders::new())?;
+        millis_duration_from_string2(dur, (self.0).0.marker)
+    }
 }
 
 impl FromYaml for PreDuration {
@@ -2314,6 +3447,99 @@ impl PreHitsPer {
     }
 }
 
+// a bandwidth, such as `64kbps`, which evaluates to a number of bytes per second
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct PreBandwidth(PreTemplate);
+
+impl PreBandwidth {
+    fn evaluate(&self, static_vars: &BTreeMap<String, json::Value>) -> Result<u64, Error> {
+        let string = self
+            .0
+            .evaluate(static_vars, &mut RequiredProviders::new())?;
+        let re =
+            Regex::new(r"^(?i)(\d+(?:\.\d+)?)\s*([kmg]?)bps$").expect("should be a valid regex");
+        let captures = re
+            .captures(&string)
+            .ok_or_else(|| Error::InvalidBandwidth(string.clone(), (self.0).0.marker))?;
+        let n: f64 = captures
+            .get(1)
+            .expect("should have capture group")
+            .as_str()
+            .parse()
+            .expect("should be valid digits for bandwidth");
+        let multiplier = match captures.get(2).map(|m| m.as_str().to_ascii_lowercase()) {
+            Some(ref s) if s == "k" => 1_000f64,
+            Some(ref s) if s == "m" => 1_000_000f64,
+            Some(ref s) if s == "g" => 1_000_000_000f64,
+            _ => 1f64,
+        };
+        let bytes_per_second = (n * multiplier / 8f64).round() as u64;
+        if bytes_per_second == 0 {
+            return Err(Error::InvalidBandwidth(string, (self.0).0.marker));
+        }
+        Ok(bytes_per_second)
+    }
+}
+
+impl FromYaml for PreBandwidth {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (p, marker) = FromYaml::parse(decoder)?;
+        Ok((Self(p), marker))
+    }
+}
+
+// a size, such as `50MB`, which evaluates to a number of bytes
+#[cfg_attr(debug_assertions, derive(Debug, PartialEq))]
+struct PreByteSize(PreTemplate);
+
+impl PreByteSize {
+    fn evaluate(&self, static_vars: &BTreeMap<String, json::Value>) -> Result<u64, Error> {
+        let string = self
+            .0
+            .evaluate(static_vars, &mut RequiredProviders::new())?;
+        byte_size_from_string2(string, (self.0).0.marker)
+    }
+}
+
+pub fn byte_size_from_string(size: String) -> Result<u64, Error> {
+    let marker = create_marker();
+    byte_size_from_string2(size, marker)
+}
+
+fn byte_size_from_string2(string: String, marker: Marker) -> Result<u64, Error> {
+    let re =
+        Regex::new(r"^(?i)(\d+(?:\.\d+)?)\s*(?:([kmgt])(i)?)?b$").expect("should be a valid regex");
+    let captures = re
+        .captures(&string)
+        .ok_or_else(|| Error::InvalidByteSize(string.clone(), marker))?;
+    let n: f64 = captures
+        .get(1)
+        .expect("should have capture group")
+        .as_str()
+        .parse()
+        .expect("should be valid digits for size");
+    let base = if captures.get(3).is_some() {
+        1_024f64
+    } else {
+        1_000f64
+    };
+    let exponent = match captures.get(2).map(|m| m.as_str().to_ascii_lowercase()) {
+        Some(ref s) if s == "k" => 1,
+        Some(ref s) if s == "m" => 2,
+        Some(ref s) if s == "g" => 3,
+        Some(ref s) if s == "t" => 4,
+        _ => 0,
+    };
+    Ok((n * base.powi(exponent)).round() as u64)
+}
+
+impl FromYaml for PreByteSize {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (p, marker) = FromYaml::parse(decoder)?;
+        Ok((Self(p), marker))
+    }
+}
+
 pub struct Config {
     pub client: ClientConfig,
     pub general: GeneralConfig,
@@ -2324,8 +3550,10 @@ pub struct LoadTest {
     pub endpoints: Vec<Endpoint>,
     pub providers: BTreeMap<String, Provider>,
     pub loggers: BTreeMap<String, Logger>,
+    pub thresholds: Vec<Threshold>,
     vars: BTreeMap<String, json::Value>,
     load_test_errors: Vec<Error>,
+    warnings: Vec<String>,
 }
 
 #[derive(Clone, Default, PartialEq)]
@@ -2385,12 +3613,16 @@ impl Logger {
 
 pub struct Endpoint {
     pub body: BodyTemplate,
+    pub checks: Vec<(String, Select)>,
+    pub compress: Option<BodyCompression>,
     pub declare: Vec<(String, ValueOrExpression)>,
     pub headers: Vec<(String, Template)>,
     pub load_pattern: Option<LoadPattern>,
     pub logs: Vec<(String, Select)>,
     pub max_parallel_requests: Option<NonZeroUsize>,
-    pub method: Method,
+    pub method: MethodTemplate,
+    // the kind and expression of each custom metric by its name
+    pub metrics: Vec<(String, MetricKind, Select)>,
     pub no_auto_returns: bool,
     pub on_demand: bool,
     pub peak_load: Option<HitsPer>,
@@ -2399,6 +3631,9 @@ pub struct Endpoint {
     pub required_providers: RequiredProviders,
     pub request_timeout: Option<Duration>,
     pub tags: BTreeMap<String, Template>,
+    pub retry: Option<Retry>,
+    pub sla: Option<Sla>,
+    pub throttle: Option<Throttle>,
     pub url: Template,
 }
 
@@ -2416,14 +3651,181 @@ pub struct MultipartBody {
     pub pieces: Vec<MultipartPiece>,
 }
 
+#[derive(Clone)]
+pub enum MethodTemplate {
+    Static(Method),
+    Template(Template),
+}
+
 #[derive(Clone)]
 pub enum BodyTemplate {
+    Bytes(Select),
     File(PathBuf, Template),
+    Form(Vec<(String, Template)>),
+    Generate(u64, BodyPattern),
+    Json(Select),
     Multipart(MultipartBody),
     None,
     String(Template),
 }
 
+// limits on how quickly an endpoint sends and receives bodies. Bandwidths are in bytes per second
+#[derive(Clone, Copy)]
+pub struct Throttle {
+    pub download: Option<u64>,
+    pub slow_read: Option<Duration>,
+    pub upload: Option<u64>,
+}
+
+// the response times an endpoint is expected to stay within
+#[derive(Clone)]
+pub struct Sla {
+    // the name of each limit (such as `p95`), its percentile and the highest allowed response time
+    pub limits: Vec<(String, f64, Duration)>,
+}
+
+impl Sla {
+    // whether a single response time is over any of the limits, which is `stats.sla_breached` for
+    // that response. The SLA breaches of a bucket depend on the percentiles of all its responses
+    pub fn is_exceeded_by(&self, rtt: Duration) -> bool {
+        self.limits.iter().any(|(_, _, limit)| rtt > *limit)
+    }
+}
+
+// how a request is re-sent when it fails
+#[derive(Clone)]
+pub struct Retry {
+    pub backoff: Backoff,
+    pub max: usize,
+    pub on: Vec<RetryOn>,
+    // how long each attempt can take. The endpoint's request timeout covers all of the attempts
+    pub per_try_timeout: Option<Duration>,
+}
+
+// the failures which cause a request to be retried
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum RetryOn {
+    ConnectionError,
+    Status(u16),
+    Timeout,
+}
+
+// how long to wait before each retry
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backoff {
+    Constant(Duration),
+    // the delay doubles with each retry, starting at the first value and capped at the second
+    Exponential(Duration, Duration),
+}
+
+impl Backoff {
+    // the delay before the nth retry (starting at 1)
+    pub fn delay(&self, retry: usize) -> Duration {
+        match *self {
+            Backoff::Constant(d) => d,
+            Backoff::Exponential(start, max) => {
+                let factor = 2u32.saturating_pow(retry.saturating_sub(1) as u32);
+                start.checked_mul(factor).unwrap_or(max).min(max)
+            }
+        }
+    }
+}
+
+// a pass/fail condition checked against the results of a test
+#[derive(Clone)]
+pub struct Threshold {
+    // also check the condition as each bucket closes, ending the test when it fails
+    pub abort_on_fail: bool,
+    pub condition: String,
+    pub metric: ThresholdMetric,
+    pub op: ThresholdOp,
+    tag_patterns: Vec<(String, Regex)>,
+    pub tags: BTreeMap<String, String>,
+    // in the unit of the metric
+    pub value: f64,
+}
+
+impl Threshold {
+    // whether the results with the given tags are included in this threshold
+    pub fn matches_tags(&self, tags: &BTreeMap<String, String>) -> bool {
+        self.tag_patterns
+            .iter()
+            .all(|(key, re)| tags.get(key).map(|v| re.is_match(v)).unwrap_or_default())
+    }
+
+    pub fn passes(&self, measured: f64) -> bool {
+        match self.op {
+            ThresholdOp::Lt => measured < self.value,
+            ThresholdOp::Le => measured <= self.value,
+            ThresholdOp::Gt => measured > self.value,
+            ThresholdOp::Ge => measured >= self.value,
+            ThresholdOp::Eq => (measured - self.value).abs() < f64::EPSILON,
+            ThresholdOp::Ne => (measured - self.value).abs() >= f64::EPSILON,
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub enum ThresholdMetric {
+    Calls,
+    // the percent of evaluations of the named check which passed
+    Check(String),
+    // the percent of requests which ended in an error or a timeout
+    ErrorRate,
+    Errors,
+    Max,
+    Mean,
+    Min,
+    Percentile(f64),
+    Retries,
+    StdDev,
+    Timeouts,
+}
+
+impl ThresholdMetric {
+    // response times are in milliseconds and rates are percents
+    pub fn unit(&self) -> &'static str {
+        match self {
+            ThresholdMetric::Max
+            | ThresholdMetric::Mean
+            | ThresholdMetric::Min
+            | ThresholdMetric::Percentile(_)
+            | ThresholdMetric::StdDev => "ms",
+            ThresholdMetric::Check(_) | ThresholdMetric::ErrorRate => "%",
+            ThresholdMetric::Calls
+            | ThresholdMetric::Errors
+            | ThresholdMetric::Retries
+            | ThresholdMetric::Timeouts => "",
+        }
+    }
+}
+
+// how the values of a custom metric are aggregated
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum MetricKind {
+    // the values are summed, with a boolean counting as 1 or 0
+    Counter,
+    // the last value is kept, along with the smallest and largest
+    Gauge,
+    // the distribution of the values
+    Histogram,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum ThresholdOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
 impl Endpoint {
     fn from_preprocessed(
         endpoint: EndpointPreProcessed,
@@ -2437,17 +3839,24 @@ impl Endpoint {
             declare,
             headers,
             body,
+            checks,
+            compress,
             load_pattern,
             logs,
             max_parallel_requests,
             method,
+            metrics,
             no_auto_returns,
             on_demand,
             peak_load,
             provides,
             url,
             request_timeout,
+            retry,
+            sla,
             mut tags,
+            throttle,
+            marker,
             ..
         } = endpoint;
         let mut required_providers = RequiredProviders::new();
@@ -2491,6 +3900,25 @@ impl Endpoint {
             })
             .collect::<Result<_, Error>>()?;
 
+        let checks = checks
+            .0
+            .into_iter()
+            .map(|(key, value)| {
+                let value = EndpointProvidesPreProcessed::from_check(value);
+                let value = Select::new(value, static_vars, &mut required_providers, false)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let metrics = metrics
+            .0
+            .into_iter()
+            .map(|(key, value)| {
+                let (kind, select) = value.evaluate(static_vars, &mut required_providers)?;
+                Ok((key, kind, select))
+            })
+            .collect::<Result<_, Error>>()?;
+
         let load_pattern = load_pattern
             .map(|l| l.evaluate(static_vars))
             .transpose()?
@@ -2507,9 +3935,31 @@ impl Endpoint {
             "_id".into(),
             PreTemplate::new(WithMarker::new(endpoint_id.to_string(), url_marker)),
         );
+        // a templated method is tagged with the method used for each request
+        let (method, method_tag) = match method {
+            PreMethod::Static(m) => {
+                let tag = m.to_string();
+                (MethodTemplate::Static(m), tag)
+            }
+            PreMethod::Template(t) => {
+                let marker = t.0.marker;
+                let tag = t.0.inner.clone();
+                match t
+                    .as_template(static_vars, &mut required_providers)?
+                    .simplify_to_string()
+                {
+                    Either::A(s) => {
+                        let m = method_from_str(&s).ok_or(Error::InvalidMethod(s, marker))?;
+                        let tag = m.to_string();
+                        (MethodTemplate::Static(m), tag)
+                    }
+                    Either::B(t) => (MethodTemplate::Template(t), tag),
+                }
+            }
+        };
         tags.insert(
             "method".into(),
-            PreTemplate::new(WithMarker::new(method.to_string(), url_marker)),
+            PreTemplate::new(WithMarker::new(method_tag, url_marker)),
         );
         let tags: BTreeMap<_, _> = tags
             .into_iter()
@@ -2531,6 +3981,34 @@ impl Endpoint {
                         let template = body.as_template(static_vars, &mut required_providers)?;
                         BodyTemplate::String(template)
                     }
+                    Body::Form(form) => {
+                        let pieces = form
+                            .0
+                            .into_iter()
+                            .map(|(k, v)| {
+                                let template =
+                                    v.as_template(static_vars, &mut required_providers)?;
+                                Ok::<_, Error>((k, template))
+                            })
+                            .collect::<Result<_, _>>()?;
+                        BodyTemplate::Form(pieces)
+                    }
+                    Body::Bytes(select) => {
+                        let value = EndpointProvidesPreProcessed::from_select(select);
+                        let select =
+                            Select::new(value, static_vars, &mut required_providers, false)?;
+                        BodyTemplate::Bytes(select)
+                    }
+                    Body::Generate(generate) => {
+                        let size = generate.size.evaluate(static_vars)?;
+                        BodyTemplate::Generate(size, generate.pattern)
+                    }
+                    Body::Json(select) => {
+                        let value = EndpointProvidesPreProcessed::from_select(select);
+                        let select =
+                            Select::new(value, static_vars, &mut required_providers, false)?;
+                        BodyTemplate::Json(select)
+                    }
                     Body::Multipart(multipart) => {
                         let pieces = multipart
                             .0
@@ -2579,6 +4057,15 @@ impl Endpoint {
             })
             .transpose()?
             .unwrap_or(BodyTemplate::None);
+        // a retried request is sent again with the same body, so the body has to be held in memory
+        let is_streamed = match &body {
+            BodyTemplate::File(..) | BodyTemplate::Generate(..) => true,
+            BodyTemplate::Multipart(m) => m.pieces.iter().any(|p| p.is_file),
+            _ => false,
+        };
+        if retry.is_some() && is_streamed {
+            return Err(Error::RetryWithStreamedBody(marker));
+        }
 
         let mut providers_to_stream = required_providers;
         let mut required_providers2 = RequiredProviders::new();
@@ -2595,15 +4082,40 @@ impl Endpoint {
         let request_timeout = request_timeout
             .map(|d| d.evaluate(static_vars))
             .transpose()?;
+        let retry = retry.map(|r| r.evaluate(static_vars, marker)).transpose()?;
+        let sla = sla
+            .map(|limits| {
+                let limits = limits
+                    .into_iter()
+                    .map(|(name, limit)| {
+                        let marker = (limit.0).0.marker;
+                        let percentile = name
+                            .strip_prefix('p')
+                            .and_then(|p| p.parse::<f64>().ok())
+                            .filter(|p| *p > 0.0 && *p <= 100.0)
+                            .ok_or_else(|| {
+                                Error::UnrecognizedKey(name.clone(), Some("sla".into()), marker)
+                            })?;
+                        let limit = limit.evaluate_millis(static_vars)?;
+                        Ok::<_, Error>((name, percentile, limit))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok::<_, Error>(Sla { limits })
+            })
+            .transpose()?;
+        let throttle = throttle.map(|t| t.evaluate(static_vars)).transpose()?;
 
         let mut endpoint = Endpoint {
             declare,
             headers,
             body,
+            checks,
+            compress,
             load_pattern,
             logs: Default::default(),
             max_parallel_requests,
             method,
+            metrics,
             no_auto_returns,
             on_demand,
             peak_load,
@@ -2611,8 +4123,11 @@ impl Endpoint {
             providers_to_stream,
             request_timeout,
             required_providers,
+            retry,
+            sla,
             url,
             tags,
+            throttle,
         };
 
         for (key, value) in logs.0 {
@@ -2698,12 +4213,21 @@ impl LoadTest {
             general: GeneralConfig {
                 auto_buffer_start_size: c.config.general.auto_buffer_start_size,
                 bucket_size: c.config.general.bucket_size.evaluate(&vars)?,
+                histogram_significant_digits: c.config.general.histogram_significant_digits,
                 log_provider_stats: c
                     .config
                     .general
                     .log_provider_stats
                     .map(|b| b.evaluate(&vars))
                     .transpose()?,
+                percentiles: c.config.general.percentiles,
+                stats_sinks: c
+                    .config
+                    .general
+                    .stats_sinks
+                    .into_iter()
+                    .map(|s| s.evaluate(&vars))
+                    .collect::<Result<_, _>>()?,
                 watch_transition_time: c
                     .config
                     .general
@@ -2713,8 +4237,9 @@ impl LoadTest {
             },
         };
         let mut load_test_errors = Vec::new();
+        let mut warnings = Vec::new();
         let mut endpoint_markers = Vec::new();
-        let endpoints = c
+        let endpoints: Vec<Endpoint> = c
             .endpoints
             .into_iter()
             .enumerate()
@@ -2730,6 +4255,18 @@ impl LoadTest {
                     config_path,
                 )?;
 
+                if let MethodTemplate::Static(m) = &e.method {
+                    if let Some(standard) = similar_standard_method(m) {
+                        warnings.push(format!(
+                            "method `{}` of the endpoint at line {} column {} is not a standard method. Did you mean `{}`?",
+                            m,
+                            marker.line(),
+                            marker.col(),
+                            standard
+                        ));
+                    }
+                }
+
                 // check for errors which would prevent a load test (but are ok for a try run)
                 if e.peak_load.is_none() {
                     let requires_response_provider = e.required_providers.iter().any(|(p, _)| {
@@ -2789,14 +4326,34 @@ impl LoadTest {
                 Ok((key, value))
             })
             .collect::<Result<_, Error>>()?;
+        let thresholds = c
+            .thresholds
+            .into_iter()
+            .map(|t| {
+                let marker = (t.condition.0).0.marker;
+                let t = t.evaluate(&vars)?;
+                // a threshold on a check needs an endpoint with that check
+                if let ThresholdMetric::Check(name) = &t.metric {
+                    let exists = endpoints
+                        .iter()
+                        .any(|e| e.checks.iter().any(|(n, _)| n == name));
+                    if !exists {
+                        return Err(Error::UnknownCheck(name.clone(), marker));
+                    }
+                }
+                Ok(t)
+            })
+            .collect::<Result<_, Error>>()?;
 
         let mut loadtest = LoadTest {
             config,
             endpoints,
             providers,
             loggers: Default::default(),
+            thresholds,
             vars,
             load_test_errors,
+            warnings,
         };
 
         for (key, value) in loggers {
@@ -2847,6 +4404,11 @@ impl LoadTest {
         }
     }
 
+    // things in the config which are allowed but are likely a mistake
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
     pub fn ok_for_loadtest(&self) -> Result<(), Error> {
         self.load_test_errors
             .get(0)
@@ -2916,9 +4478,39 @@ mod tests {
             ("CONNECT", Some(Method::CONNECT)),
             ("PATCH", Some(Method::PATCH)),
             ("TRACE", Some(Method::TRACE)),
-            ("GIT", None),
-            ("7", None),
-            ("get", None),
+            ("GIT", Some(Method::from_bytes(b"GIT").unwrap())),
+            ("7", Some(Method::from_bytes(b"7").unwrap())),
+            ("get", Some(Method::from_bytes(b"get").unwrap())),
+            ("PROPFIND", Some(Method::from_bytes(b"PROPFIND").unwrap())),
+            ("PURGE", Some(Method::from_bytes(b"PURGE").unwrap())),
+            (
+                "VERSION-CONTROL",
+                Some(Method::from_bytes(b"VERSION-CONTROL").unwrap()),
+            ),
+            ("TRACK", Some(Method::from_bytes(b"TRACK").unwrap())),
+            ("BATCH", Some(Method::from_bytes(b"BATCH").unwrap())),
+            ("M-SEARCH", Some(Method::from_bytes(b"M-SEARCH").unwrap())),
+            ("propfind", Some(Method::from_bytes(b"propfind").unwrap())),
+            ("GET /", None),
+            ("", None),
+            ("MK(COL)", None),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_pre_method() {
+        let values = vec![
+            ("PUT", Some(PreMethod::Static(Method::PUT))),
+            (
+                "MKCOL",
+                Some(PreMethod::Static(Method::from_bytes(b"MKCOL").unwrap())),
+            ),
+            (
+                "${record.method}",
+                Some(PreMethod::Template(create_template("${record.method}"))),
+            ),
+            ("GET /", None),
         ];
         check_all(values);
     }
@@ -3056,6 +4648,53 @@ mod tests {
         check_all(values);
     }
 
+    #[test]
+    fn linear_builder_percent_at() {
+        let mut lb = LinearBuilder::new(0.0, 1.0, Duration::from_secs(10));
+        lb.append(1.0, 1.0, Duration::from_secs(5));
+        let checks = vec![
+            (0, Some(0.0)),
+            (5, Some(0.5)),
+            (10, Some(1.0)),
+            (14, Some(1.0)),
+            (15, None),
+        ];
+        for (secs, expected) in checks {
+            assert_eq!(
+                lb.percent_at(Duration::from_secs(secs)),
+                expected,
+                "at {}s",
+                secs
+            );
+        }
+        assert_eq!(HitsPer::Minute(120.0).per_second(), 2.0);
+    }
+
+    #[test]
+    fn linear_builder_percent_seconds_between() {
+        let mut lb = LinearBuilder::new(0.0, 1.0, Duration::from_secs(10));
+        lb.append(1.0, 1.0, Duration::from_secs(5));
+        let checks = vec![
+            (0, 10, 5.0),
+            (0, 5, 1.25),
+            (5, 12, 5.75),
+            (10, 20, 5.0),
+            (15, 20, 0.0),
+            (5, 5, 0.0),
+        ];
+        for (from, to, expected) in checks {
+            let actual =
+                lb.percent_seconds_between(Duration::from_secs(from), Duration::from_secs(to));
+            assert!(
+                (actual - expected).abs() < 1e-9,
+                "from {}s to {}s was {}",
+                from,
+                to,
+                actual
+            );
+        }
+    }
+
     #[test]
     fn from_yaml_provider_pre_processed() {
         let values = vec![
@@ -3110,8 +4749,10 @@ mod tests {
             declare: Default::default(),
             headers: Default::default(),
             body: None,
+            checks: Default::default(),
+            compress: None,
             load_pattern: None,
-            method: Method::GET,
+            method: PreMethod::Static(Method::GET),
             on_demand: false,
             peak_load: None,
             tags: Default::default(),
@@ -3120,11 +4761,475 @@ mod tests {
             logs: Default::default(),
             no_auto_returns: false,
             max_parallel_requests: None,
+            metrics: Default::default(),
             request_timeout: None,
+            retry: None,
+            sla: None,
+            throttle: None,
             marker: create_marker(),
         }
     }
 
+    #[test]
+    fn pre_bandwidth_to_bytes_per_second() {
+        let values = vec![
+            ("64kbps", Some(8_000)),
+            ("256 Kbps", Some(32_000)),
+            ("1.5mbps", Some(187_500)),
+            ("1Gbps", Some(125_000_000)),
+            ("800bps", Some(100)),
+            ("1bps", None),
+            ("64kb", None),
+            ("fast", None),
+        ];
+
+        for (template, expect) in values {
+            let pre = PreTemplate::from_yaml_str(template).expect("should be valid yaml");
+            let value = PreBandwidth(pre).evaluate(&Default::default()).ok();
+            assert_eq!(value, expect, "{}", template);
+        }
+    }
+
+    #[test]
+    fn pre_byte_size_to_bytes() {
+        let values = vec![
+            ("50MB", Some(50_000_000)),
+            ("1.5 kb", Some(1_500)),
+            ("2KiB", Some(2_048)),
+            ("1GiB", Some(1_073_741_824)),
+            ("1TB", Some(1_000_000_000_000)),
+            ("100B", Some(100)),
+            ("100", None),
+            ("5Mbps", None),
+        ];
+
+        for (template, expect) in values {
+            let pre = PreTemplate::from_yaml_str(template).expect("should be valid yaml");
+            let value = PreByteSize(pre).evaluate(&Default::default()).ok();
+            assert_eq!(value, expect, "{}", template);
+        }
+    }
+
+    #[test]
+    fn from_yaml_generate() {
+        let values = vec![
+            (
+                "size: 50MB",
+                Some(GeneratePreProcessed {
+                    pattern: BodyPattern::Random,
+                    size: PreByteSize(create_template("50MB")),
+                }),
+            ),
+            (
+                "
+                size: 1KiB
+                pattern: zeros",
+                Some(GeneratePreProcessed {
+                    pattern: BodyPattern::Zeros,
+                    size: PreByteSize(create_template("1KiB")),
+                }),
+            ),
+            (
+                "
+                size: 10B
+                pattern:
+                  repeat: abc",
+                Some(GeneratePreProcessed {
+                    pattern: BodyPattern::Repeat("abc".into()),
+                    size: PreByteSize(create_template("10B")),
+                }),
+            ),
+            ("pattern: zeros", None),
+            ("size: 1MB\npattern: ones", None),
+            ("size: 1MB\npattern:\n  repeat: ''", None),
+            ("size: 1MB\nfoo: bar", None),
+        ];
+
+        check_all(values);
+    }
+
+    #[test]
+    fn durations_from_strings() {
+        let values = vec![
+            ("1h45m30s", Some(Duration::from_secs(6_330))),
+            ("4 hrs 15 mins", Some(Duration::from_secs(15_300))),
+            ("2 days", Some(Duration::from_secs(172_800))),
+            ("5 mins", Some(Duration::from_secs(300))),
+            ("100ms", None),
+            ("1.5s", None),
+            ("5", None),
+        ];
+
+        for (s, expect) in values {
+            assert_eq!(duration_from_string(s.into()).ok(), expect, "{}", s);
+        }
+
+        let values = vec![
+            ("100ms", Some(Duration::from_millis(100))),
+            ("1s 500ms", Some(Duration::from_millis(1_500))),
+            ("250 milliseconds", Some(Duration::from_millis(250))),
+            ("5 mins", Some(Duration::from_secs(300))),
+            ("1.5s", None),
+        ];
+
+        for (s, expect) in values {
+            let d = millis_duration_from_string2(s.into(), create_marker());
+            assert_eq!(d.ok(), expect, "{}", s);
+        }
+    }
+
+    #[test]
+    fn from_yaml_retry() {
+        let values = vec![
+            (
+                "
+                max: 3
+                on: [connection_error, timeout, 503]
+                backoff: exponential(100ms, 2s)
+                per_try_timeout: 500ms",
+                Some(RetryPreProcessed {
+                    backoff: Some(PreBackoff(create_template("exponential(100ms, 2s)"))),
+                    max: 3,
+                    on: Some(vec![
+                        RetryOn::ConnectionError,
+                        RetryOn::Timeout,
+                        RetryOn::Status(503),
+                    ]),
+                    per_try_timeout: Some(PreDuration(create_template("500ms"))),
+                }),
+            ),
+            (
+                "max: 1",
+                Some(RetryPreProcessed {
+                    backoff: None,
+                    max: 1,
+                    on: None,
+                    per_try_timeout: None,
+                }),
+            ),
+            ("on: [timeout]", None),
+            ("max: 1\non: [999]", None),
+            ("max: 1\non: [refused]", None),
+            ("max: 1\nfoo: bar", None),
+        ];
+
+        check_all(values);
+    }
+
+    #[test]
+    fn pre_backoff_evaluates() {
+        let values = vec![
+            (
+                "exponential(100ms, 2s)",
+                Some(Backoff::Exponential(
+                    Duration::from_millis(100),
+                    Duration::from_secs(2),
+                )),
+            ),
+            (
+                "constant( 500ms )",
+                Some(Backoff::Constant(Duration::from_millis(500))),
+            ),
+            ("exponential(2s, 100ms)", None),
+            ("exponential(100ms)", None),
+            ("constant(1s, 2s)", None),
+            ("linear(1s)", None),
+        ];
+
+        for (template, expect) in values {
+            let pre = PreTemplate::from_yaml_str(template).expect("should be valid yaml");
+            let value = PreBackoff(pre).evaluate(&Default::default()).ok();
+            assert_eq!(value, expect, "{}", template);
+        }
+
+        let backoff = Backoff::Exponential(Duration::from_millis(100), Duration::from_secs(2));
+        let delays: Vec<_> = (1..=7).map(|n| backoff.delay(n).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1_600, 2_000, 2_000]);
+        assert_eq!(backoff.delay(100), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn from_yaml_threshold() {
+        let values = vec![
+            (
+                "p99 < 500ms",
+                Some(ThresholdPreProcessed {
+                    abort_on_fail: false,
+                    condition: PreThreshold(create_template("p99 < 500ms")),
+                    tags: Default::default(),
+                }),
+            ),
+            (
+                "
+                condition: error_rate < 1%
+                tags:
+                    url: '*orders*'
+                abort_on_fail: true",
+                Some(ThresholdPreProcessed {
+                    abort_on_fail: true,
+                    condition: PreThreshold(create_template("error_rate < 1%")),
+                    tags: btreemap! {
+                        "url".to_string() => "*orders*".to_string(),
+                    },
+                }),
+            ),
+            ("abort_on_fail: true", None),
+            ("condition: timeouts == 0\nfoo: bar", None),
+        ];
+
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_metric() {
+        let values = vec![
+            (
+                "counter(response.status == 200)",
+                Some(MetricPreProcessed {
+                    select: create_with_marker("counter(response.status == 200)".to_string()),
+                    where_clause: None,
+                }),
+            ),
+            (
+                "
+                select: gauge(stats.rtt)
+                where: response.status == 200",
+                Some(MetricPreProcessed {
+                    select: create_with_marker("gauge(stats.rtt)".to_string()),
+                    where_clause: Some(create_with_marker("response.status == 200".to_string())),
+                }),
+            ),
+            ("where: response.status == 200", None),
+            ("select: gauge(stats.rtt)\nfoo: bar", None),
+        ];
+
+        check_all(values);
+    }
+
+    #[test]
+    fn metric_evaluates() {
+        let values = vec![
+            ("counter(response.status == 200)", Some(MetricKind::Counter)),
+            (" gauge( stats.rtt ) ", Some(MetricKind::Gauge)),
+            (
+                "histogram(response.body.items.length)",
+                Some(MetricKind::Histogram),
+            ),
+            ("average(stats.rtt)", None),
+            ("histogram()", None),
+            ("stats.rtt", None),
+        ];
+        for (select, expected) in values {
+            let metric = MetricPreProcessed {
+                select: create_with_marker(select.to_string()),
+                where_clause: None,
+            };
+            let kind = metric
+                .evaluate(&Default::default(), &mut RequiredProviders::new())
+                .ok()
+                .map(|(kind, _)| kind);
+            assert_eq!(kind, expected, "metric `{}`", select);
+        }
+    }
+
+    #[test]
+    fn pre_threshold_evaluates() {
+        let values = vec![
+            (
+                "p99 < 500ms",
+                Some((ThresholdMetric::Percentile(99.0), ThresholdOp::Lt, 500.0)),
+            ),
+            (
+                "p99.9<=2s",
+                Some((ThresholdMetric::Percentile(99.9), ThresholdOp::Le, 2_000.0)),
+            ),
+            (
+                "error_rate < 1%",
+                Some((ThresholdMetric::ErrorRate, ThresholdOp::Lt, 1.0)),
+            ),
+            (
+                "checks.status_ok > 99.5%",
+                Some((
+                    ThresholdMetric::Check("status_ok".into()),
+                    ThresholdOp::Gt,
+                    99.5,
+                )),
+            ),
+            (
+                "timeouts == 0",
+                Some((ThresholdMetric::Timeouts, ThresholdOp::Eq, 0.0)),
+            ),
+            (
+                "avg != 3",
+                Some((ThresholdMetric::Mean, ThresholdOp::Ne, 3.0)),
+            ),
+            ("timeouts == 0ms", None),
+            ("error_rate < 1s", None),
+            ("p101 < 5ms", None),
+            ("p99 = 500ms", None),
+            ("checks. > 99%", None),
+            ("latency < 5ms", None),
+        ];
+
+        for (template, expect) in values {
+            let pre = PreTemplate::from_yaml_str(template).expect("should be valid yaml");
+            let value = PreThreshold(pre)
+                .evaluate(&Default::default())
+                .ok()
+                .map(|(_, metric, op, value)| (metric, op, value));
+            assert_eq!(value, expect, "{}", template);
+        }
+
+        let threshold = ThresholdPreProcessed {
+            abort_on_fail: false,
+            condition: PreThreshold(create_template("p99 <= 500ms")),
+            tags: btreemap! {
+                "url".to_string() => "*orders*".to_string(),
+            },
+        }
+        .evaluate(&Default::default())
+        .unwrap();
+        assert!(threshold.passes(500.0));
+        assert!(!threshold.passes(500.1));
+        let tags = btreemap! {
+            "url".to_string() => "https://localhost/orders/1".to_string(),
+        };
+        assert!(threshold.matches_tags(&tags));
+        let tags = btreemap! {
+            "url".to_string() => "https://localhost/users/1".to_string(),
+        };
+        assert!(!threshold.matches_tags(&tags));
+        assert!(!threshold.matches_tags(&BTreeMap::new()));
+    }
+
+    #[test]
+    fn warns_about_misspelled_methods() {
+        for (method, warns) in [
+            ("GET", false),
+            ("PROPFIND", false),
+            ("MKCOL", false),
+            ("get", true),
+            ("GIT", true),
+            ("PUTS", true),
+        ]
+        .iter()
+        .copied()
+        {
+            let yaml = format!(
+                "endpoints:\n  - url: http://localhost\n    method: {}",
+                method
+            );
+            let config =
+                LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &BTreeMap::new()).unwrap();
+            assert_eq!(config.warnings().len(), warns as usize, "{}", method);
+        }
+    }
+
+    #[test]
+    fn retry_on_timeout_needs_a_per_try_timeout() {
+        let on = |retry: &str| {
+            let yaml = format!(
+                "endpoints:\n  - url: http://localhost\n    retry: {}",
+                retry
+            );
+            LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &BTreeMap::new())
+                .map(|mut c| c.endpoints.remove(0).retry.unwrap().on)
+        };
+
+        // timeouts are only retried by default when each attempt has its own timeout
+        assert_eq!(on("{max: 1}").unwrap(), vec![RetryOn::ConnectionError]);
+        assert_eq!(
+            on("{max: 1, per_try_timeout: 500ms}").unwrap(),
+            vec![RetryOn::ConnectionError, RetryOn::Timeout]
+        );
+        assert_eq!(
+            on("{max: 1, on: [timeout, 503], per_try_timeout: 1s}").unwrap(),
+            vec![RetryOn::Timeout, RetryOn::Status(503)]
+        );
+        assert!(matches!(
+            on("{max: 1, on: [connection_error, timeout, 503]}"),
+            Err(Error::RetryOnTimeoutWithoutPerTryTimeout(_))
+        ));
+    }
+
+    #[test]
+    fn retry_requires_a_buffered_body() {
+        let yaml = "endpoints:\n  - url: http://localhost\n    body: '{}'\n    retry: {max: 1}";
+        let r = LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &BTreeMap::new());
+        assert!(r.is_ok());
+
+        for body in &[
+            "{file: data.bin}",
+            "{generate: {size: 1MB}}",
+            "{multipart: {a: {body: {file: data.bin}}}}",
+        ] {
+            let yaml = format!(
+                "endpoints:\n  - url: http://localhost\n    body: {}\n    retry: {{max: 1}}",
+                body
+            );
+            let r = LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &BTreeMap::new());
+            assert!(
+                matches!(r, Err(Error::RetryWithStreamedBody(_))),
+                "{}",
+                body
+            );
+        }
+    }
+
+    #[test]
+    fn endpoint_sla_evaluates() {
+        let yaml = "endpoints:\n  - url: http://localhost\n    sla: {p95: 200ms, p99.9: 1s}";
+        let load_test = LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &BTreeMap::new())
+            .ok()
+            .unwrap();
+        let sla = load_test.endpoints[0].sla.as_ref().unwrap();
+        let limits: Vec<_> = sla
+            .limits
+            .iter()
+            .map(|(name, p, limit)| (name.as_str(), *p, limit.as_millis()))
+            .collect();
+        assert_eq!(limits, vec![("p95", 95.0, 200), ("p99.9", 99.9, 1_000)]);
+        assert!(!sla.is_exceeded_by(Duration::from_millis(200)));
+        assert!(sla.is_exceeded_by(Duration::from_millis(201)));
+
+        for yaml in &[
+            "endpoints:\n  - url: http://localhost\n    sla: {avg: 200ms}",
+            "endpoints:\n  - url: http://localhost\n    sla: {p101: 200ms}",
+            "endpoints:\n  - url: http://localhost\n    sla: {p95: fast}",
+        ] {
+            let r = LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &BTreeMap::new());
+            assert!(r.is_err(), "{}", yaml);
+        }
+    }
+
+    #[test]
+    fn from_yaml_throttle() {
+        let values = vec![
+            (
+                "
+                upload: 64kbps
+                download: 256kbps
+                slow_read: 1s",
+                Some(ThrottlePreProcessed {
+                    download: Some(PreBandwidth(create_template("256kbps"))),
+                    slow_read: Some(PreDuration(create_template("1s"))),
+                    upload: Some(PreBandwidth(create_template("64kbps"))),
+                }),
+            ),
+            (
+                "download: 1mbps",
+                Some(ThrottlePreProcessed {
+                    download: Some(PreBandwidth(create_template("1mbps"))),
+                    slow_read: None,
+                    upload: None,
+                }),
+            ),
+            ("fast: 1mbps", None),
+            ("64kbps", None),
+        ];
+        check_all(values);
+    }
+
     #[test]
     fn pre_hits_per_to_hits_per() {
         let values = vec![
@@ -3157,6 +5262,9 @@ mod tests {
                     baz: abc
                 method: GET
                 body: foo
+                checks:
+                    status_ok: response.status == 200
+                compress: gzip
                 load_pattern:
                     - linear:
                         to: 100%
@@ -3167,6 +5275,8 @@ mod tests {
                     foo: bar
                 url: http://localhost:8080/
                 max_parallel_requests: 3
+                metrics:
+                    items: histogram(response.body.items.length)
                 provides:
                     foo:
                         select: 1
@@ -3178,7 +5288,14 @@ mod tests {
                     foo:
                         select: 1
                 no_auto_returns: true
-                request_timeout: 15s",
+                request_timeout: 15s
+                retry:
+                    max: 2
+                sla:
+                    p95: 200ms
+                    p99: 1s
+                throttle:
+                    upload: 64kbps",
                 Some(EndpointPreProcessed {
                     declare: btreemap! {
                         "foo".to_string() => PreValueOrExpression(create_with_marker("bar".to_string()))
@@ -3189,6 +5306,12 @@ mod tests {
                     ]
                     .into(),
                     body: Some(Body::String(create_template("foo"))),
+                    checks: vec![(
+                        "status_ok".to_string(),
+                        create_with_marker("response.status == 200".to_string()),
+                    )]
+                    .into(),
+                    compress: Some(BodyCompression::Gzip),
                     load_pattern: Some(PreLoadPattern(
                         vec![LoadPatternPreProcessed::Linear(LinearBuilderPreProcessed {
                             from: None,
@@ -3197,7 +5320,7 @@ mod tests {
                         })],
                         create_marker(),
                     )),
-                    method: Method::GET,
+                    method: PreMethod::Static(Method::GET),
                     on_demand: true,
                     peak_load: Some(PreHitsPer(create_template("50hps"))),
                     tags: btreemap! {
@@ -3246,7 +5369,32 @@ mod tests {
                     .into(),
                     no_auto_returns: true,
                     max_parallel_requests: Some(NonZeroUsize::new(3).unwrap()),
+                    metrics: vec![(
+                        "items".to_string(),
+                        MetricPreProcessed {
+                            select: create_with_marker(
+                                "histogram(response.body.items.length)".to_string(),
+                            ),
+                            where_clause: None,
+                        },
+                    )]
+                    .into(),
                     request_timeout: Some(PreDuration(create_template("15s"))),
+                    retry: Some(RetryPreProcessed {
+                        backoff: None,
+                        max: 2,
+                        on: None,
+                        per_try_timeout: None,
+                    }),
+                    sla: Some(btreemap! {
+                        "p95".to_string() => PreDuration(create_template("200ms")),
+                        "p99".to_string() => PreDuration(create_template("1s")),
+                    }),
+                    throttle: Some(ThrottlePreProcessed {
+                        download: None,
+                        slow_read: None,
+                        upload: Some(PreBandwidth(create_template("64kbps"))),
+                    }),
                     marker: create_marker(),
                 }),
             ),
@@ -3271,6 +5419,31 @@ mod tests {
                 "!file foo.bar",
                 Some(Body::File(create_template("foo.bar"))),
             ),
+            (
+                "form:
+                    foo: bar
+                    baz: ${abc}",
+                Some(Body::Form(
+                    vec![
+                        ("foo".to_string(), create_template("bar")),
+                        ("baz".to_string(), create_template("${abc}")),
+                    ]
+                    .into(),
+                )),
+            ),
+            (
+                "json:
+                    foo: bar
+                    baz: 1",
+                Some(Body::Json(create_with_marker(
+                    json::json!({ "foo": "bar", "baz": 1 }),
+                ))),
+            ),
+            (
+                "bytes: foo.image",
+                Some(Body::Bytes(create_with_marker(json::json!("foo.image")))),
+            ),
+            ("foo: bar", None),
             (
                 "multipart:
                     foo: 
@@ -3292,6 +5465,19 @@ mod tests {
         check_all(values);
     }
 
+    #[test]
+    fn from_yaml_body_compression() {
+        let values = vec![
+            ("br", Some(BodyCompression::Brotli)),
+            ("deflate", Some(BodyCompression::Deflate)),
+            ("gzip", Some(BodyCompression::Gzip)),
+            ("zstd", Some(BodyCompression::Zstd)),
+            ("zip", None),
+            ("GZIP", None),
+        ];
+        check_all(values);
+    }
+
     #[test]
     fn from_yaml_endpoints_provides_pre_processed() {
         let values = vec![
@@ -3389,6 +5575,64 @@ mod tests {
                 "{}",
                 Some(GeneralConfigPreProcessed::default(create_marker())),
             ),
+            (
+                "percentiles: [50, 75, 99.99]\nhistogram_significant_digits: 4",
+                Some(GeneralConfigPreProcessed {
+                    histogram_significant_digits: 4,
+                    percentiles: vec![50.0, 75.0, 99.99],
+                    ..DefaultWithMarker::default(create_marker())
+                }),
+            ),
+            ("percentiles: [50, 0]", None),
+            ("percentiles: [100.5]", None),
+            ("percentiles: p99", None),
+            ("histogram_significant_digits: 0", None),
+            ("histogram_significant_digits: 6", None),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_stats_sink_pre_processed() {
+        let values =
+            vec![
+            ("asdf", None),
+            ("to: localhost:8125", None),
+            ("format: graphite\nto: localhost:8125", None),
+            (
+                "format: statsd\nto: localhost:8125",
+                Some(StatsSinkPreProcessed {
+                    format: StatsSinkFormat::StatsD,
+                    prefix: None,
+                    protocol: None,
+                    sample_rate: None,
+                    to: create_template("localhost:8125"),
+                }),
+            ),
+            (
+                "{format: influx, to: localhost:8089, protocol: tcp, prefix: lt, sample_rate: 0.5}",
+                Some(StatsSinkPreProcessed {
+                    format: StatsSinkFormat::Influx,
+                    prefix: Some("lt".into()),
+                    protocol: Some(StatsSinkProtocol::Tcp),
+                    sample_rate: Some(0.5),
+                    to: create_template("localhost:8089"),
+                }),
+            ),
+            ("{format: influx, to: localhost:8089, sample_rate: 0}", None),
+            ("{format: influx, to: localhost:8089, sample_rate: 1.5}", None),
+            (
+                "format: otlp\nto: http://localhost:4318/v1/metrics",
+                Some(StatsSinkPreProcessed {
+                    format: StatsSinkFormat::Otlp,
+                    prefix: None,
+                    protocol: None,
+                    sample_rate: None,
+                    to: create_template("http://localhost:4318/v1/metrics"),
+                }),
+            ),
+            ("{format: otlp, to: http://localhost:4318, protocol: udp}", None),
+            ("{format: otlp, to: http://localhost:4318, sample_rate: 0.1}", None),
         ];
         check_all(values);
     }
@@ -3405,6 +5649,23 @@ mod tests {
                     providers: Default::default(),
                     load_pattern: None,
                     loggers: Default::default(),
+                    thresholds: Default::default(),
+                    vars: Default::default(),
+                    endpoints: vec![create_endpoint_pre_processed("http://localhost:8080")],
+                }),
+            ),
+            (
+                "thresholds: [timeouts == 0]\nendpoints: [url: http://localhost:8080]",
+                Some(LoadTestPreProcessed {
+                    config: DefaultWithMarker::default(create_marker()),
+                    providers: Default::default(),
+                    load_pattern: None,
+                    loggers: Default::default(),
+                    thresholds: vec![ThresholdPreProcessed {
+                        abort_on_fail: false,
+                        condition: PreThreshold(create_template("timeouts == 0")),
+                        tags: Default::default(),
+                    }],
                     vars: Default::default(),
                     endpoints: vec![create_endpoint_pre_processed("http://localhost:8080")],
                 }),
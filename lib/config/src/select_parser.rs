@@ -1398,6 +1398,20 @@ impl Select {
         self.send_behavior = send_behavior;
     }
 
+    // evaluates just the `select` (ignoring any `for_each` and `where`) into a single value
+    pub fn evaluate(&self, d: &json::Value) -> Result<json::Value, ExecutingExpressionError> {
+        self.select
+            .evaluate(Cow::Borrowed(d), self.no_recoverable_error, None)
+            .map(Cow::into_owned)
+    }
+
+    // evaluates just the `select` and whether it is truthy, the same way a `where` clause is
+    pub fn evaluate_bool(&self, d: &json::Value) -> Result<bool, ExecutingExpressionError> {
+        self.select
+            .evaluate(Cow::Borrowed(d), self.no_recoverable_error, None)
+            .map(|v| bool_value(&v))
+    }
+
     pub fn execute_where(&self, d: &json::Value) -> Result<bool, ExecutingExpressionError> {
         self.where_clause
             .as_ref()
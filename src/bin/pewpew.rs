@@ -1,15 +1,45 @@
-use std::{convert::TryInto, fs::create_dir_all, io, path::PathBuf, time::UNIX_EPOCH};
+use std::{
+    convert::TryInto, fs::create_dir_all, io, net::SocketAddr, path::PathBuf, time::UNIX_EPOCH,
+};
 
 use clap::{crate_version, App, AppSettings, Arg, SubCommand};
-use config::duration_from_string;
+use config::{byte_size_from_string, duration_from_string};
 use futures::channel::mpsc as futures_channel;
 use pewpew::{
-    create_run, ExecConfig, RunConfig, StatsFileFormat, TryConfig, TryFilter, TryRunFormat,
+    compare_stats_files, create_run, summarize_stats_files, write_html_report, CompareConfig,
+    CompareFormat, ExecConfig, ReportConfig, RunConfig, StatsConfig, StatsFileCompression,
+    StatsFileFormat, StatsSummaryFormat, TryConfig, TryFilter, TryRunFormat,
 };
 use regex::Regex;
 use tokio::runtime;
 use yansi::Paint;
 
+// parse an include filter, which has already been validated against the regex
+fn parse_filter(filter_reg: &Regex, s: &str) -> TryFilter {
+    let captures = filter_reg
+        .captures(s)
+        .expect("include cli arg should match regex");
+    let left = captures
+        .get(1)
+        .expect("include arg should match regex")
+        .as_str()
+        .to_string();
+    let right = captures
+        .get(3)
+        .expect("include arg should match regex")
+        .as_str()
+        .to_string();
+    let comparator = captures
+        .get(2)
+        .expect("include arg should match regex")
+        .as_str();
+    match comparator {
+        "=" => TryFilter::Eq(left, right),
+        "!=" => TryFilter::Ne(left, right),
+        _ => unreachable!(),
+    }
+}
+
 fn main() {
     #[cfg(target_os = "windows")]
     {
@@ -22,6 +52,12 @@ fn main() {
     }
     let filter_reg = Regex::new("^(.*?)(!=|=)(.*)").expect("is a valid regex");
     let filter_reg2 = filter_reg.clone();
+    let filter_reg3 = filter_reg.clone();
+    let filter_reg4 = filter_reg.clone();
+    let tolerance_reg =
+        Regex::new(r"^(p\d+(?:\.\d+)?|mean|error_rate|throughput)=(\d+(?:\.\d+)?)%?$")
+            .expect("is a valid regex");
+    let tolerance_reg2 = tolerance_reg.clone();
     let matches = App::new("pewpew")
         .about("The HTTP load test tool https://familysearch.github.io/pewpew")
         .version(crate_version!())
@@ -77,16 +113,71 @@ fn main() {
                     .help("Format for the stats file")
                     .value_name("FORMAT")
                     .possible_value("json")
-                    // .possible_value("html")
+                    .possible_value("html")
                     // .possible_value("none")
                     .default_value("json")
             )
+            .arg(
+                Arg::with_name("stats-file-compression")
+                    .long("stats-file-compression")
+                    .help("Compress the stats file")
+                    .value_name("COMPRESSION")
+                    .possible_value("gzip")
+                    .possible_value("zstd")
+            )
+            .arg(
+                Arg::with_name("stats-file-rotate-size")
+                    .long("stats-file-rotate-size")
+                    .help("Start a new stats file once the current one has this much data before compression, such as `100MB`")
+                    .value_name("SIZE")
+                    .validator(|s| {
+                        match byte_size_from_string(s) {
+                            Ok(_) => Ok(()),
+                            Err(_) => Err("must be a size such as `100MB`".into()),
+                        }
+                    })
+            )
+            .arg(
+                Arg::with_name("stats-file-rotate-interval")
+                    .long("stats-file-rotate-interval")
+                    .help("Start a new stats file once the current one has been written to for this long, such as `1h`")
+                    .value_name("DURATION")
+                    .validator(|s| {
+                        match duration_from_string(s) {
+                            Ok(_) => Ok(()),
+                            Err(_) => Err("must be a duration such as `1h`".into()),
+                        }
+                    })
+            )
             .arg(
                 Arg::with_name("watch")
                     .short("w")
                     .long("watch")
                     .help("Watch the config file for changes and update the test accordingly")
             )
+            .arg(
+                Arg::with_name("junit")
+                    .long("junit")
+                    .help("Write the results of the test as a JUnit XML report to the specified file")
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::with_name("metrics-listen")
+                    .long("metrics-listen")
+                    .help("Serve the test's stats in the OpenMetrics format at `/metrics` on the specified address")
+                    .value_name("ADDRESS")
+                    .validator(|s| {
+                        match s.parse::<SocketAddr>() {
+                            Ok(_) => Ok(()),
+                            Err(_) => Err("must be an address and port such as `127.0.0.1:9100`".into()),
+                        }
+                    })
+            )
+            .arg(
+                Arg::with_name("tui")
+                    .long("tui")
+                    .help("Show a live dashboard of the test in place of the periodic stats")
+            )
             .arg(
                 Arg::with_name("CONFIG")
                     .help("Load test config file to use")
@@ -96,6 +187,12 @@ fn main() {
         .subcommand(SubCommand::with_name("try")
             .about("Runs the specified endpoint(s) a single time for testing purposes")
             .setting(AppSettings::UnifiedHelpMessage)
+            .arg(
+                Arg::with_name("junit")
+                    .long("junit")
+                    .help("Write the results of the test as a JUnit XML report to the specified file")
+                    .value_name("FILE")
+            )
             .arg(
                 Arg::with_name("loggers")
                     .short("l")
@@ -149,8 +246,266 @@ fn main() {
                     .required(true),
             )
         )
+        .subcommand(SubCommand::with_name("compare")
+            .about("Compares the stats of a candidate run against a baseline, exiting with an error code of 2 if there are regressions")
+            .setting(AppSettings::UnifiedHelpMessage)
+            .arg(
+                Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .help("Specify the format for the comparison")
+                    .value_name("FORMAT")
+                    .possible_value("human")
+                    .possible_value("json")
+                    .possible_value("markdown")
+                    .default_value("human")
+            )
+            .arg(
+                Arg::with_name("include")
+                    .short("i")
+                    .long("include")
+                    .long_help(r#"Filter which endpoints are compared. Filters work based on an endpoint's tags. Filters are specified in the format "key=value" where "*" is a wildcard. Any endpoint matching the filter is compared"#)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .validator(move |s| {
+                        if filter_reg4.is_match(&s) {
+                            Ok(())
+                        } else {
+                            Err("include filters must be in the format `tag=value` or `tag!=value`".to_string())
+                        }
+                    })
+                    .value_name("INCLUDE")
+            )
+            .arg(
+                Arg::with_name("tolerance")
+                    .short("t")
+                    .long("tolerance")
+                    .long_help("Override how much a metric can get worse before it is a regression, in the format \"metric=N%\". The metrics are the response time percentiles in the stats files, or any other percentile such as p99.99, mean and throughput, which default to a 10% change, and error_rate, which defaults to 1 percentage point")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .validator(move |s| {
+                        let captures = match tolerance_reg.captures(&s) {
+                            Some(captures) => captures,
+                            None => return Err("tolerances must be in the format `metric=N%`, such as `p95=5%`".to_string()),
+                        };
+                        match captures[1].strip_prefix('p').map(str::parse::<f64>) {
+                            Some(Ok(p)) if p <= 0.0 || p > 100.0 => {
+                                Err("percentiles must be greater than 0 and at most 100".to_string())
+                            }
+                            _ => Ok(()),
+                        }
+                    })
+                    .value_name("TOLERANCE")
+            )
+            .arg(
+                Arg::with_name("BASELINE")
+                    .help("The stats file of the baseline run")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("CANDIDATE")
+                    .help("The stats file of the run to compare against the baseline")
+                    .required(true),
+            )
+        )
+        .subcommand(SubCommand::with_name("report")
+            .about("Creates a self-contained HTML report from one or more stats files")
+            .setting(AppSettings::UnifiedHelpMessage)
+            .arg(
+                Arg::with_name("config")
+                    .short("c")
+                    .long("config")
+                    .help("The load test config file to include in the report and check the thresholds of")
+                    .value_name("CONFIG")
+            )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("The file to write the report to [default: the first stats file with an `.html` extension]")
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::with_name("STATS_FILE")
+                    .help("Stats files to include in the report")
+                    .multiple(true)
+                    .required(true),
+            )
+        )
+        .subcommand(SubCommand::with_name("stats")
+            .about("Summarizes one or more stats files, merging the stats of matching endpoints")
+            .setting(AppSettings::UnifiedHelpMessage)
+            .arg(
+                Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .help("Specify the format for the summary")
+                    .value_name("FORMAT")
+                    .possible_value("human")
+                    .possible_value("json")
+                    .possible_value("csv")
+                    .default_value("human")
+            )
+            .arg(
+                Arg::with_name("include")
+                    .short("i")
+                    .long("include")
+                    .long_help(r#"Filter which endpoints are included in the summary. Filters work based on an endpoint's tags. Filters are specified in the format "key=value" where "*" is a wildcard. Any endpoint matching the filter is included in the summary"#)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .validator(move |s| {
+                        if filter_reg3.is_match(&s) {
+                            Ok(())
+                        } else {
+                            Err("include filters must be in the format `tag=value` or `tag!=value`".to_string())
+                        }
+                    })
+                    .value_name("INCLUDE")
+            )
+            .arg(
+                Arg::with_name("from")
+                    .long("from")
+                    .help("Only include stats from this long after the start of the stats")
+                    .value_name("DURATION")
+                    .validator(|s| {
+                        match duration_from_string(s) {
+                            Ok(_) => Ok(()),
+                            Err(_) => Err("must be a duration such as `5m`".into()),
+                        }
+                    })
+            )
+            .arg(
+                Arg::with_name("to")
+                    .long("to")
+                    .help("Only include stats up to this long after the start of the stats")
+                    .value_name("DURATION")
+                    .validator(|s| {
+                        match duration_from_string(s) {
+                            Ok(_) => Ok(()),
+                            Err(_) => Err("must be a duration such as `5m`".into()),
+                        }
+                    })
+            )
+            .arg(
+                Arg::with_name("STATS_FILE")
+                    .help("Stats files to summarize")
+                    .multiple(true)
+                    .required(true),
+            )
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("compare") {
+        let filters = matches
+            .values_of("include")
+            .map(|v| v.map(|s| parse_filter(&filter_reg, s)).collect())
+            .unwrap_or_default();
+        let format: CompareFormat = matches
+            .value_of("format")
+            .and_then(|f| f.try_into().ok())
+            .expect("format cli arg unrecognized");
+        let tolerances = matches
+            .values_of("tolerance")
+            .map(|v| {
+                v.map(|s| {
+                    let captures = tolerance_reg2
+                        .captures(s)
+                        .expect("tolerance cli arg should match regex");
+                    let tolerance = captures[2]
+                        .parse()
+                        .expect("tolerance cli arg should be a number");
+                    (captures[1].to_string(), tolerance)
+                })
+                .collect()
+            })
+            .unwrap_or_default();
+        let compare_config = CompareConfig {
+            baseline: matches
+                .value_of_os("BASELINE")
+                .expect("should have BASELINE param")
+                .into(),
+            candidate: matches
+                .value_of_os("CANDIDATE")
+                .expect("should have CANDIDATE param")
+                .into(),
+            filters,
+            format,
+            tolerances,
+        };
+        match compare_stats_files(compare_config) {
+            Ok((comparison, regressed)) => {
+                print!("{}", comparison);
+                if regressed {
+                    std::process::exit(2);
+                }
+            }
+            Err(e) => {
+                eprintln!("\n{} {}", Paint::red("Fatal error").bold(), e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("report") {
+        let files: Vec<PathBuf> = matches
+            .values_of_os("STATS_FILE")
+            .expect("should have STATS_FILE param")
+            .map(PathBuf::from)
+            .collect();
+        let output = matches
+            .value_of_os("output")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| files[0].with_extension("html"));
+        let report_config = ReportConfig {
+            config_file: matches.value_of_os("config").map(PathBuf::from),
+            files,
+            output,
+        };
+        if let Err(e) = write_html_report(report_config) {
+            eprintln!("\n{} {}", Paint::red("Fatal error").bold(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("stats") {
+        let files = matches
+            .values_of_os("STATS_FILE")
+            .expect("should have STATS_FILE param")
+            .map(PathBuf::from)
+            .collect();
+        let filters = matches
+            .values_of("include")
+            .map(|v| v.map(|s| parse_filter(&filter_reg, s)).collect())
+            .unwrap_or_default();
+        let format: StatsSummaryFormat = matches
+            .value_of("format")
+            .and_then(|f| f.try_into().ok())
+            .expect("format cli arg unrecognized");
+        let from = matches
+            .value_of("from")
+            .map(|s| duration_from_string(s.to_string()).expect("from should match pattern"));
+        let to = matches
+            .value_of("to")
+            .map(|s| duration_from_string(s.to_string()).expect("to should match pattern"));
+        let stats_config = StatsConfig {
+            files,
+            filters,
+            format,
+            from,
+            to,
+        };
+        match summarize_stats_files(stats_config) {
+            Ok(summary) => print!("{}", summary),
+            Err(e) => {
+                eprintln!("\n{} {}", Paint::red("Fatal error").bold(), e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     let (ctrl_c_tx, ctrlc_channel) = futures_channel::unbounded();
 
     let _ = ctrlc::set_handler(move || {
@@ -172,6 +527,23 @@ fn main() {
                 .expect("should have output_format cli arg"),
         )
         .expect("output_format cli arg unrecognized");
+        let stats_file_format: StatsFileFormat = matches
+            .value_of("stats-file-format")
+            .and_then(|f| f.try_into().ok())
+            .expect("stats_file_format cli arg unrecognized");
+        let stats_file_compression: Option<StatsFileCompression> =
+            matches.value_of("stats-file-compression").map(|c| {
+                c.try_into()
+                    .expect("stats_file_compression cli arg unrecognized")
+            });
+        let extension = match stats_file_format {
+            StatsFileFormat::Html => "html",
+            StatsFileFormat::Json => "json",
+        };
+        let extension = match stats_file_compression {
+            Some(compression) => format!("{}.{}", extension, compression.extension()),
+            None => extension.to_string(),
+        };
         let stats_file = matches
             .value_of_os("stats-file")
             .map(PathBuf::from)
@@ -182,9 +554,9 @@ fn main() {
                     .unwrap_or_default();
                 let test_name = config_file.file_stem().and_then(std::ffi::OsStr::to_str);
                 let file = if let Some(test_name) = test_name {
-                    format!("stats-{}-{}.json", test_name, start_sec)
+                    format!("stats-{}-{}.{}", test_name, start_sec, extension)
                 } else {
-                    format!("stats-{}.json", start_sec)
+                    format!("stats-{}.{}", start_sec, extension)
                 };
                 PathBuf::from(file)
             });
@@ -195,18 +567,51 @@ fn main() {
         } else {
             stats_file
         };
-        let stats_file_format = StatsFileFormat::Json;
         let watch_config_file = matches.is_present("watch");
+        let metrics_listen = matches
+            .value_of("metrics-listen")
+            .map(|s| s.parse().expect("metrics-listen should be a valid address"));
         let start_at = matches
             .value_of("start-at")
             .map(|s| duration_from_string(s.to_string()).expect("start_at should match pattern"));
+        let stats_file_rotate_size = matches.value_of("stats-file-rotate-size").map(|s| {
+            byte_size_from_string(s.to_string()).expect("stats_file_rotate_size should be a size")
+        });
+        let stats_file_rotate_interval = matches.value_of("stats-file-rotate-interval").map(|s| {
+            duration_from_string(s.to_string())
+                .expect("stats_file_rotate_interval should be a duration")
+        });
+        let junit = matches.value_of_os("junit").map(PathBuf::from);
+        let tui = matches.is_present("tui");
+        // the dashboard redraws the terminal, so it can't be mixed with json output or written to
+        // something other than a terminal
+        if tui && matches.value_of("output-format") == Some("json") {
+            clap::Error::with_description(
+                "The argument '--tui' cannot be used with '--output-format json'",
+                clap::ErrorKind::ArgumentConflict,
+            )
+            .exit();
+        }
+        if tui && atty::isnt(atty::Stream::Stdout) {
+            clap::Error::with_description(
+                "The argument '--tui' can only be used when stdout is a terminal",
+                clap::ErrorKind::InvalidValue,
+            )
+            .exit();
+        }
         let run_config = RunConfig {
             config_file,
+            junit,
+            metrics_listen,
             output_format,
             results_dir,
             start_at,
             stats_file,
+            stats_file_compression,
             stats_file_format,
+            stats_file_rotate_interval,
+            stats_file_rotate_size,
+            tui,
             watch_config_file,
         };
         ExecConfig::Run(run_config)
@@ -224,43 +629,21 @@ fn main() {
             }
             _ => None,
         };
-        let filters = matches.values_of("include").map(|v| {
-            v.map(|s| {
-                let captures = filter_reg
-                    .captures(s)
-                    .expect("include cli arg should match regex");
-                let left = captures
-                    .get(1)
-                    .expect("include arg should match regex")
-                    .as_str()
-                    .to_string();
-                let right = captures
-                    .get(3)
-                    .expect("include arg should match regex")
-                    .as_str()
-                    .to_string();
-                let comparator = captures
-                    .get(2)
-                    .expect("include arg should match regex")
-                    .as_str();
-                match comparator {
-                    "=" => TryFilter::Eq(left, right),
-                    "!=" => TryFilter::Ne(left, right),
-                    _ => unreachable!(),
-                }
-            })
-            .collect()
-        });
+        let filters = matches
+            .values_of("include")
+            .map(|v| v.map(|s| parse_filter(&filter_reg, s)).collect());
         let format: TryRunFormat = matches
             .value_of("format")
             .and_then(|f| f.try_into().ok())
             .unwrap_or_default();
         let file = matches.value_of("file").map(Into::into);
+        let junit = matches.value_of_os("junit").map(PathBuf::from);
         let try_config = TryConfig {
             config_file,
             file,
             filters,
             format,
+            junit,
             loggers_on,
             results_dir,
         };
@@ -281,7 +664,7 @@ fn main() {
     // shutdown the runtime in case there are any hanging threads/tasks
     rt.shutdown_timeout(Default::default());
 
-    if result.is_err() {
-        std::process::exit(1)
+    if let Err(e) = result {
+        std::process::exit(e.exit_code())
     }
 }
@@ -1,6 +1,8 @@
 use hyper::http::Error as HttpError;
 
-use std::{error::Error as StdError, fmt, path::PathBuf, sync::Arc, time::SystemTime};
+use std::{
+    error::Error as StdError, fmt, net::SocketAddr, path::PathBuf, sync::Arc, time::SystemTime,
+};
 
 // An error that can happen in normal execution of an endpoint, but should not halt the test
 #[derive(Clone, Debug)]
@@ -9,6 +11,9 @@ pub enum RecoverableError {
     BodyErr(Arc<dyn StdError + Send + Sync>),
     ConnectionErr(SystemTime, Arc<dyn StdError + Send + Sync>),
     ExecutingExpression(Box<config::ExecutingExpressionError>),
+    InvalidMethod(String),
+    // a histogram metric's name and the negative value it was given
+    NegativeHistogramMetric(String, f64),
     Timeout(SystemTime),
 }
 
@@ -22,6 +27,8 @@ impl RecoverableError {
             ExecutingExpression(..) => 3,
             Timeout(_) => 4,
             ProviderDelay(_) => 5,
+            InvalidMethod(_) => 6,
+            NegativeHistogramMetric(..) => 7,
         }
     }
 }
@@ -32,6 +39,12 @@ impl fmt::Display for RecoverableError {
             BodyErr(e) => write!(f, "body error: {}", e),
             ConnectionErr(_, e) => write!(f, "connection error: `{}`", e),
             ExecutingExpression(e) => e.fmt(f),
+            InvalidMethod(m) => write!(f, "invalid method `{}`", m),
+            NegativeHistogramMetric(name, value) => write!(
+                f,
+                "histogram metric `{}` cannot record the negative value {}",
+                name, value
+            ),
             ProviderDelay(p) => write!(f, "endpoint was delayed waiting for provider `{}`", p),
             Timeout(..) => write!(f, "request timed out"),
         }
@@ -43,10 +56,14 @@ impl fmt::Display for RecoverableError {
 pub enum TestError {
     CannotCreateLoggerFile(String, Arc<std::io::Error>),
     CannotCreateStatsFile(String, Arc<std::io::Error>),
+    CannotStartMetricsServer(SocketAddr, Arc<hyper::Error>),
     CannotOpenFile(PathBuf, Arc<std::io::Error>),
     Config(Box<config::Error>),
+    ConsoleLoggerWithTui(String, String),
     FileReading(String, Arc<std::io::Error>),
     InvalidConfigFilePath(PathBuf),
+    InvalidStatsFile(PathBuf, Arc<serde_json::Error>),
+    InvalidStatsSink(String, Arc<std::io::Error>),
     InvalidUrl(String),
     Recoverable(RecoverableError),
     RequestBuilderErr(Arc<HttpError>),
@@ -67,12 +84,26 @@ impl fmt::Display for TestError {
         match self {
             CannotCreateLoggerFile(s, e) => write!(f, "error creating logger file `{}`: {}", s, e),
             CannotCreateStatsFile(s, e) => write!(f, "error creating stats file `{}`: {}", s, e),
+            CannotStartMetricsServer(a, e) => {
+                write!(f, "error starting metrics server on `{}`: {}", a, e)
+            }
             CannotOpenFile(p, e) => write!(f, "error opening file `{}`: {}", p.display(), e),
             Config(e) => e.fmt(f),
+            ConsoleLoggerWithTui(l, to) => {
+                write!(
+                    f,
+                    "logger `{}` writes to {} which can't be used with `--tui`",
+                    l, to
+                )
+            }
             FileReading(s, e) => write!(f, "error reading file `{}`: {}", s, e),
             InvalidConfigFilePath(p) => {
                 write!(f, "could not find config file at path `{}`", p.display())
             }
+            InvalidStatsFile(p, e) => {
+                write!(f, "error reading stats file `{}`: {}", p.display(), e)
+            }
+            InvalidStatsSink(a, e) => write!(f, "invalid stats sink address `{}`: {}", a, e),
             InvalidUrl(u) => write!(f, "invalid url `{}`", u),
             Recoverable(r) => write!(f, "recoverable error: {}", r),
             RequestBuilderErr(e) => write!(f, "error creating request: {}", e),
@@ -87,9 +118,12 @@ impl StdError for TestError {
         match self {
             CannotCreateLoggerFile(_, e) => Some(&**e),
             CannotCreateStatsFile(_, e) => Some(&**e),
+            CannotStartMetricsServer(_, e) => Some(&**e),
             CannotOpenFile(_, e) => Some(&**e),
             Config(e) => Some(e),
             FileReading(_, e) => Some(&**e),
+            InvalidStatsFile(_, e) => Some(&**e),
+            InvalidStatsSink(_, e) => Some(&**e),
             Recoverable(BodyErr(e)) => Some(&**e),
             Recoverable(ConnectionErr(_, e)) => Some(&**e),
             RequestBuilderErr(e) => Some(&**e),
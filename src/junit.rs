@@ -0,0 +1,154 @@
+use crate::error::TestError;
+use crate::util::xml_escape;
+
+use futures::channel::oneshot;
+
+use std::{fmt::Write, path::Path, time::Duration};
+
+// how the stats send their test cases for the report once the test has ended. The test cases are
+// given `class_name`
+pub(crate) struct TestCasesSender {
+    pub(crate) class_name: String,
+    pub(crate) tx: oneshot::Sender<Vec<TestCase>>,
+}
+
+// a test case in a JUnit report. Bucket groups and thresholds each become a test case
+pub(crate) struct TestCase {
+    pub(crate) class_name: String,
+    pub(crate) error: Option<String>,
+    pub(crate) failures: Vec<Failure>,
+    pub(crate) name: String,
+    pub(crate) system_out: String,
+}
+
+impl TestCase {
+    pub(crate) fn new(class_name: String, name: String) -> Self {
+        TestCase {
+            class_name,
+            error: None,
+            failures: Vec::new(),
+            name,
+            system_out: String::new(),
+        }
+    }
+}
+
+// why a test case failed. `kind` is what failed, such as a `check` or `threshold`
+pub(crate) struct Failure {
+    pub(crate) details: String,
+    pub(crate) kind: &'static str,
+    pub(crate) message: String,
+}
+
+// create a JUnit XML report with a single test suite
+pub(crate) fn create_report(suite_name: &str, time: Duration, test_cases: &[TestCase]) -> String {
+    let failures = test_cases
+        .iter()
+        .filter(|t| t.error.is_none() && !t.failures.is_empty())
+        .count();
+    let errors = test_cases.iter().filter(|t| t.error.is_some()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites>\n  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">",
+        xml_escape(suite_name),
+        test_cases.len(),
+        failures,
+        errors,
+        time.as_secs_f64()
+    );
+    for test_case in test_cases {
+        let _ = write!(
+            xml,
+            "    <testcase classname=\"{}\" name=\"{}\"",
+            xml_escape(&test_case.class_name),
+            xml_escape(&test_case.name)
+        );
+        if test_case.error.is_none()
+            && test_case.failures.is_empty()
+            && test_case.system_out.is_empty()
+        {
+            xml.push_str("/>\n");
+            continue;
+        }
+        xml.push_str(">\n");
+        if let Some(error) = &test_case.error {
+            let _ = writeln!(
+                xml,
+                "      <error message=\"{}\" type=\"fatal\">{}</error>",
+                xml_escape(error),
+                xml_escape(error)
+            );
+        }
+        for failure in &test_case.failures {
+            let _ = writeln!(
+                xml,
+                "      <failure message=\"{}\" type=\"{}\">{}</failure>",
+                xml_escape(&failure.message),
+                failure.kind,
+                xml_escape(&failure.details)
+            );
+        }
+        if !test_case.system_out.is_empty() {
+            let _ = writeln!(
+                xml,
+                "      <system-out>{}</system-out>",
+                xml_escape(&test_case.system_out)
+            );
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+pub(crate) fn write_report(
+    path: &Path,
+    suite_name: &str,
+    time: Duration,
+    test_cases: &[TestCase],
+) -> Result<(), TestError> {
+    let xml = create_report(suite_name, time, test_cases);
+    std::fs::write(path, xml)
+        .map_err(|e| TestError::WritingToFile(path.to_string_lossy().into_owned(), e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_junit_report() {
+        let mut failed = TestCase::new("test".into(), "GET http://localhost/ (_id=0)".into());
+        failed.failures.push(Failure {
+            details: "2 of 10 calls failed".into(),
+            kind: "check",
+            message: "check `status < 400` failed".into(),
+        });
+        failed.system_out = "calls made: 10".into();
+        let mut error = TestCase::new("test".into(), "test run".into());
+        error.error = Some("invalid config".into());
+        let test_cases = vec![
+            TestCase::new("test".into(), "POST http://localhost/".into()),
+            failed,
+            error,
+        ];
+        let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <testsuites>\n  \
+            <testsuite name=\"test\" tests=\"3\" failures=\"1\" errors=\"1\" time=\"1.500\">\n    \
+            <testcase classname=\"test\" name=\"POST http://localhost/\"/>\n    \
+            <testcase classname=\"test\" name=\"GET http://localhost/ (_id=0)\">\n      \
+            <failure message=\"check `status &lt; 400` failed\" type=\"check\">2 of 10 calls failed</failure>\n      \
+            <system-out>calls made: 10</system-out>\n    \
+            </testcase>\n    \
+            <testcase classname=\"test\" name=\"test run\">\n      \
+            <error message=\"invalid config\" type=\"fatal\">invalid config</error>\n    \
+            </testcase>\n  \
+            </testsuite>\n\
+            </testsuites>\n";
+        assert_eq!(
+            create_report("test", Duration::from_millis(1_500), &test_cases),
+            expected
+        );
+    }
+}
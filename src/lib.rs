@@ -3,7 +3,9 @@
 #![allow(clippy::type_complexity)]
 
 mod error;
+mod junit;
 mod line_writer;
+mod open_metrics;
 mod providers;
 mod request;
 mod stats;
@@ -12,11 +14,16 @@ mod util;
 use crate::error::TestError;
 use crate::stats::{create_stats_channel, create_try_run_stats_channel, StatsMessage};
 
+pub use crate::stats::{compare_stats_files, summarize_stats_files, write_html_report};
+
 use ether::Either;
 use futures::{
-    channel::mpsc::{
-        Sender as FCSender, UnboundedReceiver as FCUnboundedReceiver,
-        UnboundedSender as FCUnboundedSender,
+    channel::{
+        mpsc::{
+            Sender as FCSender, UnboundedReceiver as FCUnboundedReceiver,
+            UnboundedSender as FCUnboundedSender,
+        },
+        oneshot,
     },
     executor::{block_on, block_on_stream},
     future::{self, try_join_all},
@@ -27,7 +34,8 @@ use futures_timer::Delay;
 use hyper::{client::HttpConnector, Body, Client};
 use hyper_tls::HttpsConnector;
 use itertools::Itertools;
-use line_writer::{blocking_writer, MsgType};
+use junit::{TestCase, TestCasesSender};
+use line_writer::{blocking_writer, blocking_writer_with_finish, HeldWriter, MsgType};
 use mod_interval::{ModInterval, PerX};
 use native_tls::TlsConnector;
 use serde_json as json;
@@ -44,9 +52,13 @@ use std::{
     future::Future,
     io::{Error as IOError, ErrorKind as IOErrorKind, Read, Seek, SeekFrom, Write},
     mem,
+    net::SocketAddr,
     path::{Path, PathBuf},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::Poll,
     time::{Duration, Instant},
 };
@@ -174,11 +186,52 @@ impl TryFrom<&str> for RunOutputFormat {
 
 #[derive(Clone, Debug)]
 pub enum StatsFileFormat {
-    // Html,
+    Html,
     Json,
     // None,
 }
 
+impl TryFrom<&str> for StatsFileFormat {
+    type Error = ();
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "html" => Ok(StatsFileFormat::Html),
+            "json" => Ok(StatsFileFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+// how the stats file is compressed
+#[derive(Clone, Copy, Debug)]
+pub enum StatsFileCompression {
+    Gzip,
+    Zstd,
+}
+
+impl StatsFileCompression {
+    // the extension added to the name of a compressed stats file
+    pub fn extension(self) -> &'static str {
+        match self {
+            StatsFileCompression::Gzip => "gz",
+            StatsFileCompression::Zstd => "zst",
+        }
+    }
+}
+
+impl TryFrom<&str> for StatsFileCompression {
+    type Error = ();
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "gzip" => Ok(StatsFileCompression::Gzip),
+            "zstd" => Ok(StatsFileCompression::Zstd),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum TryRunFormat {
     Human,
@@ -206,11 +259,20 @@ impl TryFrom<&str> for TryRunFormat {
 #[derive(Clone, Debug)]
 pub struct RunConfig {
     pub config_file: PathBuf,
+    pub junit: Option<PathBuf>,
+    pub metrics_listen: Option<SocketAddr>,
     pub output_format: RunOutputFormat,
     pub results_dir: Option<PathBuf>,
     pub start_at: Option<Duration>,
     pub stats_file: PathBuf,
+    pub stats_file_compression: Option<StatsFileCompression>,
     pub stats_file_format: StatsFileFormat,
+    // start a new stats file once the current one has this many bytes (before compression) or
+    // has been written to for this long
+    pub stats_file_rotate_interval: Option<Duration>,
+    pub stats_file_rotate_size: Option<u64>,
+    // show a live dashboard in place of the periodic stats
+    pub tui: bool,
     pub watch_config_file: bool,
 }
 
@@ -220,16 +282,123 @@ pub enum TryFilter {
     Ne(String, String),
 }
 
+// create a function which checks whether tags match any of the filters. With no filters all tags
+// match
+pub(crate) fn create_tag_filter(
+    filters: Vec<TryFilter>,
+) -> impl Fn(&BTreeMap<String, String>) -> bool {
+    let filters: Vec<_> = filters
+        .into_iter()
+        .map(|try_filter| {
+            let (is_eq, key, right) = match try_filter {
+                TryFilter::Eq(key, right) => (true, key, right),
+                TryFilter::Ne(key, right) => (false, key, right),
+            };
+            let right = right.split('*').map(regex::escape).join(".*?");
+            let right = format!("^{}$", right);
+            (
+                is_eq,
+                key,
+                regex::Regex::new(&right).expect("filter should be a valid regex"),
+            )
+        })
+        .collect();
+    move |tags: &BTreeMap<String, String>| -> bool {
+        filters.is_empty()
+            || filters.iter().any(|(is_eq, key, regex)| {
+                let check = tags
+                    .get(key)
+                    .map(|left| regex.is_match(left))
+                    .unwrap_or(false);
+                if *is_eq {
+                    check
+                } else {
+                    !check
+                }
+            })
+    }
+}
+
 #[derive(Clone)]
 pub struct TryConfig {
     pub config_file: PathBuf,
     pub file: Option<String>,
     pub filters: Option<Vec<TryFilter>>,
     pub format: TryRunFormat,
+    pub junit: Option<PathBuf>,
     pub loggers_on: bool,
     pub results_dir: Option<PathBuf>,
 }
 
+#[derive(Clone, Copy, Debug)]
+pub enum StatsSummaryFormat {
+    Csv,
+    Human,
+    Json,
+}
+
+impl TryFrom<&str> for StatsSummaryFormat {
+    type Error = ();
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "csv" => Ok(StatsSummaryFormat::Csv),
+            "human" => Ok(StatsSummaryFormat::Human),
+            "json" => Ok(StatsSummaryFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+// the options for the `stats` subcommand. `from` and `to` are relative to the start of the stats
+#[derive(Clone)]
+pub struct StatsConfig {
+    pub files: Vec<PathBuf>,
+    pub filters: Vec<TryFilter>,
+    pub format: StatsSummaryFormat,
+    pub from: Option<Duration>,
+    pub to: Option<Duration>,
+}
+
+// the options for the `report` subcommand
+#[derive(Clone)]
+pub struct ReportConfig {
+    pub config_file: Option<PathBuf>,
+    pub files: Vec<PathBuf>,
+    pub output: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum CompareFormat {
+    Human,
+    Json,
+    Markdown,
+}
+
+impl TryFrom<&str> for CompareFormat {
+    type Error = ();
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "human" => Ok(CompareFormat::Human),
+            "json" => Ok(CompareFormat::Json),
+            "markdown" => Ok(CompareFormat::Markdown),
+            _ => Err(()),
+        }
+    }
+}
+
+// the options for the `compare` subcommand. `tolerances` overrides the default tolerance of a
+// metric by its name, such as `p95` or `error_rate`
+#[derive(Clone)]
+pub struct CompareConfig {
+    pub baseline: PathBuf,
+    pub candidate: PathBuf,
+    pub filters: Vec<TryFilter>,
+    pub format: CompareFormat,
+    pub tolerances: BTreeMap<String, f64>,
+}
+
 pub enum ExecConfig {
     Run(RunConfig),
     Try(TryConfig),
@@ -243,6 +412,13 @@ impl ExecConfig {
         }
     }
 
+    fn get_junit_file(&self) -> Option<&PathBuf> {
+        match self {
+            ExecConfig::Run(r) => r.junit.as_ref(),
+            ExecConfig::Try(t) => t.junit.as_ref(),
+        }
+    }
+
     fn get_output_format(&self) -> RunOutputFormat {
         match self {
             ExecConfig::Run(r) => r.output_format,
@@ -257,9 +433,31 @@ pub enum TestEndReason {
     CtrlC,
     KilledByLogger,
     ProviderEnded,
-    ConfigUpdate(Arc<BTreeMap<String, providers::Provider>>),
+    ThresholdFailed,
+    ConfigUpdate(
+        Arc<BTreeMap<String, providers::Provider>>,
+        Arc<Vec<stats::TargetRate>>,
+    ),
+}
+
+// why a run did not succeed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunError {
+    Fatal,
+    ThresholdFailed,
+}
+
+impl RunError {
+    // the exit code for the process, distinct for each kind of failure
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunError::Fatal => 1,
+            RunError::ThresholdFailed => 2,
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn _create_run(
     exec_config: ExecConfig,
     mut ctrlc_channel: FCUnboundedReceiver<()>,
@@ -267,6 +465,8 @@ async fn _create_run(
     stderr: FCSender<MsgType>,
     test_ended_tx: broadcast::Sender<Result<TestEndReason, TestError>>,
     mut test_ended_rx: BroadcastStream<Result<TestEndReason, TestError>>,
+    thresholds_failed: Arc<AtomicBool>,
+    junit: Option<TestCasesSender>,
 ) -> Result<TestEndReason, TestError> {
     let config_file = exec_config.get_config_file().clone();
     let config_file2 = config_file.clone();
@@ -306,9 +506,21 @@ async fn _create_run(
     let config_file_path = exec_config.get_config_file().clone();
     let mut config =
         config::LoadTest::from_config(&config_bytes, exec_config.get_config_file(), &env_vars)?;
+    // things in the config which are allowed but are likely a mistake
+    for warning in config.warnings() {
+        let msg = match output_format {
+            RunOutputFormat::Human => format!("{} {}\n", Paint::yellow("Warning:"), warning),
+            RunOutputFormat::Json => {
+                let json = json::json!({"type": "warn", "msg": warning});
+                format!("{}\n", json)
+            }
+        };
+        let _ = stderr.clone().send(MsgType::Other(msg)).await;
+    }
     let test_runner = match exec_config {
         ExecConfig::Try(t) => {
-            create_try_run_future(config, t, test_ended_tx.clone(), stdout, stderr).map(Either::A)
+            create_try_run_future(config, t, test_ended_tx.clone(), stdout, stderr, junit)
+                .map(Either::A)
         }
         ExecConfig::Run(r) => {
             let config_providers = mem::take(&mut config.providers);
@@ -322,12 +534,19 @@ async fn _create_run(
 
             let stats_tx = create_stats_channel(
                 test_ended_tx.clone(),
-                &config.config.general,
+                &config,
+                thresholds_failed,
                 &providers,
                 stdout.clone(),
                 &r,
+                junit,
             )?;
 
+            if let Some(addr) = r.metrics_listen {
+                let server = open_metrics::serve(addr, stats_tx.clone(), &test_ended_tx)?;
+                tokio::spawn(server);
+            }
+
             let providers = Arc::new(providers);
 
             if r.watch_config_file {
@@ -364,7 +583,7 @@ async fn _create_run(
             let mut test_result = Ok(TestEndReason::Completed);
             while let Some(v) = test_ended_rx.next().await {
                 match v {
-                    Ok(Ok(TestEndReason::ConfigUpdate(_))) => continue,
+                    Ok(Ok(TestEndReason::ConfigUpdate(..))) => continue,
                     Ok(v) => {
                         test_result = v;
                     }
@@ -383,7 +602,7 @@ pub async fn create_run<So, Se>(
     ctrlc_channel: FCUnboundedReceiver<()>,
     stdout: So,
     stderr: Se,
-) -> Result<(), ()>
+) -> Result<(), RunError>
 where
     So: Write + Send + 'static,
     Se: Write + Send + 'static,
@@ -391,8 +610,44 @@ where
     let (test_ended_tx, test_ended_rx) = broadcast::channel(1);
     let test_ended_rx = BroadcastStream::new(test_ended_rx);
     let output_format = exec_config.get_output_format();
+    let tui = matches!(&exec_config, ExecConfig::Run(r) if r.tui);
     let (stdout, stdout_done) = blocking_writer(stdout, test_ended_tx.clone(), "stdout".into());
-    let (mut stderr, stderr_done) = blocking_writer(stderr, test_ended_tx.clone(), "stderr".into());
+    // the dashboard takes over the terminal, so anything for stderr is written once it's gone
+    let (mut stderr, stderr_done) = if tui {
+        blocking_writer_with_finish(
+            HeldWriter::new(stderr),
+            HeldWriter::release,
+            test_ended_tx.clone(),
+            "stderr".into(),
+        )
+    } else {
+        blocking_writer(stderr, test_ended_tx.clone(), "stderr".into())
+    };
+    let thresholds_failed = Arc::new(AtomicBool::new(false));
+    let start_time = Instant::now();
+    let test_name = exec_config
+        .get_config_file()
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (junit_tx, junit) = match exec_config.get_junit_file() {
+        Some(path) => {
+            let (tx, rx) = oneshot::channel();
+            let sender = TestCasesSender {
+                class_name: test_name.clone(),
+                tx,
+            };
+            (Some(sender), Some((path.clone(), rx)))
+        }
+        None => (None, None),
+    };
+    let fatal_msg = |e: &TestError| match output_format {
+        RunOutputFormat::Human => format!("\n{} {}\n", Paint::red("Fatal error").bold(), e),
+        RunOutputFormat::Json => {
+            let json = json::json!({"type": "fatal", "msg": format!("{}", e)});
+            format!("{}\n", json)
+        }
+    };
     let test_result = _create_run(
         exec_config,
         ctrlc_channel,
@@ -400,6 +655,8 @@ where
         stderr.clone(),
         test_ended_tx.clone(),
         test_ended_rx,
+        thresholds_failed.clone(),
+        junit_tx,
     )
     .await;
 
@@ -407,15 +664,17 @@ where
         Err(e) => {
             // send the test end message to ensure the stats channel closes
             let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
-            let msg = match output_format {
-                RunOutputFormat::Human => format!("\n{} {}\n", Paint::red("Fatal error").bold(), e),
-                RunOutputFormat::Json => {
-                    let json = json::json!({"type": "fatal", "msg": format!("{}", e)});
-                    format!("{}\n", json)
-                }
-            };
+            let mut msg = fatal_msg(&e);
+            if let Err(e) = write_junit_report(junit, &test_name, start_time, Some(&e)).await {
+                msg.push_str(&fatal_msg(&e));
+            }
             let _ = stderr.send(MsgType::Final(msg)).await;
-            return Err(());
+            // the stats are finalized once the test ends, and the dashboard leaves the screen,
+            // before the error is written
+            let _ = stdout_done.await;
+            drop(stderr);
+            let _ = stderr_done.await;
+            return Err(RunError::Fatal);
         }
         Ok(TestEndReason::KilledByLogger) => {
             let msg = match output_format {
@@ -455,15 +714,68 @@ where
             };
             let _ = stderr.send(MsgType::Final(msg)).await;
         }
+        Ok(TestEndReason::ThresholdFailed) => {
+            let msg = match output_format {
+                RunOutputFormat::Human => format!(
+                    "\n{}\n",
+                    Paint::red("Test killed early because a threshold failed").bold()
+                ),
+                RunOutputFormat::Json => {
+                    "{\"type\":\"end\",\"msg\":\"Test killed early because a threshold failed\"}\n"
+                        .to_string()
+                }
+            };
+            let _ = stderr.send(MsgType::Final(msg)).await;
+        }
         _ => (),
     };
-    drop(stderr);
-    // wait for all stderr and stdout output to be written
-    let _ = stderr_done.await;
-    let _ = stdout_done.await;
+    let junit_result = write_junit_report(junit, &test_name, start_time, None).await;
+    if let Err(e) = &junit_result {
+        let _ = stderr.send(MsgType::Other(fatal_msg(e))).await;
+    }
+    // wait for all stderr and stdout output to be written. With the dashboard stdout goes first, so
+    // the dashboard has left the screen before anything held for stderr is written
+    if tui {
+        let _ = stdout_done.await;
+        drop(stderr);
+        let _ = stderr_done.await;
+    } else {
+        drop(stderr);
+        let _ = stderr_done.await;
+        let _ = stdout_done.await;
+    }
+    if junit_result.is_err() {
+        return Err(RunError::Fatal);
+    }
+    // the thresholds are checked as the stats are finalized, which is done once stdout is written
+    if thresholds_failed.load(Ordering::Acquire) {
+        return Err(RunError::ThresholdFailed);
+    }
     Ok(())
 }
 
+// wait for the stats to send their test cases and write them to the JUnit report, along with the
+// fatal error which ended the test, if any
+async fn write_junit_report(
+    junit: Option<(PathBuf, oneshot::Receiver<Vec<TestCase>>)>,
+    test_name: &str,
+    start_time: Instant,
+    error: Option<&TestError>,
+) -> Result<(), TestError> {
+    let (path, rx) = match junit {
+        Some(junit) => junit,
+        None => return Ok(()),
+    };
+    // the stats may never have been created, such as when the config file is invalid
+    let mut test_cases = rx.await.unwrap_or_default();
+    if let Some(e) = error {
+        let mut test_case = TestCase::new(test_name.into(), "test run".into());
+        test_case.error = Some(e.to_string());
+        test_cases.push(test_case);
+    }
+    junit::write_report(&path, test_name, start_time.elapsed(), &test_cases)
+}
+
 #[allow(clippy::too_many_arguments)]
 fn create_config_watcher(
     mut file: File,
@@ -485,7 +797,7 @@ fn create_config_watcher(
     let stream = stream::poll_fn(move |cx| match interval.poll_next_unpin(cx) {
         Poll::Ready(_) => Poll::Ready(Some(())),
         Poll::Pending => match test_end_rx.poll_next_unpin(cx) {
-            Poll::Ready(Some(Ok(Ok(TestEndReason::ConfigUpdate(_))))) | Poll::Pending => {
+            Poll::Ready(Some(Ok(Ok(TestEndReason::ConfigUpdate(..))))) | Poll::Pending => {
                 Poll::Pending
             }
             Poll::Ready(_) => Poll::Ready(None),
@@ -589,7 +901,10 @@ fn create_config_watcher(
             run_config.start_at = Some(Instant::now() - start_time);
 
             if test_ended_tx
-                .send(Ok(TestEndReason::ConfigUpdate(providers.clone())))
+                .send(Ok(TestEndReason::ConfigUpdate(
+                    providers.clone(),
+                    Arc::new(stats::target_rates(&config)),
+                )))
                 .is_err()
             {
                 break;
@@ -634,6 +949,7 @@ fn create_try_run_future(
     test_ended_tx: broadcast::Sender<Result<TestEndReason, TestError>>,
     stdout: FCSender<MsgType>,
     stderr: FCSender<MsgType>,
+    junit: Option<TestCasesSender>,
 ) -> Result<impl Future<Output = ()>, TestError> {
     // create a logger for the try run
     let select = if let TryRunFormat::Human = try_config.format {
@@ -683,38 +999,7 @@ fn create_try_run_future(
     )?;
 
     // setup "filters" which decide which endpoints are included in this try run
-    let filters: Vec<_> = try_config
-        .filters
-        .unwrap_or_default()
-        .into_iter()
-        .map(|try_filter| {
-            let (is_eq, key, right) = match try_filter {
-                TryFilter::Eq(key, right) => (true, key, right),
-                TryFilter::Ne(key, right) => (false, key, right),
-            };
-            let right = right.split('*').map(regex::escape).join(".*?");
-            let right = format!("^{}$", right);
-            (
-                is_eq,
-                key,
-                regex::Regex::new(&right).expect("filter should be a valid regex"),
-            )
-        })
-        .collect();
-    let filter_fn = move |tags: &BTreeMap<String, String>| -> bool {
-        filters.is_empty()
-            || filters.iter().any(|(is_eq, key, regex)| {
-                let check = tags
-                    .get(key)
-                    .map(|left| regex.is_match(left))
-                    .unwrap_or(false);
-                if *is_eq {
-                    check
-                } else {
-                    !check
-                }
-            })
-    };
+    let filter_fn = create_tag_filter(try_config.filters.unwrap_or_default());
 
     // create the loggers
     let loggers = get_loggers_from_config(
@@ -768,7 +1053,12 @@ fn create_try_run_future(
 
     // create the stats channel
     let test_complete = BroadcastStream::new(test_ended_tx.subscribe());
-    let stats_tx = create_try_run_stats_channel(test_complete, stderr);
+    let stats_tx = create_try_run_stats_channel(
+        test_complete,
+        stderr,
+        junit,
+        config_config.general.percentiles.clone(),
+    );
 
     let mut builder_ctx = request::BuilderContext {
         config: config_config,
@@ -813,6 +1103,20 @@ fn create_load_test_future(
 
     let config_config = config.config;
 
+    // the dashboard redraws stdout and holds stderr until the test ends
+    if run_config.tui {
+        let console_logger = config
+            .loggers
+            .iter()
+            .find(|(_, l)| l.to == "stdout" || l.to == "stderr");
+        if let Some((name, logger)) = console_logger {
+            return Err(TestError::ConsoleLoggerWithTui(
+                name.clone(),
+                logger.to.clone(),
+            ));
+        }
+    }
+
     // create the loggers
     let loggers = get_loggers_from_config(
         config.loggers,
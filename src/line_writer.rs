@@ -1,18 +1,24 @@
+use flate2::{write::GzEncoder, Compression};
 use futures::{
     channel::{mpsc, oneshot},
     executor::block_on_stream,
 };
 use tokio::{sync::broadcast, task::spawn_blocking};
 
-use crate::{TestEndReason, TestError};
+use crate::{StatsFileCompression, TestEndReason, TestError};
 
-use std::io::Write;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
 
-// The `Sender` returned from `blocking_writer` accepts two types of messages `Final` and `Other`
+// The `Sender` returned from `blocking_writer` accepts three types of messages `Final`, `Other` and `Flush`
 // `Other` messages are written out to the writer as soon as they are received
 // `Final` are written after the internal `futures::mpsc::Receiver` closes
+// `Flush` flushes what has been written so far, such as once a bucket of stats is written
 pub enum MsgType {
     Final(String),
+    Flush,
     Other(String),
 }
 
@@ -21,10 +27,25 @@ pub enum MsgType {
 // The `Sender` is used to send messages into the writer.
 // The `Receiver` is used to signal when this writer has finished
 pub fn blocking_writer<W: Write + Send + 'static>(
-    mut writer: W,
+    writer: W,
     test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
     file_name: String,
 ) -> (mpsc::Sender<MsgType>, oneshot::Receiver<()>) {
+    blocking_writer_with_finish(writer, |_| Ok(()), test_killer, file_name)
+}
+
+// The same as `blocking_writer` except `finish` is called with the `Writer` once everything has been
+// written to it, such as to finish compressing a file
+pub fn blocking_writer_with_finish<W, F>(
+    mut writer: W,
+    finish: F,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    file_name: String,
+) -> (mpsc::Sender<MsgType>, oneshot::Receiver<()>)
+where
+    W: Write + Send + 'static,
+    F: FnOnce(W) -> io::Result<()> + Send + 'static,
+{
     // create the needed channels
     let (tx, rx) = mpsc::channel(5);
     let (done_tx, done_rx) = oneshot::channel();
@@ -37,6 +58,13 @@ pub fn blocking_writer<W: Write + Send + 'static>(
         for msg in block_on_stream(rx) {
             match msg {
                 MsgType::Final(s) => final_msg = Some(s),
+                MsgType::Flush => {
+                    if let Err(e) = writer.flush() {
+                        let _ =
+                            test_killer.send(Err(TestError::WritingToFile(file_name, e.into())));
+                        return;
+                    }
+                }
                 MsgType::Other(s) => {
                     // write message to the `Writer`
                     if let Err(e) = writer.write_all(s.as_bytes()) {
@@ -50,10 +78,107 @@ pub fn blocking_writer<W: Write + Send + 'static>(
         if let Some(s) = final_msg {
             // if there's a final message write that to the `Writer`
             if let Err(e) = writer.write_all(s.as_bytes()) {
-                let _ = test_killer.send(Err(TestError::WritingToFile(file_name, e.into())));
+                let _ =
+                    test_killer.send(Err(TestError::WritingToFile(file_name.clone(), e.into())));
             }
         }
+        if let Err(e) = finish(writer) {
+            let _ = test_killer.send(Err(TestError::WritingToFile(file_name, e.into())));
+        }
         let _ = done_tx.send(());
     });
     (tx, done_rx)
 }
+
+// A writer which holds on to everything written to it until it is released, such as to keep
+// messages from being drawn over by the dashboard
+pub struct HeldWriter<W> {
+    held: Vec<u8>,
+    writer: W,
+}
+
+impl<W: Write> HeldWriter<W> {
+    pub fn new(writer: W) -> Self {
+        HeldWriter {
+            held: Vec::new(),
+            writer,
+        }
+    }
+
+    // write out everything which was held
+    pub fn release(mut self) -> io::Result<()> {
+        self.writer.write_all(&self.held)?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Write for HeldWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.held.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A file which is written to through `blocking_writer_with_finish`, compressed if need be. A
+// compressed file is only flushed when asked to, such as at the end of each bucket of stats, so a
+// stats file can be read up to its last bucket while the test is still running. A flush doesn't
+// reset the compression, so the file compresses as well as if it had been written all at once
+pub enum FileWriter {
+    Plain(File),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl FileWriter {
+    pub fn new(file: File, compression: Option<StatsFileCompression>) -> io::Result<Self> {
+        let writer = match compression {
+            None => FileWriter::Plain(file),
+            Some(StatsFileCompression::Gzip) => {
+                FileWriter::Gzip(GzEncoder::new(BufWriter::new(file), Compression::default()))
+            }
+            Some(StatsFileCompression::Zstd) => {
+                // 0 is zstd's default compression level
+                FileWriter::Zstd(zstd::Encoder::new(BufWriter::new(file), 0)?)
+            }
+        };
+        Ok(writer)
+    }
+
+    // finish any compression and flush everything out to the file
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            FileWriter::Plain(mut file) => return file.flush(),
+            FileWriter::Gzip(encoder) => encoder.finish()?,
+            FileWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileWriter::Plain(file) => file.write(buf),
+            FileWriter::Gzip(encoder) => encoder.write(buf),
+            FileWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    // a gzip or zstd flush ends the current compressed block, without ending the stream, and
+    // writes it out to the file
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::Plain(file) => file.flush(),
+            FileWriter::Gzip(encoder) => encoder.flush(),
+            FileWriter::Zstd(encoder) => {
+                // the zstd encoder doesn't flush the writer it wraps
+                encoder.flush()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+}
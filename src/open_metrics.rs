@@ -0,0 +1,182 @@
+use crate::error::TestError;
+use crate::stats::StatsMessage;
+use crate::TestEndReason;
+
+use futures::{
+    channel::{mpsc::UnboundedSender, oneshot},
+    FutureExt, StreamExt,
+};
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+use std::{
+    collections::BTreeSet, convert::Infallible, fmt, fmt::Write, future::Future, net::SocketAddr,
+};
+
+const OPEN_METRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// builds up a body in the OpenMetrics text format. All the samples for a metric family must be
+// added right after the family
+#[derive(Default)]
+pub(crate) struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    // start a new metric family. `kind` is the OpenMetrics type, such as `counter` or `gauge`
+    pub(crate) fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = write!(
+            self.out,
+            "# TYPE {} {}\n# HELP {} {}\n",
+            name,
+            kind,
+            name,
+            escape(help)
+        );
+    }
+
+    pub(crate) fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl fmt::Display) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            // different tags, such as `my-tag` and `my_tag`, can end up with the same label name
+            let mut names = BTreeSet::new();
+            let labels = labels
+                .iter()
+                .map(|(k, v)| {
+                    let name = label_name(k);
+                    let mut unique = name.clone();
+                    let mut suffix = 1;
+                    while !names.insert(unique.clone()) {
+                        unique = format!("{}_{}", name, suffix);
+                        suffix += 1;
+                    }
+                    format!("{}=\"{}\"", unique, escape(v))
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = write!(self.out, "{{{}}}", labels);
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+
+    pub(crate) fn finish(mut self) -> String {
+        self.out.push_str("# EOF\n");
+        self.out
+    }
+}
+
+// tags can have any name, but label names can only be made up of letters, numbers and underscores
+// and can't start with a number
+fn label_name(name: &str) -> String {
+    let mut label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if label.starts_with(|c: char| c.is_ascii_digit()) || label.is_empty() {
+        label.insert(0, '_');
+    }
+    label
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// serve the stats of a running test at `/metrics` in the OpenMetrics format. The server stops
+// when the test ends
+pub(crate) fn serve(
+    addr: SocketAddr,
+    stats_tx: UnboundedSender<StatsMessage>,
+    test_killer: &broadcast::Sender<Result<TestEndReason, TestError>>,
+) -> Result<impl Future<Output = ()>, TestError> {
+    let builder =
+        Server::try_bind(&addr).map_err(|e| TestError::CannotStartMetricsServer(addr, e.into()))?;
+    let make_service = make_service_fn(move |_| {
+        let stats_tx = stats_tx.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(request, stats_tx.clone())
+            }))
+        }
+    });
+    let mut test_ended = BroadcastStream::new(test_killer.subscribe());
+    let shutdown = async move {
+        while let Some(Ok(Ok(TestEndReason::ConfigUpdate(..))) | Err(_)) = test_ended.next().await {
+        }
+    };
+    let server = builder
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .map(|_| ());
+    Ok(server)
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    stats_tx: UnboundedSender<StatsMessage>,
+) -> Result<Response<Body>, Infallible> {
+    let mut response = Response::new(Body::empty());
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+    let (tx, rx) = oneshot::channel();
+    let _ = stats_tx.unbounded_send(StatsMessage::Metrics(tx));
+    match rx.await {
+        Ok(body) => {
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                OPEN_METRICS_CONTENT_TYPE
+                    .parse()
+                    .expect("should be a valid header value"),
+            );
+            *response.body_mut() = body.into();
+        }
+        // the stats are no longer being collected because the test has ended
+        Err(_) => *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE,
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_open_metrics() {
+        let mut writer = MetricsWriter::default();
+        writer.family("pewpew_calls", "counter", "Calls made");
+        writer.sample("pewpew_calls_total", &[], 3);
+        writer.sample(
+            "pewpew_calls_total",
+            &[("_id", "0"), ("my-tag", "a \"b\"\n"), ("1x", "c\\")],
+            1.5,
+        );
+        // labels which are the same once sanitized get a suffix
+        writer.sample(
+            "pewpew_calls_total",
+            &[
+                ("my-tag", "a"),
+                ("my.tag", "b"),
+                ("my_tag", "c"),
+                ("my_tag_1", "d"),
+            ],
+            1,
+        );
+        let expected = "# TYPE pewpew_calls counter\n\
+                        # HELP pewpew_calls Calls made\n\
+                        pewpew_calls_total 3\n\
+                        pewpew_calls_total{_id=\"0\",my_tag=\"a \\\"b\\\"\\n\",_1x=\"c\\\\\"} 1.5\n\
+                        pewpew_calls_total{my_tag=\"a\",my_tag_1=\"b\",my_tag_2=\"c\",my_tag_1_1=\"d\"} 1\n\
+                        # EOF\n";
+        assert_eq!(writer.finish(), expected);
+    }
+}
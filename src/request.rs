@@ -2,13 +2,16 @@
 mod body_handler;
 mod request_maker;
 mod response_handler;
+mod throttle;
 
 use self::body_handler::BodyHandler;
 use self::request_maker::RequestMaker;
+use self::throttle::Throttled;
 
 use request_maker::ProviderDelays;
 
-use bytes::Bytes;
+use body_reader::{BodyWriter, Compression};
+use bytes::{Bytes, BytesMut};
 use ether::{Either, Either3, EitherExt};
 use for_each_parallel::ForEachParallel;
 use futures::{
@@ -20,10 +23,13 @@ use futures::{
 use hyper::{
     client::HttpConnector,
     header::{Entry as HeaderEntry, HeaderName, HeaderValue, CONTENT_DISPOSITION},
-    Body as HyperBody, Client, Method, Response,
+    Body as HyperBody, Client, Response,
 };
 use hyper_tls::HttpsConnector;
-use rand::distributions::{Alphanumeric, Distribution};
+use rand::{
+    distributions::{Alphanumeric, Distribution},
+    RngCore,
+};
 use select_any::select_any;
 use serde_json as json;
 use tokio::{
@@ -37,19 +43,26 @@ use crate::providers;
 use crate::stats;
 use crate::util::tweak_path;
 use config::{
-    BodyTemplate, EndpointProvidesSendOptions, MultipartBody, ProviderStream, Select, Template,
+    BodyCompression, BodyPattern, BodyTemplate, EndpointProvidesSendOptions, MethodTemplate,
+    MetricKind, MultipartBody, ProviderStream, Retry, Select, Sla, Template, Throttle,
 };
 
 use std::{
     borrow::Cow,
     collections::{BTreeMap, BTreeSet},
+    convert::Infallible,
+    error::Error as StdError,
     future::Future,
+    io,
     num::NonZeroUsize,
     ops::{Deref, DerefMut},
     path::PathBuf,
     pin::Pin,
     str,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
     time::{Duration, Instant},
 };
@@ -225,6 +238,9 @@ impl EndpointBuilder {
             method,
             headers,
             body,
+            checks,
+            compress,
+            metrics,
             no_auto_returns,
             providers_to_stream,
             url,
@@ -234,6 +250,9 @@ impl EndpointBuilder {
             on_demand,
             tags,
             request_timeout,
+            retry,
+            sla,
+            throttle,
             ..
         } = self.endpoint;
 
@@ -267,7 +286,7 @@ impl EndpointBuilder {
         if let Some(start_stream) = self.start_stream {
             streams.push((
                 true,
-                Box::new(start_stream.map(|(_, d)| Ok(StreamItem::Instant(d)))),
+                Box::new(start_stream.map(|(scheduled, d)| Ok(StreamItem::Instant(scheduled, d)))),
             ));
         } else if let Some(set) = provides_set {
             let stream = stream::poll_fn(move |_| {
@@ -329,19 +348,25 @@ impl EndpointBuilder {
         let client = ctx.client.clone();
         Endpoint {
             body,
+            checks: Arc::new(checks),
             client,
+            compress,
             headers,
             max_parallel_requests,
             method,
+            metrics: Arc::new(metrics),
             no_auto_returns,
             on_demand_streams,
             outgoing,
             precheck_rr_providers,
             provides,
+            retry,
             rr_providers,
+            sla: sla.map(Arc::new),
             tags: Arc::new(tags),
             stats_tx,
             stream_collection: streams,
+            throttle,
             url,
             timeout,
         }
@@ -349,7 +374,8 @@ impl EndpointBuilder {
 }
 
 pub enum StreamItem {
-    Instant(Option<Instant>),
+    // the tick of the load pattern this request is for, and the tick after it
+    Instant(Instant, Option<Instant>),
     Declare(String, json::Value, Vec<AutoReturn>, Instant),
     None,
     TemplateValue(String, json::Value, Option<AutoReturn>, Instant),
@@ -538,6 +564,71 @@ async fn create_file_hyper_body(filename: String) -> Result<(u64, HyperBody), Te
     Ok((bytes, body))
 }
 
+// creates a body of `size` bytes which are generated as the body is streamed out, so large
+// bodies never need to be held in memory or written to disk
+fn create_generated_hyper_body(size: u64, pattern: &BodyPattern) -> (u64, HyperBody) {
+    const CHUNK_SIZE: usize = 8192;
+    let repeated = match pattern {
+        BodyPattern::Random => None,
+        BodyPattern::Repeat(s) => Some(s.as_bytes()),
+        BodyPattern::Zeros => Some(&[0u8][..]),
+    };
+    // a chunk made up of whole repetitions of the pattern so consecutive chunks line up
+    let chunk = repeated.map(|r| Bytes::from(r.repeat((CHUNK_SIZE / r.len()).max(1))));
+    let mut remaining = size;
+    let stream = stream::poll_fn(move |_| {
+        if remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let bytes = match &chunk {
+            Some(chunk) => chunk.slice(..(chunk.len() as u64).min(remaining) as usize),
+            None => {
+                let mut buffer = vec![0; (CHUNK_SIZE as u64).min(remaining) as usize];
+                rand::thread_rng().fill_bytes(&mut buffer);
+                buffer.into()
+            }
+        };
+        remaining -= bytes.len() as u64;
+        Poll::Ready(Some(Ok::<_, Infallible>(bytes)))
+    });
+    (size, HyperBody::wrap_stream(stream))
+}
+
+// wraps a body so it is compressed as it is streamed out. Because the compressed size is not
+// known ahead of time, the body is sent without a content-length
+fn compress_hyper_body(body: HyperBody, compress: BodyCompression) -> Result<HyperBody, TestError> {
+    let compression = match compress {
+        BodyCompression::Brotli => Compression::Brotli,
+        BodyCompression::Deflate => Compression::Deflate,
+        BodyCompression::Gzip => Compression::Gzip,
+        BodyCompression::Zstd => Compression::Zstd,
+    };
+    let mut writer = BodyWriter::new(compression)
+        .map_err::<TestError, _>(|e| RecoverableError::BodyErr(Arc::new(e)).into())?;
+    let stream = body
+        .map(Some)
+        .chain(stream::once(future::ready(None)))
+        .map(move |chunk| {
+            let mut out_bytes = BytesMut::new();
+            match chunk {
+                Some(Ok(bytes)) => writer.encode(&bytes, &mut out_bytes)?,
+                Some(Err(e)) => return Err(e.into()),
+                None => writer.finish(&mut out_bytes)?,
+            }
+            Ok::<_, Box<dyn StdError + Send + Sync>>(out_bytes.freeze())
+        })
+        .try_filter(|bytes| future::ready(!bytes.is_empty()));
+    Ok(HyperBody::wrap_stream(stream))
+}
+
+// wraps a body so the number of bytes streamed out is added to `counter`
+fn count_hyper_body(body: HyperBody, counter: Arc<AtomicU64>) -> HyperBody {
+    let stream = body.inspect_ok(move |bytes| {
+        counter.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+    });
+    HyperBody::wrap_stream(stream)
+}
+
 fn body_template_as_hyper_body<'a>(
     body_template: &BodyTemplate,
     template_values: &TemplateValues,
@@ -546,7 +637,41 @@ fn body_template_as_hyper_body<'a>(
     content_type_entry: HeaderEntry<'a, HeaderValue>,
 ) -> impl Future<Output = Result<(u64, HyperBody), TestError>> {
     let template = match body_template {
+        BodyTemplate::Bytes(select) => {
+            let r = bytes_body(select, template_values).map(|body| {
+                if copy_body_value {
+                    *body_value = Some(format!("<<binary data: {} bytes>>", body.len()));
+                }
+                (body.len() as u64, body.into())
+            });
+            return Either3::B(future::ready(r));
+        }
         BodyTemplate::File(_, t) => t,
+        BodyTemplate::Form(pieces) => {
+            let r = form_body(pieces, template_values).map(|body| {
+                content_type_entry.or_insert(HeaderValue::from_static(
+                    "application/x-www-form-urlencoded",
+                ));
+                string_as_hyper_body(body, copy_body_value, body_value)
+            });
+            return Either3::B(future::ready(r));
+        }
+        BodyTemplate::Generate(size, pattern) => {
+            if copy_body_value {
+                *body_value = Some(format!("<<generated data: {} bytes>>", size));
+            }
+            return Either3::B(future::ok(create_generated_hyper_body(*size, pattern)));
+        }
+        BodyTemplate::Json(select) => {
+            let r = select
+                .evaluate(template_values.as_json())
+                .map_err(TestError::from)
+                .map(|value| {
+                    content_type_entry.or_insert(HeaderValue::from_static("application/json"));
+                    string_as_hyper_body(value.to_string(), copy_body_value, body_value)
+                });
+            return Either3::B(future::ready(r));
+        }
         BodyTemplate::Multipart(m) => {
             let r = multipart_body_as_hyper_body(
                 m,
@@ -571,11 +696,61 @@ fn body_template_as_hyper_body<'a>(
         }
         Either3::C(create_file_hyper_body(body))
     } else {
-        if copy_body_value {
-            *body_value = Some(body.clone());
+        Either3::B(future::ok(string_as_hyper_body(
+            body,
+            copy_body_value,
+            body_value,
+        )))
+    }
+}
+
+fn string_as_hyper_body(
+    body: String,
+    copy_body_value: bool,
+    body_value: &mut Option<String>,
+) -> (u64, HyperBody) {
+    if copy_body_value {
+        *body_value = Some(body.clone());
+    }
+    (body.as_bytes().len() as u64, body.into())
+}
+
+// decodes the result of a `bytes` body's select, which is either a base64 string or an array
+// of numbers between 0 and 255
+fn bytes_body(select: &Select, template_values: &TemplateValues) -> Result<Vec<u8>, TestError> {
+    let invalid = || {
+        let e = io::Error::new(
+            io::ErrorKind::InvalidData,
+            "bytes body must be a base64 string or an array of bytes",
+        );
+        RecoverableError::BodyErr(Arc::new(e)).into()
+    };
+    match select.evaluate(template_values.as_json())? {
+        json::Value::String(s) => {
+            base64::decode(&s).map_err(|e| RecoverableError::BodyErr(Arc::new(e)).into())
         }
-        Either3::B(future::ok((body.as_bytes().len() as u64, body.into())))
+        json::Value::Array(a) => a
+            .iter()
+            .map(|v| match v.as_u64() {
+                Some(n) if n <= u8::MAX as u64 => Ok(n as u8),
+                _ => Err(invalid()),
+            })
+            .collect(),
+        _ => Err(invalid()),
+    }
+}
+
+// percent-encodes each of the evaluated templates into an `application/x-www-form-urlencoded` body
+fn form_body(
+    pieces: &[(String, Template)],
+    template_values: &TemplateValues,
+) -> Result<String, TestError> {
+    let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+    for (name, t) in pieces {
+        let value = t.evaluate(Cow::Borrowed(template_values.as_json()), None)?;
+        serializer.append_pair(name, &value);
     }
+    Ok(serializer.finish())
 }
 
 type StreamCollection = Vec<(
@@ -587,19 +762,25 @@ pub type StatsTx = futures_channel::UnboundedSender<stats::StatsMessage>;
 
 pub struct Endpoint {
     body: BodyTemplate,
+    checks: Arc<Vec<(String, Select)>>,
     client: Arc<Client<HttpsConnector<HttpConnector<hyper::client::connect::dns::GaiResolver>>>>,
+    compress: Option<BodyCompression>,
     headers: Vec<(String, Template)>,
     max_parallel_requests: Option<NonZeroUsize>,
-    method: Method,
+    method: MethodTemplate,
+    metrics: Arc<Vec<(String, MetricKind, Select)>>,
     no_auto_returns: bool,
     on_demand_streams: OnDemandStreams,
     outgoing: Vec<Outgoing>,
     precheck_rr_providers: u16,
     provides: Vec<Outgoing>,
+    retry: Option<Retry>,
     rr_providers: u16,
+    sla: Option<Arc<Sla>>,
     tags: Arc<BTreeMap<String, Template>>,
     stats_tx: StatsTx,
     stream_collection: StreamCollection,
+    throttle: Option<Throttle>,
     timeout: Duration,
     url: Template,
 }
@@ -628,6 +809,9 @@ impl Endpoint {
         let method = self.method;
         let headers = self.headers;
         let body = self.body;
+        let checks = self.checks;
+        let metrics = self.metrics;
+        let compress = self.compress;
         let rr_providers = self.rr_providers;
         let client = self.client;
         let stats_tx = self.stats_tx;
@@ -661,6 +845,9 @@ impl Endpoint {
         let outgoing = Arc::new(outgoing);
         let precheck_rr_providers = self.precheck_rr_providers;
         let timeout = self.timeout;
+        let retry = self.retry;
+        let sla = self.sla;
+        let throttle = self.throttle;
         let max_parallel_requests = self.max_parallel_requests;
         let tags = self.tags;
         let blocking_outgoing: Vec<_> = outgoing
@@ -675,13 +862,19 @@ impl Endpoint {
             method,
             headers,
             body,
+            checks,
+            compress,
+            metrics,
             rr_providers,
             client,
             stats_tx,
             no_auto_returns,
             outgoing,
             precheck_rr_providers,
+            retry,
+            sla,
             tags,
+            throttle,
             timeout,
         };
         let limit_fn: Option<Box<dyn FnMut(usize) -> usize + Send + Unpin>> =
@@ -865,6 +1058,7 @@ impl<V: Iterator<Item = Result<json::Value, RecoverableError>> + Unpin> Drop for
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hyper::header::{HeaderMap, CONTENT_TYPE};
     use stream::StreamExt;
     use tokio::runtime::Runtime;
 
@@ -884,4 +1078,141 @@ mod tests {
         let file_bytes = include_bytes!("../tests/test.jpg").to_vec();
         assert_eq!(file_bytes, streamed_bytes);
     }
+
+    #[test]
+    fn generated_bodies_work() {
+        let patterns = vec![
+            (BodyPattern::Zeros, 20_000, None),
+            (BodyPattern::Random, 20_000, None),
+            (BodyPattern::Repeat("abc".into()), 20_000, Some(b"abc")),
+            (BodyPattern::Repeat("abc".into()), 5, Some(b"abc")),
+            (BodyPattern::Zeros, 0, None),
+        ];
+        let rt = Runtime::new().unwrap();
+        for (pattern, size, repeat) in patterns {
+            let (len, body) = create_generated_hyper_body(size, &pattern);
+            let bytes = rt.block_on(hyper::body::to_bytes(body)).unwrap();
+            assert_eq!(len, size);
+            assert_eq!(bytes.len() as u64, size);
+            match repeat {
+                Some(r) => assert!(bytes.iter().zip(r.iter().cycle()).all(|(a, b)| a == b)),
+                None if pattern == BodyPattern::Zeros => assert!(bytes.iter().all(|b| *b == 0)),
+                None => assert!(bytes.iter().any(|b| *b != 0)),
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_bodies_work() {
+        let compressions = vec![
+            (BodyCompression::Brotli, Compression::Brotli),
+            (BodyCompression::Deflate, Compression::Deflate),
+            (BodyCompression::Gzip, Compression::Gzip),
+            (BodyCompression::Zstd, Compression::Zstd),
+        ];
+        let rt = Runtime::new().unwrap();
+        for (compress, compression) in compressions {
+            let f = async {
+                let (_, body) = create_file_hyper_body("tests/test.jpg".to_string())
+                    .await
+                    .unwrap();
+                let body = compress_hyper_body(body, compress).unwrap();
+                let mut reader = body_reader::BodyReader::new(compression);
+                let mut decoded_bytes = BytesMut::new();
+                body.for_each(|b| {
+                    reader.decode(b.unwrap(), &mut decoded_bytes).unwrap();
+                    future::ready(())
+                })
+                .await;
+                decoded_bytes.to_vec()
+            };
+            let streamed_bytes = rt.block_on(f);
+            let file_bytes = include_bytes!("../tests/test.jpg").to_vec();
+            assert_eq!(file_bytes, streamed_bytes, "{}", compress.as_str());
+        }
+    }
+
+    #[test]
+    fn form_and_json_bodies_work() {
+        let mut template_values = TemplateValues::new();
+        template_values.insert("a".into(), json::json!({ "b": 3 }));
+        let form = BodyTemplate::Form(vec![
+            ("name".into(), Template::simple("foo bar")),
+            ("q".into(), Template::simple("a&b=c")),
+        ]);
+        let select = Select::simple(
+            json::json!({ "x": "a.b", "y": ["1", "\"z\""] }),
+            EndpointProvidesSendOptions::Block,
+            None,
+            None,
+            None,
+        );
+        let json_body = BodyTemplate::Json(select);
+        let checks = vec![
+            (
+                form,
+                "name=foo+bar&q=a%26b%3Dc",
+                "application/x-www-form-urlencoded",
+            ),
+            (json_body, r#"{"x":3,"y":[1,"z"]}"#, "application/json"),
+        ];
+        let rt = Runtime::new().unwrap();
+        for (body_template, expect, content_type) in checks {
+            let mut headers = HeaderMap::new();
+            let mut body_value = None;
+            let f = body_template_as_hyper_body(
+                &body_template,
+                &template_values,
+                true,
+                &mut body_value,
+                headers.entry(CONTENT_TYPE),
+            );
+            let (len, body) = rt.block_on(f).unwrap();
+            let bytes = rt.block_on(hyper::body::to_bytes(body)).unwrap();
+            assert_eq!(&bytes[..], expect.as_bytes());
+            assert_eq!(len, expect.len() as u64);
+            assert_eq!(body_value.as_deref(), Some(expect));
+            assert_eq!(headers.get(CONTENT_TYPE).unwrap(), content_type);
+        }
+    }
+
+    #[test]
+    fn bytes_bodies_work() {
+        let mut template_values = TemplateValues::new();
+        template_values.insert("b64".into(), "AP8Q".into());
+        template_values.insert("array".into(), json::json!([0, 255, 16]));
+        template_values.insert("bad".into(), json::json!([0, 256]));
+        let rt = Runtime::new().unwrap();
+        let body_bytes = |select: &str| {
+            let select =
+                Select::simple(select, EndpointProvidesSendOptions::Block, None, None, None);
+            let mut headers = HeaderMap::new();
+            let mut body_value = None;
+            let f = body_template_as_hyper_body(
+                &BodyTemplate::Bytes(select),
+                &template_values,
+                true,
+                &mut body_value,
+                headers.entry(CONTENT_TYPE),
+            );
+            rt.block_on(
+                f.and_then(|(_, body)| hyper::body::to_bytes(body).map_err(|_| unreachable!())),
+            )
+            .map(|b| (b.to_vec(), body_value))
+        };
+
+        for select in &["b64", "array"] {
+            let (bytes, body_value) = body_bytes(select).unwrap();
+            assert_eq!(bytes, vec![0, 255, 16], "{}", select);
+            assert_eq!(body_value.unwrap(), "<<binary data: 3 bytes>>");
+        }
+        for select in &["bad", "'not base64!'", "1"] {
+            let r = body_bytes(select);
+            assert!(
+                matches!(r, Err(TestError::Recoverable(RecoverableError::BodyErr(_)))),
+                "{}",
+                select
+            );
+        }
+    }
 }
@@ -1,7 +1,7 @@
 use crate::error::RecoverableError;
 use crate::stats;
 
-use config::{EndpointProvidesSendOptions, Template};
+use config::{EndpointProvidesSendOptions, MetricKind, Select, Sla, Template};
 use ether::EitherExt;
 use futures::{
     future::{select_all, try_join_all},
@@ -20,13 +20,20 @@ use std::{
 use super::{BlockSender, Outgoing, ProviderDelays, ProviderOrLogger, StatsTx, TemplateValues};
 
 pub(super) struct BodyHandler {
+    pub(super) checks: Arc<Vec<(String, Select)>>,
     pub(super) included_outgoing_indexes: BTreeSet<usize>,
+    pub(super) metrics: Arc<Vec<(String, MetricKind, Select)>>,
     pub(super) now: Instant,
     pub(super) outgoing: Arc<Vec<Outgoing>>,
     pub(super) provider_delays: ProviderDelays,
+    pub(super) request_bytes: stats::ByteCounts,
+    pub(super) response_bytes: stats::ByteCounts,
+    pub(super) sla: Option<Arc<Sla>>,
     pub(super) stats_tx: StatsTx,
     pub(super) status: u16,
     pub(super) tags: Arc<BTreeMap<String, Template>>,
+    // when the request was meant to be sent, for endpoints with a load pattern
+    pub(super) target_instant: Option<Instant>,
     pub(super) template_values: TemplateValues,
 }
 
@@ -44,9 +51,25 @@ impl BodyHandler {
         let stats_tx = self.stats_tx;
         let outgoing = self.outgoing.clone();
         let has_logger = outgoing.iter().any(|o| o.tx.is_logger());
-        let rtt = self.now.elapsed().as_micros() as u64;
+        let elapsed = self.now.elapsed();
+        let rtt = elapsed.as_micros() as u64;
+        // per response, unlike the SLA breaches in the stats which are checked per bucket
+        let sla_breached = self
+            .sla
+            .as_ref()
+            .map(|sla| sla.is_exceeded_by(elapsed))
+            .unwrap_or_default();
         let mut template_values = self.template_values;
-        template_values.insert("stats".into(), json::json!({ "rtt": rtt as f64 / 1000.0 }));
+        let (request_bytes, response_bytes) = (self.request_bytes, self.response_bytes);
+        template_values.insert(
+            "stats".into(),
+            json::json!({
+                "rtt": rtt as f64 / 1000.0,
+                "sla_breached": sla_breached,
+                "request_bytes": { "wire": request_bytes.wire, "decoded": request_bytes.decoded },
+                "response_bytes": { "wire": response_bytes.wire, "decoded": response_bytes.decoded },
+            }),
+        );
         let error_result = match result {
             Ok(Some(body)) => {
                 template_values
@@ -121,6 +144,33 @@ impl BodyHandler {
             let kind = stats::StatKind::RecoverableError(e);
             futures.push(send_response_stat(kind, None).a3());
         } else {
+            // a check which can't be evaluated counts as failed
+            for (name, check) in self.checks.iter() {
+                let passed = check.evaluate_bool(&template_values).unwrap_or(false);
+                let kind = stats::StatKind::Check(name.clone(), passed);
+                futures.push(send_response_stat(kind, None).a3());
+            }
+            // a metric is only recorded when its `where` passes and its value is a number or a
+            // boolean. A histogram can't record a negative value, which is a test error instead
+            for (name, kind, select) in self.metrics.iter() {
+                if !select.execute_where(&template_values).unwrap_or(false) {
+                    continue;
+                }
+                let value = match select.evaluate(&template_values) {
+                    Ok(json::Value::Number(n)) => n.as_f64(),
+                    Ok(json::Value::Bool(b)) => Some(if b { 1.0 } else { 0.0 }),
+                    _ => None,
+                };
+                let kind = match value {
+                    Some(value) if *kind == MetricKind::Histogram && value < 0.0 => {
+                        let r = RecoverableError::NegativeHistogramMetric(name.clone(), value);
+                        stats::StatKind::RecoverableError(r)
+                    }
+                    Some(value) => stats::StatKind::Metric(name.clone(), *kind, value),
+                    None => continue,
+                };
+                futures.push(send_response_stat(kind, None).a3());
+            }
             let mut blocked = Vec::new();
             for (i, o) in self.outgoing.iter().enumerate() {
                 if !self.included_outgoing_indexes.contains(&i) {
@@ -192,6 +242,15 @@ impl BodyHandler {
                 futures.push(f.b().b3());
             }
         }
+        let kind = stats::StatKind::Bytes(request_bytes, response_bytes);
+        futures.push(send_response_stat(kind, None).a3());
+        if let Some(target_instant) = self.target_instant {
+            // the round-trip time from when the request should have been sent, which includes any
+            // time it was held up waiting for providers or `max_parallel_requests`
+            let delay = self.now.saturating_duration_since(target_instant);
+            let kind = stats::StatKind::ScheduledRtt(rtt + delay.as_micros() as u64);
+            futures.push(send_response_stat(kind, None).a3());
+        }
         futures.push(send_response_stat(stats::StatKind::Response(self.status), Some(rtt)).a3());
         try_join_all(futures).map_ok(|_| ())
     }
@@ -204,7 +263,10 @@ mod tests {
     use futures::{channel::mpsc as futures_channel, executor::block_on, StreamExt};
     use maplit::{btreemap, btreeset};
 
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
 
     use config::{EndpointProvidesSendOptions::*, Select};
 
@@ -244,14 +306,26 @@ mod tests {
         let tags = Arc::new(btreemap! {"_id".into() => Template::simple("0") });
 
         let bh = BodyHandler {
+            checks: Vec::new().into(),
             now,
             provider_delays: ProviderDelays::new(),
+            request_bytes: stats::ByteCounts {
+                decoded: 10,
+                wire: 10,
+            },
+            response_bytes: stats::ByteCounts {
+                decoded: 40,
+                wire: 12,
+            },
+            sla: None,
             template_values,
             included_outgoing_indexes,
+            metrics: Vec::new().into(),
             outgoing,
             stats_tx,
             status,
             tags,
+            target_instant: Some(now - Duration::from_millis(5)),
         };
 
         let auto_return_called = Arc::new(AtomicBool::new(false));
@@ -318,6 +392,43 @@ mod tests {
         assert!(b, "not included receier is closed, {:?}", r);
 
         // check that the stats_rx received the correct stats data
+        let r = stats_rx.next().now_or_never();
+        let b = matches!(
+            &r,
+            Some(Some(stats::StatsMessage::ResponseStat(
+                stats::ResponseStat {
+                    kind: stats::StatKind::Bytes(
+                        stats::ByteCounts {
+                            decoded: 10,
+                            wire: 10
+                        },
+                        stats::ByteCounts {
+                            decoded: 40,
+                            wire: 12
+                        },
+                    ),
+                    ..
+                }
+            )))
+        );
+        assert!(b, "stats_rx should have received bytes stat. {:?}", r);
+
+        let r = stats_rx.next().now_or_never();
+        let b = matches!(
+            &r,
+            Some(Some(stats::StatsMessage::ResponseStat(
+                stats::ResponseStat {
+                    kind: stats::StatKind::ScheduledRtt(rtt),
+                    ..
+                }
+            ))) if *rtt >= 5_000
+        );
+        assert!(
+            b,
+            "stats_rx should have received scheduled rtt stat. {:?}",
+            r
+        );
+
         let r = stats_rx.next().now_or_never();
         let b = match &r {
             Some(Some(stats::StatsMessage::ResponseStat(rs))) => match rs.tags.get("_id") {
@@ -363,14 +474,20 @@ mod tests {
         let tags = Arc::new(BTreeMap::new());
 
         let bh = BodyHandler {
+            checks: Vec::new().into(),
             now,
             provider_delays: ProviderDelays::new(),
+            request_bytes: Default::default(),
+            response_bytes: Default::default(),
+            sla: None,
             template_values,
             included_outgoing_indexes,
+            metrics: Vec::new().into(),
             outgoing,
             stats_tx,
             status,
             tags,
+            target_instant: None,
         };
 
         type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
@@ -419,4 +536,165 @@ mod tests {
         };
         assert!(b, "receiver 3 is closed, {:?}", r);
     }
+
+    #[test]
+    fn evaluates_checks() {
+        let template_values = json::json!({"response": {"status": 200}}).into();
+        let checks = vec![
+            (
+                "status_ok".to_string(),
+                Select::simple("response.status == 200", Block, None, None, None),
+            ),
+            (
+                "has_id".to_string(),
+                Select::simple("response.body.id != null", Block, None, None, None),
+            ),
+            (
+                "missing".to_string(),
+                Select::simple("response.body.foo.bar", Block, None, None, None),
+            ),
+            (
+                "within_sla".to_string(),
+                Select::simple("stats.sla_breached == false", Block, None, None, None),
+            ),
+        ];
+        let (stats_tx, stats_rx) = futures_channel::unbounded();
+
+        let bh = BodyHandler {
+            checks: checks.into(),
+            now: Instant::now(),
+            provider_delays: ProviderDelays::new(),
+            request_bytes: Default::default(),
+            response_bytes: Default::default(),
+            sla: None,
+            template_values,
+            included_outgoing_indexes: BTreeSet::new(),
+            metrics: Vec::new().into(),
+            outgoing: Vec::new().into(),
+            stats_tx,
+            status: 200,
+            tags: Arc::new(BTreeMap::new()),
+            target_instant: None,
+        };
+
+        type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
+        let auto_returns: AutoReturns = None;
+
+        let r = block_on(bh.handle(Ok(Some(json::json!({"foo": "bar"}))), auto_returns));
+        assert!(r.is_ok());
+
+        let checks: Vec<_> = block_on(
+            stats_rx
+                .filter_map(|msg| async move {
+                    match msg {
+                        stats::StatsMessage::ResponseStat(stats::ResponseStat {
+                            kind: stats::StatKind::Check(name, passed),
+                            ..
+                        }) => Some((name, passed)),
+                        _ => None,
+                    }
+                })
+                .collect(),
+        );
+        let expected = vec![
+            ("status_ok".to_string(), true),
+            ("has_id".to_string(), false),
+            ("missing".to_string(), false),
+            ("within_sla".to_string(), true),
+        ];
+        assert_eq!(checks, expected);
+    }
+
+    #[test]
+    fn records_metrics() {
+        let template_values = json::json!({"response": {"status": 200}}).into();
+        let metrics = vec![
+            (
+                "items".to_string(),
+                MetricKind::Histogram,
+                Select::simple("response.body.items.length", Block, None, None, None),
+            ),
+            (
+                "ok".to_string(),
+                MetricKind::Counter,
+                Select::simple("response.status == 200", Block, None, None, None),
+            ),
+            (
+                "filtered".to_string(),
+                MetricKind::Counter,
+                Select::simple("1", Block, None, Some("response.status == 500"), None),
+            ),
+            (
+                "not_a_number".to_string(),
+                MetricKind::Gauge,
+                Select::simple("response.body.name", Block, None, None, None),
+            ),
+            (
+                "offset".to_string(),
+                MetricKind::Histogram,
+                Select::simple("response.body.offset", Block, None, None, None),
+            ),
+        ];
+        let (stats_tx, stats_rx) = futures_channel::unbounded();
+
+        let bh = BodyHandler {
+            checks: Vec::new().into(),
+            now: Instant::now(),
+            provider_delays: ProviderDelays::new(),
+            request_bytes: Default::default(),
+            response_bytes: Default::default(),
+            sla: None,
+            template_values,
+            included_outgoing_indexes: BTreeSet::new(),
+            metrics: metrics.into(),
+            outgoing: Vec::new().into(),
+            stats_tx,
+            status: 200,
+            tags: Arc::new(BTreeMap::new()),
+            target_instant: None,
+        };
+
+        type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
+        let auto_returns: AutoReturns = None;
+
+        let body = json::json!({"items": [1, 2, 3], "name": "foo", "offset": -2});
+        let r = block_on(bh.handle(Ok(Some(body)), auto_returns));
+        assert!(r.is_ok());
+
+        let kinds: Vec<_> = block_on(
+            stats_rx
+                .filter_map(|msg| async move {
+                    match msg {
+                        stats::StatsMessage::ResponseStat(stats::ResponseStat { kind, .. }) => {
+                            Some(kind)
+                        }
+                        _ => None,
+                    }
+                })
+                .collect(),
+        );
+        let metrics: Vec<_> = kinds
+            .iter()
+            .filter_map(|kind| match kind {
+                stats::StatKind::Metric(name, kind, value) => Some((name.clone(), *kind, *value)),
+                _ => None,
+            })
+            .collect();
+        let expected = vec![
+            ("items".to_string(), MetricKind::Histogram, 3.0),
+            ("ok".to_string(), MetricKind::Counter, 1.0),
+        ];
+        assert_eq!(metrics, expected);
+
+        // a negative value for a histogram is a test error
+        let errors: Vec<_> = kinds
+            .iter()
+            .filter_map(|kind| match kind {
+                stats::StatKind::RecoverableError(e) => Some(format!("{}", e)),
+                _ => None,
+            })
+            .collect();
+        let expected = vec!["histogram metric `offset` cannot record the negative value -2"];
+        assert_eq!(errors, expected);
+    }
 }
@@ -2,7 +2,8 @@ use crate::error::{RecoverableError, TestError};
 use crate::stats;
 
 use config::{
-    BodyTemplate, Template, REQUEST_BODY, REQUEST_HEADERS, REQUEST_HEADERS_ALL, REQUEST_STARTLINE,
+    BodyCompression, BodyTemplate, MethodTemplate, MetricKind, Retry, RetryOn, Select, Sla,
+    Template, Throttle, REQUEST_BODY, REQUEST_HEADERS, REQUEST_HEADERS_ALL, REQUEST_STARTLINE,
     REQUEST_URL,
 };
 use ether::EitherExt;
@@ -13,15 +14,18 @@ use futures::{
 use futures_timer::Delay;
 use hyper::{
     client::HttpConnector,
-    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, HOST},
-    Client, Method, Request,
+    header::{
+        HeaderMap, HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, HOST,
+    },
+    Body as HyperBody, Client, Request, Response,
 };
 use hyper_tls::HttpsConnector;
 use serde_json as json;
 
 use super::{
-    body_template_as_hyper_body, response_handler::ResponseHandler, AutoReturn, BlockSender,
-    Outgoing, StatsTx, StreamItem, TemplateValues,
+    body_template_as_hyper_body, compress_hyper_body, count_hyper_body,
+    response_handler::ResponseHandler, AutoReturn, BlockSender, Outgoing, StatsTx, StreamItem,
+    TemplateValues, Throttled,
 };
 
 use std::{
@@ -29,16 +33,19 @@ use std::{
     collections::BTreeMap,
     error::Error as StdError,
     future::Future,
-    sync::Arc,
+    sync::{atomic::AtomicU64, Arc},
     task::Poll,
     time::{Duration, Instant, SystemTime},
 };
 
 pub(super) struct RequestMaker {
     pub(super) url: Template,
-    pub(super) method: Method,
+    pub(super) method: MethodTemplate,
     pub(super) headers: Vec<(String, Template)>,
     pub(super) body: BodyTemplate,
+    pub(super) checks: Arc<Vec<(String, Select)>>,
+    pub(super) compress: Option<BodyCompression>,
+    pub(super) metrics: Arc<Vec<(String, MetricKind, Select)>>,
     pub(super) rr_providers: u16,
     pub(super) client:
         Arc<Client<HttpsConnector<HttpConnector<hyper::client::connect::dns::GaiResolver>>>>,
@@ -46,7 +53,10 @@ pub(super) struct RequestMaker {
     pub(super) no_auto_returns: bool,
     pub(super) outgoing: Arc<Vec<Outgoing>>,
     pub(super) precheck_rr_providers: u16,
+    pub(super) retry: Option<Retry>,
+    pub(super) sla: Option<Arc<Sla>>,
     pub(super) tags: Arc<BTreeMap<String, Template>>,
+    pub(super) throttle: Option<Throttle>,
     pub(super) timeout: Duration,
 }
 
@@ -79,6 +89,137 @@ impl ProviderDelays {
     }
 }
 
+type HttpClient = Client<HttpsConnector<HttpConnector<hyper::client::connect::dns::GaiResolver>>>;
+
+// evaluates the tags for a stat which is recorded without a response
+fn evaluate_tags(
+    tags: &BTreeMap<String, Template>,
+    template_values: &TemplateValues,
+) -> Arc<BTreeMap<String, String>> {
+    let tags = tags
+        .iter()
+        .filter_map(|(k, v)| {
+            v.evaluate(Cow::Borrowed(template_values.as_json()), None)
+                .ok()
+                .map(move |v| (k.clone(), v))
+        })
+        .collect();
+    Arc::new(tags)
+}
+
+// sends a request and waits for the response, failing if the timeout is reached first
+async fn send_once(
+    client: &HttpClient,
+    request: Request<HyperBody>,
+    timeout: Duration,
+    upload: Option<u64>,
+) -> Result<Response<HyperBody>, TestError> {
+    let request = match upload {
+        Some(bytes_per_second) => request
+            .map(|body| HyperBody::wrap_stream(Throttled::new(body, Some(bytes_per_second), None))),
+        None => request,
+    };
+    let mut response_future = client.request(request).map_err(|e| {
+        let err: Arc<dyn StdError + Send + Sync> = if let Some(io_error_maybe) = e.source() {
+            if io_error_maybe.downcast_ref::<std::io::Error>().is_some() {
+                let io_error = e.into_cause().expect("should have a cause error");
+                Arc::new(
+                    *io_error
+                        .downcast::<std::io::Error>()
+                        .expect("should downcast as io error"),
+                )
+            } else {
+                Arc::new(e)
+            }
+        } else {
+            Arc::new(e)
+        };
+        TestError::from(RecoverableError::ConnectionErr(SystemTime::now(), err))
+    });
+    let mut timeout = Delay::new(timeout);
+    future::poll_fn(move |cx| match timeout.poll_unpin(cx) {
+        Poll::Ready(_) => Poll::Ready(Err(TestError::from(RecoverableError::Timeout(
+            SystemTime::now(),
+        )))),
+        Poll::Pending => response_future.poll_unpin(cx),
+    })
+    .await
+}
+
+// sends a request, re-sending it when it fails in a way the retry policy covers. To be able to
+// send the same request again, the body is buffered in memory when retries are enabled (the config
+// doesn't allow retries with streamed bodies). The timeout covers every attempt and the backoffs
+// between them, and the retry policy's `per_try_timeout` limits each attempt. Each retry is
+// recorded as a stat, and only the outcome of the last attempt is returned, along with when that
+// attempt was sent
+async fn send_with_retry(
+    client: Arc<HttpClient>,
+    request: Request<HyperBody>,
+    retry: Option<Retry>,
+    upload: Option<u64>,
+    timeout: Duration,
+    stats_tx: StatsTx,
+    tags: Arc<BTreeMap<String, String>>,
+) -> (Instant, Result<Response<HyperBody>, TestError>) {
+    let start = Instant::now();
+    let retry = match retry {
+        Some(r) if r.max > 0 => r,
+        _ => return (start, send_once(&client, request, timeout, upload).await),
+    };
+    let deadline = start + timeout;
+    let (parts, body) = request.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => return (start, Err(RecoverableError::BodyErr(Arc::new(e)).into())),
+    };
+    let mut retries = 0;
+    loop {
+        let mut request = Request::new(HyperBody::from(body.clone()));
+        *request.method_mut() = parts.method.clone();
+        *request.uri_mut() = parts.uri.clone();
+        *request.version_mut() = parts.version;
+        *request.headers_mut() = parts.headers.clone();
+        let attempt_start = Instant::now();
+        let remaining = deadline.saturating_duration_since(attempt_start);
+        let attempt_timeout = retry
+            .per_try_timeout
+            .map_or(remaining, |t| t.min(remaining));
+        let result = send_once(&client, request, attempt_timeout, upload).await;
+        let reason = match &result {
+            Ok(r) if retry.on.contains(&RetryOn::Status(r.status().as_u16())) => {
+                r.status().as_u16().to_string()
+            }
+            Err(TestError::Recoverable(RecoverableError::ConnectionErr(..)))
+                if retry.on.contains(&RetryOn::ConnectionError) =>
+            {
+                "connection error".to_string()
+            }
+            Err(TestError::Recoverable(RecoverableError::Timeout(..)))
+                if retry.on.contains(&RetryOn::Timeout) =>
+            {
+                "timeout".to_string()
+            }
+            _ => return (attempt_start, result),
+        };
+        let backoff = retry.backoff.delay(retries + 1);
+        // a retry which couldn't be sent before the timeout isn't attempted
+        if retries >= retry.max || Instant::now() + backoff >= deadline {
+            return (attempt_start, result);
+        }
+        retries += 1;
+        let _ = stats_tx.unbounded_send(
+            stats::ResponseStat {
+                kind: stats::StatKind::Retry(retries, reason),
+                rtt: None,
+                time: SystemTime::now(),
+                tags: tags.clone(),
+            }
+            .into(),
+        );
+        Delay::new(backoff).await;
+    }
+}
+
 impl RequestMaker {
     // this function is not async because of a compiler bug which raises a nonsensical error
     // https://github.com/rust-lang/rust/issues/71723
@@ -88,11 +229,13 @@ impl RequestMaker {
     ) -> impl Future<Output = Result<(), TestError>> {
         let mut template_values = TemplateValues::new();
         let mut auto_returns = Vec::new();
+        let mut scheduled_instant = None;
         let mut target_instant = None;
         let mut provider_delays = ProviderDelays::new();
         for tv in values {
             match tv {
-                StreamItem::Instant(next_trigger) => {
+                StreamItem::Instant(scheduled, next_trigger) => {
+                    scheduled_instant = Some(scheduled);
                     target_instant = next_trigger;
                 }
                 StreamItem::Declare(name, value, returns, instant) => {
@@ -139,9 +282,23 @@ impl RequestMaker {
                 return future::ready(Err(e)).a();
             }
         };
-        let request = Request::builder()
-            .method(self.method.clone())
-            .uri(url.as_str());
+        let method = match &self.method {
+            MethodTemplate::Static(m) => m.clone(),
+            MethodTemplate::Template(t) => {
+                let method = t
+                    .evaluate(Cow::Borrowed(template_values.as_json()), None)
+                    .map_err(TestError::from)
+                    .and_then(|m| {
+                        config::method_from_str(&m)
+                            .ok_or_else(|| RecoverableError::InvalidMethod(m).into())
+                    });
+                match method {
+                    Ok(m) => m,
+                    Err(e) => return future::ready(Err(e)).a(),
+                }
+            }
+        };
+        let request = Request::builder().method(method.clone()).uri(url.as_str());
         let headers = self
             .headers
             .iter()
@@ -169,18 +326,46 @@ impl RequestMaker {
             ct_entry,
         );
 
+        let compress = match self.body {
+            BodyTemplate::None => None,
+            _ => self.compress,
+        };
+        let checks = self.checks.clone();
+        let metrics = self.metrics.clone();
         let client = self.client.clone();
         let stats_tx = self.stats_tx.clone();
         let outgoing = self.outgoing.clone();
         let timeout_in_micros = self.timeout.as_micros() as u64;
         let precheck_rr_providers = self.precheck_rr_providers;
         let rr_providers = self.rr_providers;
-        let method = self.method.clone();
         let timeout = self.timeout;
         let tags = self.tags.clone();
+        // the `_id` tag is never a template, so the endpoint can be identified without evaluating
+        let id: Option<Arc<str>> = self.tags.get("_id").map(|t| t.evaluate_with_star().into());
+        let retry = self.retry.clone();
+        let sla = self.sla.clone();
+        let throttle = self.throttle;
         let auto_returns2 = auto_returns.clone();
 
         body.and_then(move |(content_length, body)| {
+            // without compression the body is sent as is, otherwise the compressed bytes are
+            // counted as they are sent
+            let request_body_size = content_length;
+            let request_wire_bytes = match compress {
+                Some(_) => Arc::new(AtomicU64::new(0)),
+                None => Arc::new(AtomicU64::new(content_length)),
+            };
+            let (content_length, body) = match compress {
+                Some(c) => {
+                    let body = match compress_hyper_body(body, c) {
+                        Ok(b) => b,
+                        Err(e) => return future::ready(Err(e)).a(),
+                    };
+                    headers.insert(CONTENT_ENCODING, HeaderValue::from_static(c.as_str()));
+                    (0, count_hyper_body(body, request_wire_bytes.clone()))
+                }
+                None => (content_length, body),
+            };
             let request = request.body(body);
             let mut request = match request {
                 Ok(r) => r,
@@ -275,54 +460,47 @@ impl RequestMaker {
             template_values.insert("request".into(), request_provider);
             request.headers_mut().extend(headers);
 
-            let mut response_future = client.request(request).map_err(|e| {
-                let err: Arc<dyn StdError + Send + Sync> = if let Some(io_error_maybe) = e.source()
-                {
-                    if io_error_maybe.downcast_ref::<std::io::Error>().is_some() {
-                        let io_error = e.into_cause().expect("should have a cause error");
-                        Arc::new(
-                            *io_error
-                                .downcast::<std::io::Error>()
-                                .expect("should downcast as io error"),
-                        )
-                    } else {
-                        Arc::new(e)
-                    }
-                } else {
-                    Arc::new(e)
-                };
-                TestError::from(RecoverableError::ConnectionErr(SystemTime::now(), err))
-            });
+            let retry_tags = match retry {
+                Some(_) => evaluate_tags(&tags, &template_values),
+                None => Default::default(),
+            };
+            if let Some(id) = id {
+                let _ = stats_tx.unbounded_send(stats::StatsMessage::Sent(id));
+            }
+            let response_future = send_with_retry(
+                client,
+                request,
+                retry,
+                throttle.and_then(|t| t.upload),
+                timeout,
+                stats_tx.clone(),
+                retry_tags,
+            );
             let outgoing2 = outgoing.clone();
             let mut template_values2 = template_values.clone();
             let stats_tx2 = stats_tx.clone();
             let tags2 = tags.clone();
-            let now = Instant::now();
 
-            let mut timeout = Delay::new(timeout);
-                future::poll_fn(move |cx| {
-                    match timeout.poll_unpin(cx) {
-                        Poll::Ready(_) => Poll::Ready(Err(TestError::from(RecoverableError::Timeout(SystemTime::now())))),
-                        Poll::Pending => {
-                            match response_future.poll_unpin(cx) {
-                                Poll::Ready(v) => Poll::Ready(Ok(v)),
-                                Poll::Pending => Poll::Pending,
-                            }
-                        }
-                    }
-                }).and_then(|r| {
-                    future::ready(r)
-                })
+            // `now` is when the last attempt was sent, so retries aren't counted in the response time
+            response_future.then(move |(now, result)| {
+                future::ready(result)
                 .and_then(move |response| {
                     let rh = ResponseHandler {
+                        checks,
+                        metrics,
                         provider_delays,
                         template_values,
                         precheck_rr_providers,
                         rr_providers,
                         outgoing,
                         now,
+                        request_body_size,
+                        request_wire_bytes,
+                        sla,
                         stats_tx,
                         tags,
+                        target_instant: scheduled_instant,
+                        throttle,
                     };
                     rh.handle(response, auto_returns)
                         .map_err(TestError::from)
@@ -332,15 +510,7 @@ impl RequestMaker {
                         TestError::Recoverable(r) => r,
                         _ => return future::err(r).a(),
                     };
-                    let tags = tags2
-                        .iter()
-                        .filter_map(|(k, v)| {
-                            v.evaluate(Cow::Borrowed(template_values2.as_json()), None)
-                                .ok()
-                                .map(move |v| (k.clone(), v))
-                        })
-                        .collect();
-                    let tags = Arc::new(tags);
+                    let tags = evaluate_tags(&tags2, &template_values2);
                     let mut futures = Vec::new();
                     if outgoing2.iter().any(|o| o.tx.is_logger()) {
                         let error = json::json!({
@@ -365,9 +535,23 @@ impl RequestMaker {
                         _ => SystemTime::now(),
                     };
                     let rtt = match r {
-                        RecoverableError::Timeout(_) => Some(timeout_in_micros),
+                        RecoverableError::Timeout(_) => {
+                            Some(timeout_in_micros.min(now.elapsed().as_micros() as u64))
+                        }
                         _ => None,
                     };
+                    if let (Some(rtt), Some(scheduled_instant)) = (rtt, scheduled_instant) {
+                        let delay = now.saturating_duration_since(scheduled_instant);
+                        let _ = stats_tx2.unbounded_send(
+                            stats::ResponseStat {
+                                kind: stats::StatKind::ScheduledRtt(rtt + delay.as_micros() as u64),
+                                rtt: None,
+                                time,
+                                tags: tags.clone(),
+                            }
+                            .into(),
+                        );
+                    }
                     let _ = stats_tx2.unbounded_send(
                         stats::ResponseStat {
                             kind: stats::StatKind::RecoverableError(r),
@@ -380,7 +564,8 @@ impl RequestMaker {
                     join_all(futures)
                         .map(|_| Ok(()))
                         .b()
-                }).b()
+                })
+            }).b()
         }).then(move |_| {
             if let Some(f) = auto_returns2 {
                 f.map(|_| Ok(())).a()
@@ -395,7 +580,10 @@ impl RequestMaker {
 mod tests {
     use super::*;
     use crate::create_http_client;
-    use futures::channel::mpsc as futures_channel;
+    use config::Backoff;
+    use futures::{channel::mpsc as futures_channel, StreamExt};
+    use hyper::Method;
+    use mod_interval::{ModInterval, PerX};
     use tokio::runtime::Runtime;
 
     #[test]
@@ -404,9 +592,12 @@ mod tests {
         rt.block_on(async move {
             let (port, ..) = test_common::start_test_server(None);
             let url = Template::simple(&format!("https://127.0.0.1:{}", port));
-            let method = Method::GET;
+            let method = MethodTemplate::Static(Method::GET);
             let headers = Vec::new();
             let body = BodyTemplate::None;
+            let checks = Vec::new().into();
+            let compress = None;
+            let metrics = Vec::new().into();
             let rr_providers = 0;
             let precheck_rr_providers = 0;
             let client = create_http_client(Duration::from_secs(60)).unwrap().into();
@@ -415,19 +606,28 @@ mod tests {
             let outgoing = Vec::new().into();
             let timeout = Duration::from_secs(120);
             let tags = Arc::new(BTreeMap::new());
+            let retry = None;
+            let sla = None;
+            let throttle = None;
 
             let rm = RequestMaker {
                 url,
                 method,
                 headers,
                 body,
+                checks,
+                compress,
+                metrics,
                 rr_providers,
                 client,
                 stats_tx,
                 no_auto_returns,
                 outgoing,
                 precheck_rr_providers,
+                retry,
+                sla,
                 tags,
+                throttle,
                 timeout,
             };
 
@@ -435,4 +635,180 @@ mod tests {
             assert!(r.is_ok());
         });
     }
+
+    #[test]
+    fn schedules_from_the_load_pattern_tick() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, _kill_server, _) = test_common::start_test_server(None);
+            let (stats_tx, stats_rx) = futures_channel::unbounded();
+            let rm = RequestMaker {
+                url: Template::simple(&format!("http://127.0.0.1:{}", port)),
+                method: MethodTemplate::Static(Method::GET),
+                headers: Vec::new(),
+                body: BodyTemplate::None,
+                checks: Vec::new().into(),
+                compress: None,
+                metrics: Vec::new().into(),
+                rr_providers: 0,
+                client: create_http_client(Duration::from_secs(60)).unwrap().into(),
+                stats_tx,
+                no_auto_returns: true,
+                outgoing: Vec::new().into(),
+                precheck_rr_providers: 0,
+                retry: None,
+                sla: None,
+                tags: Arc::new(BTreeMap::new()),
+                throttle: None,
+                timeout: Duration::from_secs(120),
+            };
+
+            // 10 hits per second, so the following tick is 100ms after the scheduled one
+            let mut mod_interval = ModInterval::new();
+            mod_interval.append_segment(
+                PerX::second(10.0),
+                Duration::from_secs(10),
+                PerX::second(10.0),
+            );
+            let mut ticks = Box::pin(mod_interval.into_stream(None));
+            let (scheduled, following) = ticks.next().await.unwrap();
+
+            // the request goes out late, as when it waits on a provider
+            let late = Duration::from_millis(50);
+            Delay::new(late).await;
+            let r = rm
+                .send_request(vec![StreamItem::Instant(scheduled, following)])
+                .await;
+            assert!(r.is_ok());
+
+            drop(rm);
+            let stats: Vec<_> = stats_rx.collect().await;
+            let rtt = stats
+                .iter()
+                .find_map(|s| match s {
+                    stats::StatsMessage::ResponseStat(stats::ResponseStat {
+                        kind: stats::StatKind::Response(_),
+                        rtt,
+                        ..
+                    }) => *rtt,
+                    _ => None,
+                })
+                .unwrap();
+            let scheduled_rtt = stats
+                .iter()
+                .find_map(|s| match s {
+                    stats::StatsMessage::ResponseStat(stats::ResponseStat {
+                        kind: stats::StatKind::ScheduledRtt(rtt),
+                        ..
+                    }) => Some(*rtt),
+                    _ => None,
+                })
+                .unwrap();
+            let delay = Duration::from_micros(scheduled_rtt - rtt);
+            assert!(delay >= late, "{:?}", delay);
+            assert!(delay < Duration::from_millis(100), "{:?}", delay);
+        });
+    }
+
+    #[test]
+    fn retries_requests() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, _kill_server, _) = test_common::start_test_server(None);
+            let client: Arc<HttpClient> =
+                create_http_client(Duration::from_secs(60)).unwrap().into();
+            let backoff = Backoff::Constant(Duration::from_millis(10));
+            let checks = vec![
+                // retried until `max` is reached
+                (
+                    "/missing",
+                    Duration::from_secs(10),
+                    None,
+                    RetryOn::Status(404),
+                    2,
+                    "404",
+                ),
+                // not retried
+                (
+                    "/missing",
+                    Duration::from_secs(10),
+                    None,
+                    RetryOn::Status(503),
+                    0,
+                    "",
+                ),
+                (
+                    "/?wait=500",
+                    Duration::from_secs(10),
+                    Some(Duration::from_millis(50)),
+                    RetryOn::Timeout,
+                    2,
+                    "timeout",
+                ),
+                // the timeout covers all of the attempts
+                (
+                    "/?wait=500",
+                    Duration::from_millis(100),
+                    Some(Duration::from_millis(50)),
+                    RetryOn::Timeout,
+                    1,
+                    "timeout",
+                ),
+            ];
+            for (path, timeout, per_try_timeout, on, expected_retries, reason) in checks {
+                let (stats_tx, stats_rx) = futures_channel::unbounded();
+                let request = Request::post(format!("http://127.0.0.1:{}{}", port, path))
+                    .body(HyperBody::from("foo"))
+                    .unwrap();
+                let retry = Retry {
+                    backoff,
+                    max: 2,
+                    on: vec![on],
+                    per_try_timeout,
+                };
+                let start = Instant::now();
+                let (attempt_start, r) = send_with_retry(
+                    client.clone(),
+                    request,
+                    Some(retry),
+                    None,
+                    timeout,
+                    stats_tx,
+                    Default::default(),
+                )
+                .await;
+                match on {
+                    RetryOn::Timeout => assert!(matches!(
+                        r,
+                        Err(TestError::Recoverable(RecoverableError::Timeout(_)))
+                    )),
+                    _ => assert_eq!(r.unwrap().status(), 404),
+                }
+                // only the last attempt counts toward the response time
+                let backoffs = Duration::from_millis(10) * expected_retries as u32;
+                assert!(attempt_start >= start + backoffs, "{}", path);
+                assert!(
+                    start.elapsed() <= timeout + Duration::from_millis(50),
+                    "{}",
+                    path
+                );
+                let retries: Vec<_> = stats_rx
+                    .filter_map(|msg| async move {
+                        match msg {
+                            stats::StatsMessage::ResponseStat(stats::ResponseStat {
+                                kind: stats::StatKind::Retry(n, reason),
+                                ..
+                            }) => Some((n, reason)),
+                            _ => None,
+                        }
+                    })
+                    .collect()
+                    .await;
+                let expected: Vec<_> = (1..=expected_retries)
+                    .map(|n| (n, reason.to_string()))
+                    .collect();
+                assert_eq!(retries, expected, "{}", path);
+            }
+        });
+    }
 }
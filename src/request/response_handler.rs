@@ -1,17 +1,27 @@
 use super::*;
 
-use config::{RESPONSE_BODY, RESPONSE_HEADERS, RESPONSE_HEADERS_ALL, RESPONSE_STARTLINE, STATS};
+use super::throttle::Throttled;
+use config::{
+    Throttle, RESPONSE_BODY, RESPONSE_HEADERS, RESPONSE_HEADERS_ALL, RESPONSE_STARTLINE, STATS,
+};
 use futures::TryStreamExt;
 
 pub(super) struct ResponseHandler {
+    pub(super) checks: Arc<Vec<(String, Select)>>,
+    pub(super) metrics: Arc<Vec<(String, MetricKind, Select)>>,
     pub(super) provider_delays: ProviderDelays,
     pub(super) template_values: TemplateValues,
     pub(super) precheck_rr_providers: u16,
     pub(super) rr_providers: u16,
     pub(super) outgoing: Arc<Vec<Outgoing>>,
     pub(super) now: Instant,
+    pub(super) request_body_size: u64,
+    pub(super) request_wire_bytes: Arc<AtomicU64>,
+    pub(super) sla: Option<Arc<Sla>>,
     pub(super) stats_tx: StatsTx,
     pub(super) tags: Arc<BTreeMap<String, Template>>,
+    pub(super) target_instant: Option<Instant>,
+    pub(super) throttle: Option<Throttle>,
 }
 
 impl ResponseHandler {
@@ -75,64 +85,125 @@ impl ResponseHandler {
             Ok(i) => i,
             Err(e) => return future::err(e).a(),
         };
-        let ce_header = response.headers().get("content-encoding").map(|h| {
-            h.to_str()
-                .expect("content-encoding header should cast to str")
-        });
-        let ce_header = ce_header.unwrap_or("");
-        let body_future = match (
-            response_fields_added & RESPONSE_BODY != 0,
-            body_reader::Compression::try_from(ce_header),
-        ) {
-            (true, Some(ce)) => {
-                let body = response
-                    .into_body()
-                    .map_err(|e| RecoverableError::BodyErr(Arc::new(e)));
-                let br = body_reader::BodyReader::new(ce);
-                let body_buffer = bytes::BytesMut::new();
-                body.try_fold(
-                    (br, body_buffer),
-                    |(mut br, mut body_buffer), chunks| match br.decode(chunks, &mut body_buffer) {
-                        Ok(_) => future::ready(Ok((br, body_buffer))),
-                        Err(e) => future::ready(Err(RecoverableError::BodyErr(Arc::new(e)))),
-                    },
-                )
-                .map_ok(|(_, body_buffer)| {
-                    let body_string = str::from_utf8(&body_buffer).unwrap_or("<<binary data>>");
-                    let value = if let Ok(value) = json::from_str(body_string) {
-                        value
-                    } else {
-                        json::Value::String(body_string.into())
-                    };
-                    Some(value)
-                })
-                .a()
+        let ce_header = response
+            .headers()
+            .get_all("content-encoding")
+            .iter()
+            .map(|h| String::from_utf8_lossy(h.as_bytes()))
+            .collect::<Vec<_>>()
+            .join(",");
+        let body = match self.throttle {
+            Some(t) if t.download.is_some() || t.slow_read.is_some() => {
+                Throttled::new(response.into_body(), t.download, t.slow_read).a()
             }
-            _ => {
-                // when we don't need the body, skip parsing it, but make sure we get it all
-                response
-                    .into_body()
-                    .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))
-                    .try_fold((), |_, _| future::ok(()))
-                    .map_ok(|_| None)
-                    .b()
+            _ => response.into_body().b(),
+        };
+        let body = body.map_err(|e| RecoverableError::BodyErr(Arc::new(e)));
+        let body_future = if response_fields_added & RESPONSE_BODY != 0 {
+            match body_reader::BodyReader::from_content_encoding(&ce_header) {
+                Ok(br) => {
+                    let body_buffer = bytes::BytesMut::new();
+                    body.try_fold(
+                        (br, body_buffer, 0),
+                        |(mut br, mut body_buffer, wire), chunks| {
+                            let wire = wire + chunks.len() as u64;
+                            match br.decode(chunks, &mut body_buffer) {
+                                Ok(_) => future::ready(Ok((br, body_buffer, wire))),
+                                Err(e) => {
+                                    future::ready(Err(RecoverableError::BodyErr(Arc::new(e))))
+                                }
+                            }
+                        },
+                    )
+                    .map_ok(|(_, body_buffer, wire)| {
+                        let bytes = stats::ByteCounts {
+                            decoded: body_buffer.len() as u64,
+                            wire,
+                        };
+                        let body_string = str::from_utf8(&body_buffer).unwrap_or("<<binary data>>");
+                        let value = if let Ok(value) = json::from_str(body_string) {
+                            value
+                        } else {
+                            json::Value::String(body_string.into())
+                        };
+                        (Some(value), bytes)
+                    })
+                    .a3()
+                }
+                Err(e) => {
+                    // the body is needed but can't be decoded, so drain it and report the error
+                    let e = RecoverableError::BodyErr(Arc::new(e));
+                    body.try_fold((), |_, _| future::ok(()))
+                        .and_then(|_| future::err(e))
+                        .b3()
+                }
             }
+        } else {
+            // when we don't need the body, skip parsing it, but make sure we get it all. An encoded
+            // body is still decoded (and thrown away) to know its decoded size
+            let br = if ce_header.is_empty() {
+                None
+            } else {
+                body_reader::BodyReader::from_content_encoding(&ce_header).ok()
+            };
+            let scratch = bytes::BytesMut::new();
+            body.try_fold(
+                (br, scratch, stats::ByteCounts::default()),
+                |(mut br, mut scratch, mut bytes), chunks| {
+                    bytes.wire += chunks.len() as u64;
+                    match &mut br {
+                        Some(reader) => {
+                            if reader.decode(chunks, &mut scratch).is_ok() {
+                                bytes.decoded += scratch.len() as u64;
+                                scratch.clear();
+                            } else {
+                                // can't be decoded, so count the rest of the body as is
+                                br = None;
+                            }
+                        }
+                        None => bytes.decoded += chunks.len() as u64,
+                    }
+                    future::ok((br, scratch, bytes))
+                },
+            )
+            .map_ok(|(_, _, bytes)| (None, bytes))
+            .c3()
         };
+        let checks = self.checks;
+        let metrics = self.metrics;
         let provider_delays = self.provider_delays;
         let now = self.now;
+        let request_body_size = self.request_body_size;
+        let request_wire_bytes = self.request_wire_bytes;
+        let sla = self.sla;
         let outgoing = self.outgoing;
         let stats_tx = self.stats_tx;
         let tags = self.tags;
+        let target_instant = self.target_instant;
         body_future
             .then(move |body_value| {
+                let (body_value, response_bytes) = match body_value {
+                    Ok((value, bytes)) => (Ok(value), bytes),
+                    Err(e) => (Err(e), Default::default()),
+                };
+                let request_bytes = stats::ByteCounts {
+                    decoded: request_body_size,
+                    wire: request_wire_bytes.load(Ordering::Relaxed),
+                };
                 let bh = BodyHandler {
+                    checks,
                     included_outgoing_indexes,
+                    metrics,
                     now,
                     outgoing,
                     provider_delays,
+                    request_bytes,
+                    response_bytes,
+                    sla,
                     stats_tx,
                     status,
                     tags,
+                    target_instant,
                     template_values,
                 };
                 bh.handle(body_value, auto_returns)
@@ -206,14 +277,21 @@ mod tests {
         let (stats_tx, _) = futures_channel::unbounded();
         let tags = Arc::new(BTreeMap::new());
         let rh = ResponseHandler {
+            checks: Vec::new().into(),
+            metrics: Vec::new().into(),
             provider_delays: ProviderDelays::new(),
             template_values,
             precheck_rr_providers,
             rr_providers,
             outgoing,
             now,
+            request_body_size: 0,
+            request_wire_bytes: Default::default(),
+            sla: None,
             stats_tx,
             tags,
+            target_instant: None,
+            throttle: None,
         };
 
         let auto_returns: Option<futures::future::Pending<_>> = None;
@@ -221,4 +299,48 @@ mod tests {
         let r = block_on(rh.handle(Default::default(), auto_returns));
         assert!(r.is_ok());
     }
+
+    #[test]
+    fn reports_unsupported_encoding() {
+        let template_values = TemplateValues::new();
+        let precheck_rr_providers = RESPONSE_BODY;
+        let rr_providers = RESPONSE_BODY;
+        let outgoing = Vec::new().into();
+        let now = Instant::now();
+        let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+        let tags = Arc::new(BTreeMap::new());
+        let rh = ResponseHandler {
+            checks: Vec::new().into(),
+            metrics: Vec::new().into(),
+            provider_delays: ProviderDelays::new(),
+            template_values,
+            precheck_rr_providers,
+            rr_providers,
+            outgoing,
+            now,
+            request_body_size: 0,
+            request_wire_bytes: Default::default(),
+            sla: None,
+            stats_tx,
+            tags,
+            target_instant: None,
+            throttle: None,
+        };
+
+        let auto_returns: Option<futures::future::Pending<_>> = None;
+        let response = hyper::Response::builder()
+            .header("content-encoding", "gzip, lz4")
+            .body(HyperBody::from("foo"))
+            .unwrap();
+
+        let r = block_on(rh.handle(response, auto_returns));
+        assert!(r.is_ok());
+        match block_on(stats_rx.next()) {
+            Some(stats::StatsMessage::ResponseStat(stats::ResponseStat {
+                kind: stats::StatKind::RecoverableError(RecoverableError::BodyErr(e)),
+                ..
+            })) => assert_eq!(e.to_string(), "unsupported content-encoding `lz4`"),
+            _ => panic!("expected a body error to be recorded"),
+        }
+    }
 }
@@ -0,0 +1,124 @@
+use bytes::Bytes;
+use futures::{FutureExt, Stream, StreamExt};
+use futures_timer::Delay;
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+// wraps a stream of bytes (a request or response body) and limits how fast the bytes are
+// passed through. When a body is being read this creates backpressure on the connection
+// which makes pewpew behave like a slow client
+pub(super) struct Throttled<S> {
+    // the number of bytes that have passed through, used to calculate when the next chunk is due
+    bytes: u64,
+    bytes_per_second: Option<u64>,
+    delay: Option<Delay>,
+    // the part of a chunk which was too big to pass through at once
+    leftover: Option<Bytes>,
+    pause: Option<Duration>,
+    // a chunk which is waiting on the delay before being passed through
+    ready: Option<Bytes>,
+    start: Option<Instant>,
+    stream: S,
+}
+
+impl<S> Throttled<S> {
+    pub(super) fn new(stream: S, bytes_per_second: Option<u64>, pause: Option<Duration>) -> Self {
+        Throttled {
+            bytes: 0,
+            bytes_per_second,
+            delay: None,
+            leftover: None,
+            pause,
+            ready: None,
+            start: None,
+            stream,
+        }
+    }
+}
+
+impl<S, E> Stream for Throttled<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(delay) = &mut self.delay {
+            match delay.poll_unpin(cx) {
+                Poll::Ready(_) => self.delay = None,
+                Poll::Pending => return Poll::Pending,
+            }
+            if let Some(chunk) = self.ready.take() {
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+        }
+        let mut chunk = match self.leftover.take() {
+            Some(chunk) => chunk,
+            None => match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(chunk))) => chunk,
+                p => return p,
+            },
+        };
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let mut wait = self.pause.unwrap_or_default();
+        if let Some(bytes_per_second) = self.bytes_per_second {
+            // pass bytes through in pieces no bigger than what is allowed in 100ms so the
+            // throughput stays smooth
+            let max_piece = ((bytes_per_second / 10) as usize).max(1);
+            if chunk.len() > max_piece {
+                self.leftover = Some(chunk.split_off(max_piece));
+            }
+            self.bytes += chunk.len() as u64;
+            let due = start + Duration::from_secs_f64(self.bytes as f64 / bytes_per_second as f64);
+            wait = wait.max(due.saturating_duration_since(Instant::now()));
+        }
+        if wait == Duration::from_secs(0) {
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+        let mut delay = Delay::new(wait);
+        match delay.poll_unpin(cx) {
+            Poll::Ready(_) => Poll::Ready(Some(Ok(chunk))),
+            Poll::Pending => {
+                self.delay = Some(delay);
+                self.ready = Some(chunk);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, stream};
+
+    #[test]
+    fn throttles_bytes() {
+        let chunks = vec![Ok::<_, ()>(Bytes::from(vec![0u8; 1_000]))];
+        let throttled = Throttled::new(stream::iter(chunks), Some(4_000), None);
+        let start = Instant::now();
+        let pieces = block_on(throttled.collect::<Vec<_>>());
+        let elapsed = start.elapsed();
+
+        assert_eq!(pieces.len(), 3);
+        let total: usize = pieces.iter().map(|p| p.as_ref().unwrap().len()).sum();
+        assert_eq!(total, 1_000);
+        assert!(elapsed >= Duration::from_millis(240), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn pauses_between_chunks() {
+        let chunks = vec![Ok::<_, ()>(Bytes::from_static(b"foo")); 3];
+        let throttled = Throttled::new(stream::iter(chunks), None, Some(Duration::from_millis(50)));
+        let start = Instant::now();
+        let pieces = block_on(throttled.collect::<Vec<_>>());
+        let elapsed = start.elapsed();
+
+        assert_eq!(pieces.len(), 3);
+        assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+    }
+}
@@ -1,19 +1,28 @@
 use crate::error::{RecoverableError, TestError};
-use crate::line_writer::{blocking_writer, MsgType};
+use crate::junit::{Failure, TestCase, TestCasesSender};
+use crate::line_writer::{blocking_writer_with_finish, FileWriter, MsgType};
+use crate::open_metrics::MetricsWriter;
 use crate::providers;
 use crate::TestEndReason;
-use crate::{RunConfig, RunOutputFormat};
+use crate::{
+    create_tag_filter, CompareConfig, ReportConfig, RunConfig, RunOutputFormat, StatsConfig,
+    StatsFileCompression, StatsFileFormat, StatsSummaryFormat,
+};
 
-use channel::ChannelStatsReader;
+use channel::{ChannelStats, ChannelStatsReader};
 use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDateTime, Utc};
+use config::{LinearBuilder, MetricKind, Sla, Threshold, ThresholdMetric};
 use ether::Either;
 use futures::{
-    channel::mpsc::{self as futures_channel, Sender as FCSender},
+    channel::{
+        mpsc::{self as futures_channel, Sender as FCSender},
+        oneshot,
+    },
     future::join_all,
     sink::SinkExt,
     stream, FutureExt, StreamExt,
 };
-use hdrhistogram::Histogram;
+use hdrhistogram::{errors::RecordError, Histogram};
 use serde::{Deserialize, Serialize};
 use serde_json as json;
 use tokio::{
@@ -23,19 +32,34 @@ use tokio::{
 use tokio_stream::wrappers::{BroadcastStream, IntervalStream};
 use yansi::Paint;
 
+use dashboard::{Dashboard, DashboardView};
+use reader::MergedStats;
+use rotation::Rotation;
+use sinks::StatsSinks;
+
 use std::{
     collections::BTreeMap,
     fs::File,
     future::Future,
     io, mem,
-    path::Path,
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::Poll,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-// A helper module which tells serde how to serialize (and deserialize, though that's not currently
-// used anywhere) an HDRHistogram
+mod compare;
+mod dashboard;
+mod reader;
+mod report;
+mod rotation;
+mod sinks;
+
+// A helper module which tells serde how to serialize (and deserialize, when reading stats files)
+// an HDRHistogram
 mod histogram_serde {
     use hdrhistogram::{
         serialization::{
@@ -66,9 +90,12 @@ mod histogram_serde {
             serde::de::Error::custom("could not base64 decode string for HDRHistogram")
         })?;
         let mut hdr_deserializer = HDRDeserializer::new();
-        hdr_deserializer
+        let mut histogram: Histogram<u64> = hdr_deserializer
             .deserialize(&mut bytes.to_vec().as_slice())
-            .map_err(|_| serde::de::Error::custom("could not deserialize HDRHistogram"))
+            .map_err(|_| serde::de::Error::custom("could not deserialize HDRHistogram"))?;
+        // like a new histogram, allow it to grow when combined with others
+        histogram.auto(true);
+        Ok(histogram)
     }
 }
 
@@ -82,13 +109,17 @@ enum FileMessage {
 }
 
 // The header message written to a stats file contains the test name, pewpew
-// version and bucket size
-#[derive(Deserialize, Serialize)]
+// version, bucket size and the settings for the response time stats
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct FileHeader {
     test: String,
     bin: String,
     bucket_size: u64,
+    #[serde(default = "config::default_histogram_significant_digits")]
+    histogram_significant_digits: u8,
+    #[serde(default = "config::default_percentiles")]
+    percentiles: Vec<f64>,
 }
 
 // The tags message written to a stats file contains an index and corresponding
@@ -105,6 +136,50 @@ struct FileTags {
 struct TimeBucket {
     time: u64,
     entries: BTreeMap<usize, BucketGroupStats>,
+    // the throughput of each endpoint by its `_id` tag
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    throughput: BTreeMap<String, Throughput>,
+    // the state of each provider when the bucket closed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    providers: BTreeMap<String, ProviderStats>,
+}
+
+// the hits an endpoint's load pattern called for and the requests it actually sent
+#[derive(Clone, Default, Deserialize, Serialize)]
+struct Throughput {
+    target: f64,
+    sent: u64,
+}
+
+// how many values a provider had, how many endpoints and providers were using it and how many
+// of those were waiting on it
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProviderStats {
+    len: usize,
+    limit: usize,
+    receiver_count: usize,
+    sender_count: usize,
+    on_demand_count: usize,
+    // older stats files don't have the waiting counts
+    #[serde(default)]
+    waiting_receiver_count: usize,
+    #[serde(default)]
+    waiting_sender_count: usize,
+}
+
+impl From<ChannelStats<'_>> for ProviderStats {
+    fn from(stats: ChannelStats<'_>) -> Self {
+        ProviderStats {
+            len: stats.len,
+            limit: stats.limit,
+            receiver_count: stats.receiver_count,
+            sender_count: stats.sender_count,
+            on_demand_count: stats.on_demand_count,
+            waiting_receiver_count: stats.waiting_receiver_count,
+            waiting_sender_count: stats.waiting_sender_count,
+        }
+    }
 }
 
 impl TimeBucket {
@@ -112,12 +187,18 @@ impl TimeBucket {
         TimeBucket {
             time,
             entries: BTreeMap::new(),
+            throughput: BTreeMap::new(),
+            providers: BTreeMap::new(),
         }
     }
 
-    // Append some statistics to this `TimeBucket` for the bucket group with the given index
-    fn append(&mut self, stat: ResponseStat, index: usize) {
-        let entry = self.entries.entry(index).or_default();
+    // Append some statistics to this `TimeBucket` for the bucket group with the given index. A new
+    // bucket group's histograms have the given number of significant digits
+    fn append(&mut self, stat: ResponseStat, index: usize, significant_digits: u8) {
+        let entry = self
+            .entries
+            .entry(index)
+            .or_insert_with(|| BucketGroupStats::new(significant_digits));
         entry.append(stat);
     }
 
@@ -129,6 +210,27 @@ impl TimeBucket {
                 .and_modify(|b| b.combine(entry))
                 .or_insert_with(|| entry.clone());
         }
+        for (id, throughput) in &rhs.throughput {
+            let entry = self.throughput.entry(id.clone()).or_default();
+            entry.target += throughput.target;
+            entry.sent += throughput.sent;
+        }
+        // providers are a point in time, so the later bucket's are kept
+        for (provider, stats) in &rhs.providers {
+            self.providers.insert(provider.clone(), stats.clone());
+        }
+    }
+
+    // Create a CSV summary for this `TimeBucket`, with a row for each bucket group and a column for
+    // each of the percentiles
+    fn create_csv_summary(&self, tags: &BTreeMap<Tags, usize>, percentiles: &[f64]) -> String {
+        let mut csv = csv_header(percentiles);
+        for (tags, index) in tags {
+            if let Some(bucket) = self.entries.get(index) {
+                csv.push_str(&bucket.create_csv_row(tags, percentiles));
+            }
+        }
+        csv
     }
 
     // Create a string summary for this `TimeBucket`
@@ -136,6 +238,7 @@ impl TimeBucket {
         &self,
         tags: &BTreeMap<Tags, usize>,
         format: RunOutputFormat,
+        percentiles: &[f64],
         bucket_size: u64,
         remaining_seconds: Option<u64>,
     ) -> String {
@@ -162,6 +265,7 @@ impl TimeBucket {
                 let piece = bucket.create_print_summary(
                     tags,
                     format,
+                    percentiles,
                     self.time,
                     test_complete,
                     bucket_size,
@@ -186,12 +290,37 @@ impl TimeBucket {
 
 // The aggregate statistics that are tracked for each bucket group in a given interval (bucket size)
 #[derive(Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(default, rename_all = "camelCase")]
 struct BucketGroupStats {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<String, CheckCounts>,
+    // what the first attempts of requests which were retried failed with
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    first_attempt_failures: BTreeMap<String, u64>,
+    // the custom metrics from the endpoint's `metrics` by their name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metrics: BTreeMap<String, MetricStats>,
+    // how many times the endpoint was delayed waiting on a provider. These are also counted in the
+    // test errors
+    #[serde(default, skip_serializing_if = "is_zero")]
+    provider_delays: u64,
     #[serde(skip_serializing_if = "is_zero")]
     request_timeouts: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    retries: u64,
+    #[serde(default, skip_serializing_if = "ByteTotals::is_empty")]
+    request_bytes: ByteTotals,
+    #[serde(default, skip_serializing_if = "ByteTotals::is_empty")]
+    response_bytes: ByteTotals,
     #[serde(with = "histogram_serde", skip_serializing_if = "Histogram::is_empty")]
     rtt_histogram: Histogram<u64>,
+    // round-trip times measured from when requests should have been sent rather than when they
+    // were, which corrects for coordinated omission
+    #[serde(with = "histogram_serde", skip_serializing_if = "Histogram::is_empty")]
+    scheduled_rtt_histogram: Histogram<u64>,
+    // which limits of the endpoint's SLA were breached, set when the bucket is closed out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sla_breaches: Vec<SlaBreach>,
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     status_counts: BTreeMap<u16, u64>,
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
@@ -200,21 +329,39 @@ struct BucketGroupStats {
 
 impl Default for BucketGroupStats {
     fn default() -> Self {
+        BucketGroupStats::new(config::default_histogram_significant_digits())
+    }
+}
+
+impl BucketGroupStats {
+    // the round-trip time histograms have the given number of significant digits
+    fn new(significant_digits: u8) -> Self {
         BucketGroupStats {
+            checks: Default::default(),
+            first_attempt_failures: Default::default(),
+            metrics: Default::default(),
+            provider_delays: 0,
+            request_bytes: ByteTotals::new(significant_digits),
             request_timeouts: 0,
-            rtt_histogram: Histogram::new(3).expect("could not create histogram"),
+            response_bytes: ByteTotals::new(significant_digits),
+            retries: 0,
+            rtt_histogram: Histogram::new(significant_digits).expect("could not create histogram"),
+            scheduled_rtt_histogram: Histogram::new(significant_digits)
+                .expect("could not create histogram"),
+            sla_breaches: Vec::new(),
             status_counts: Default::default(),
             test_errors: Default::default(),
         }
     }
-}
 
-impl BucketGroupStats {
     // Append new stats into the aggregates
     fn append(&mut self, stat: ResponseStat) {
         match stat.kind {
             StatKind::RecoverableError(RecoverableError::Timeout(..)) => self.request_timeouts += 1,
             StatKind::RecoverableError(r) => {
+                if let RecoverableError::ProviderDelay(_) = r {
+                    self.provider_delays += 1;
+                }
                 let msg = format!("{}", r);
                 self.test_errors
                     .entry(msg)
@@ -227,16 +374,96 @@ impl BucketGroupStats {
                     .and_modify(|n| *n += 1)
                     .or_insert(1);
             }
+            StatKind::Check(name, passed) => {
+                let counts = self.checks.entry(name).or_default();
+                if passed {
+                    counts.passed += 1;
+                } else {
+                    counts.failed += 1;
+                }
+            }
+            StatKind::Bytes(request, response) => {
+                self.request_bytes.append(request);
+                self.response_bytes.append(response);
+            }
+            StatKind::ScheduledRtt(rtt) => self.scheduled_rtt_histogram += rtt,
+            StatKind::Metric(name, kind, value) => {
+                // histogram metrics keep the same precision as the round-trip times
+                let significant_digits = self.rtt_histogram.sigfig();
+                let result = self
+                    .metrics
+                    .entry(name.clone())
+                    .or_insert_with(|| MetricStats::new(kind, significant_digits))
+                    .append(value);
+                if let Err(e) = result {
+                    let msg = format!("could not record {} for metric `{}`: {:?}", value, name, e);
+                    self.test_errors
+                        .entry(msg)
+                        .and_modify(|n| *n += 1)
+                        .or_insert(1);
+                }
+            }
+            StatKind::Retry(retry, reason) => {
+                self.retries += 1;
+                if retry == 1 {
+                    self.first_attempt_failures
+                        .entry(reason)
+                        .and_modify(|n| *n += 1)
+                        .or_insert(1);
+                }
+            }
         }
         if let Some(rtt) = stat.rtt {
             self.rtt_histogram += rtt;
         }
     }
 
+    // the cumulative count of round-trip times at or below each bucket's upper bound, with the
+    // bounds in microseconds. The bounds come from the histogram, doubling from 1ms up to the
+    // slowest response
+    fn rtt_buckets(&self) -> Vec<(u64, u64)> {
+        let histogram = &self.rtt_histogram;
+        histogram
+            .iter_log(1_000, 2.0)
+            .map(|v| {
+                let bound = v.value_iterated_to() + 1;
+                (bound, histogram.count_between(0, bound))
+            })
+            .collect()
+    }
+
+    fn rtt_summary(&self) -> RttSummary {
+        RttSummary::new(&self.rtt_histogram)
+    }
+
     // Combine two `BucketGroupStats`
     fn combine(&mut self, rhs: &BucketGroupStats) {
+        self.provider_delays += rhs.provider_delays;
         self.request_timeouts += rhs.request_timeouts;
+        self.retries += rhs.retries;
+        self.request_bytes.combine(&rhs.request_bytes);
+        self.response_bytes.combine(&rhs.response_bytes);
+        for (name, counts) in &rhs.checks {
+            let entry = self.checks.entry(name.clone()).or_default();
+            entry.passed += counts.passed;
+            entry.failed += counts.failed;
+        }
+        for (name, metric) in &rhs.metrics {
+            self.metrics
+                .entry(name.clone())
+                .and_modify(|m| m.combine(metric))
+                .or_insert_with(|| metric.clone());
+        }
+        for (reason, count) in &rhs.first_attempt_failures {
+            self.first_attempt_failures
+                .entry(reason.clone())
+                .and_modify(|n| *n += count)
+                .or_insert(*count);
+        }
         let _ = self.rtt_histogram.add(&rhs.rtt_histogram);
+        let _ = self
+            .scheduled_rtt_histogram
+            .add(&rhs.scheduled_rtt_histogram);
         for (status, count) in &rhs.status_counts {
             self.status_counts
                 .entry(*status)
@@ -251,32 +478,125 @@ impl BucketGroupStats {
         }
     }
 
+    // record which of the SLA's limits these stats breach
+    fn check_sla(&mut self, sla: &Sla) {
+        const MICROS_TO_MS: f64 = 1_000.0;
+        if self.rtt_histogram.is_empty() {
+            return;
+        }
+        self.sla_breaches = sla
+            .limits
+            .iter()
+            .filter_map(|(name, percentile, limit)| {
+                let value = self.rtt_histogram.value_at_quantile(percentile / 100.0);
+                if value > limit.as_micros() as u64 {
+                    Some(SlaBreach {
+                        limit: limit.as_micros() as f64 / MICROS_TO_MS,
+                        name: name.clone(),
+                        value: value as f64 / MICROS_TO_MS,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    // the value of a threshold's metric for these stats, `None` when there is no data for it
+    fn threshold_value(&self, metric: &ThresholdMetric) -> Option<f64> {
+        const MICROS_TO_MS: f64 = 1_000.0;
+        let calls = self.rtt_histogram.len();
+        let errors: u64 = self.test_errors.values().sum();
+        let requests = calls + errors + self.request_timeouts;
+        let value = match metric {
+            ThresholdMetric::Calls if requests > 0 => calls as f64,
+            ThresholdMetric::Check(name) => {
+                let counts = self.checks.get(name)?;
+                let total = counts.passed + counts.failed;
+                if total == 0 {
+                    return None;
+                }
+                counts.passed as f64 / total as f64 * 100.0
+            }
+            ThresholdMetric::ErrorRate if requests > 0 => {
+                (errors + self.request_timeouts) as f64 / requests as f64 * 100.0
+            }
+            ThresholdMetric::Errors if requests > 0 => errors as f64,
+            ThresholdMetric::Max if calls > 0 => self.rtt_histogram.max() as f64 / MICROS_TO_MS,
+            ThresholdMetric::Mean if calls > 0 => self.rtt_histogram.mean() / MICROS_TO_MS,
+            ThresholdMetric::Min if calls > 0 => self.rtt_histogram.min() as f64 / MICROS_TO_MS,
+            ThresholdMetric::Percentile(p) if calls > 0 => {
+                self.rtt_histogram.value_at_quantile(p / 100.0) as f64 / MICROS_TO_MS
+            }
+            ThresholdMetric::Retries if requests > 0 => self.retries as f64,
+            ThresholdMetric::StdDev if calls > 0 => self.rtt_histogram.stdev() / MICROS_TO_MS,
+            ThresholdMetric::Timeouts if requests > 0 => self.request_timeouts as f64,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    // a JUnit test case for these stats, which fails for each failed check and breached SLA limit
+    fn junit_test_case(&self, class_name: &str, tags: &Tags, percentiles: &[f64]) -> TestCase {
+        let mut test_case = TestCase::new(class_name.into(), group_name(tags));
+        for (name, counts) in &self.checks {
+            if counts.failed > 0 {
+                test_case.failures.push(Failure {
+                    details: format!(
+                        "{} of {} calls failed the check",
+                        counts.failed,
+                        counts.failed + counts.passed
+                    ),
+                    kind: "check",
+                    message: format!("check `{}` failed", name),
+                });
+            }
+        }
+        for breach in &self.sla_breaches {
+            test_case.failures.push(Failure {
+                details: format!("{}: {}ms > {}ms", breach.name, breach.value, breach.limit),
+                kind: "sla",
+                message: format!("SLA breached for {}", breach.name),
+            });
+        }
+        let rtt_times = rtt_percentiles(&self.rtt_histogram, percentiles)
+            .into_iter()
+            .map(|(name, value)| format!("{}: {}ms, ", name, value))
+            .collect::<String>();
+        test_case.system_out = format!(
+            "calls made: {}\nstatus counts: {:?}\nrequest timeouts: {}\ntest errors: {:?}\n\
+             {}max: {}ms",
+            self.rtt_histogram.len(),
+            self.status_counts,
+            self.request_timeouts,
+            self.test_errors,
+            rtt_times,
+            self.rtt_summary().max
+        );
+        test_case
+    }
+
     // create a string summary for this `BucketGroupStats`
     fn create_print_summary(
         &self,
         tags: &Tags,
         format: RunOutputFormat,
+        percentiles: &[f64],
         time: u64,
         test_complete: bool,
         bucket_size: u64,
     ) -> String {
         let calls_made = self.rtt_histogram.len();
         let mut print_string = String::new();
-        if calls_made == 0 && self.test_errors.is_empty() && self.request_timeouts == 0 {
+        if calls_made == 0
+            && self.test_errors.is_empty()
+            && self.request_timeouts == 0
+            && self.retries == 0
+        {
             return print_string;
         }
-        const MICROS_TO_MS: f64 = 1_000.0;
         let method = tags.get("method").expect("tags missing `method`");
         let url = tags.get("url").expect("tags missing `url`");
-        let p50 = self.rtt_histogram.value_at_quantile(0.5) as f64 / MICROS_TO_MS;
-        let p90 = self.rtt_histogram.value_at_quantile(0.90) as f64 / MICROS_TO_MS;
-        let p95 = self.rtt_histogram.value_at_quantile(0.95) as f64 / MICROS_TO_MS;
-        let p99 = self.rtt_histogram.value_at_quantile(0.99) as f64 / MICROS_TO_MS;
-        let p99_9 = self.rtt_histogram.value_at_quantile(0.999) as f64 / MICROS_TO_MS;
-        let min = self.rtt_histogram.min() as f64 / MICROS_TO_MS;
-        let max = self.rtt_histogram.max() as f64 / MICROS_TO_MS;
-        let mean = self.rtt_histogram.mean().round() / MICROS_TO_MS;
-        let stddev = self.rtt_histogram.stdev().round() / MICROS_TO_MS;
         match format {
             RunOutputFormat::Human => {
                 // human format
@@ -295,17 +615,68 @@ impl BucketGroupStats {
                     let piece = format!("  test errors: {:?}\n", self.test_errors);
                     print_string.push_str(&piece);
                 }
-                let piece = format!(
-                    "  p50: {}ms, p90: {}ms, p95: {}ms, p99: {}ms, p99.9: {}ms\n  \
-                     min: {}ms, max: {}ms, avg: {}ms, std. dev: {}ms\n",
-                    p50, p90, p95, p99, p99_9, min, max, mean, stddev
-                );
+                if self.retries > 0 {
+                    let piece = format!(
+                        "  retries: {}\n  first attempt failures: {:?}\n",
+                        self.retries, self.first_attempt_failures
+                    );
+                    print_string.push_str(&piece);
+                }
+                if !self.request_bytes.is_empty() || !self.response_bytes.is_empty() {
+                    let piece = format!(
+                        "  bytes sent: {}\n  bytes received: {}\n",
+                        self.request_bytes.create_print_summary(percentiles),
+                        self.response_bytes.create_print_summary(percentiles)
+                    );
+                    print_string.push_str(&piece);
+                }
+                if !self.sla_breaches.is_empty() {
+                    let breaches = self
+                        .sla_breaches
+                        .iter()
+                        .map(|b| format!("{}: {}ms > {}ms", b.name, b.value, b.limit))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let piece = format!(
+                        "  {}\n",
+                        Paint::red(format!("SLA breached: {}", breaches)).bold()
+                    );
+                    print_string.push_str(&piece);
+                }
+                if !self.checks.is_empty() {
+                    print_string.push_str("  checks:\n");
+                    for (name, counts) in &self.checks {
+                        let piece = format!(
+                            "    {}: {} passed, {} failed\n",
+                            name, counts.passed, counts.failed
+                        );
+                        print_string.push_str(&piece);
+                    }
+                }
+                if !self.metrics.is_empty() {
+                    print_string.push_str("  metrics:\n");
+                    for (name, metric) in &self.metrics {
+                        let piece = format!(
+                            "    {}: {}\n",
+                            name,
+                            metric.create_print_summary(percentiles)
+                        );
+                        print_string.push_str(&piece);
+                    }
+                }
+                let piece = rtt_print_summary(&self.rtt_histogram, percentiles, "  ");
                 print_string.push_str(&piece);
+                if !self.scheduled_rtt_histogram.is_empty() {
+                    print_string.push_str("  from scheduled send time:\n");
+                    let piece =
+                        rtt_print_summary(&self.scheduled_rtt_histogram, percentiles, "    ");
+                    print_string.push_str(&piece);
+                }
             }
             RunOutputFormat::Json => {
                 // json format
                 let summary_type = if test_complete { "test" } else { "bucket" };
-                let output = json::json!({
+                let mut output = json::json!({
                     "type": "summary",
                     "startTime": time,
                     "timestamp": time + bucket_size,
@@ -325,25 +696,449 @@ impl BucketGroupStats {
                     "testErrorCount":
                         self.test_errors.iter()
                             .fold(0, |sum, (_, c)| sum + c),
-                    "p50": p50,
-                    "p90": p90,
-                    "p95": p95,
-                    "p99": p99,
-                    "p99_9": p99_9,
-                    "min": min,
-                    "max": max,
-                    "mean": mean,
-                    "stddev": stddev,
+                    "retries": self.retries,
+                    "firstAttemptFailures":
+                        self.first_attempt_failures.iter()
+                            .map(|(reason, count)| json::json!({ "reason": reason, "count": count }))
+                            .collect::<Vec<_>>(),
+                    "requestBytes": self.request_bytes.as_json(percentiles),
+                    "responseBytes": self.response_bytes.as_json(percentiles),
+                    "slaBreaches": self.sla_breaches,
+                    "checks":
+                        self.checks.iter()
+                            .map(|(name, counts)| json::json!({
+                                "name": name,
+                                "passed": counts.passed,
+                                "failed": counts.failed,
+                            }))
+                            .collect::<Vec<_>>(),
+                    "metrics":
+                        self.metrics.iter()
+                            .map(|(name, metric)| (name.clone(), metric.as_json(percentiles)))
+                            .collect::<json::Map<_, _>>(),
+                    "scheduled": if self.scheduled_rtt_histogram.is_empty() {
+                        json::Value::Null
+                    } else {
+                        rtt_json(&self.scheduled_rtt_histogram, percentiles).into()
+                    },
                     "tags": tags.iter()
                         .filter(|(k, _)| k.as_str() != "method" && k.as_str() != "url")
                         .collect::<BTreeMap<_, _>>(),
                 });
+                if let Some(output) = output.as_object_mut() {
+                    output.extend(rtt_json(&self.rtt_histogram, percentiles));
+                }
                 let piece = format!("{}\n", output);
                 print_string.push_str(&piece);
             }
         }
         print_string
     }
+
+    // a row of the CSV summary, in the order of `csv_header`
+    fn create_csv_row(&self, tags: &Tags, percentiles: &[f64]) -> String {
+        let method = tags.get("method").map(String::as_str).unwrap_or_default();
+        let url = tags.get("url").map(String::as_str).unwrap_or_default();
+        let other_tags = join_tags(other_tags(tags), ";");
+        let status_counts = self
+            .status_counts
+            .iter()
+            .map(|(status, count)| format!("{}:{}", status, count))
+            .collect::<Vec<_>>()
+            .join(";");
+        let rtt = self.rtt_summary();
+        let mut fields = vec![
+            csv_escape(method),
+            csv_escape(url),
+            csv_escape(&other_tags),
+            self.rtt_histogram.len().to_string(),
+            status_counts,
+            self.request_timeouts.to_string(),
+            self.test_errors.values().sum::<u64>().to_string(),
+            self.retries.to_string(),
+            self.request_bytes.wire.to_string(),
+            self.response_bytes.wire.to_string(),
+        ];
+        fields.extend(
+            rtt_percentiles(&self.rtt_histogram, percentiles)
+                .into_iter()
+                .map(|(_, value)| value.to_string()),
+        );
+        fields.extend(vec![
+            rtt.min.to_string(),
+            rtt.max.to_string(),
+            rtt.mean.to_string(),
+            rtt.stddev.to_string(),
+        ]);
+        format!("{}\n", fields.join(","))
+    }
+}
+
+// the header of the CSV summary, with a column for each of the percentiles named such as `p99_9`
+fn csv_header(percentiles: &[f64]) -> String {
+    let percentiles = percentiles
+        .iter()
+        .map(|p| format!("p{},", p).replace('.', "_"))
+        .collect::<String>();
+    format!(
+        "method,url,tags,calls,status_counts,request_timeouts,test_errors,retries,request_bytes,\
+         response_bytes,{}min,max,mean,stddev\n",
+        percentiles
+    )
+}
+
+// quote a CSV field if it needs to be
+fn csv_escape(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// the round-trip times of a bucket group, in milliseconds. The percentiles are configurable, so
+// they come from `rtt_percentiles`
+struct RttSummary {
+    min: f64,
+    max: f64,
+    mean: f64,
+    stddev: f64,
+}
+
+impl RttSummary {
+    fn new(histogram: &Histogram<u64>) -> Self {
+        const MICROS_TO_MS: f64 = 1_000.0;
+        RttSummary {
+            min: histogram.min() as f64 / MICROS_TO_MS,
+            max: histogram.max() as f64 / MICROS_TO_MS,
+            mean: histogram.mean().round() / MICROS_TO_MS,
+            stddev: histogram.stdev().round() / MICROS_TO_MS,
+        }
+    }
+}
+
+// the round-trip times of a histogram at each of the percentiles, in milliseconds. Each is named
+// such as `p99.9`
+fn rtt_percentiles(histogram: &Histogram<u64>, percentiles: &[f64]) -> Vec<(String, f64)> {
+    const MICROS_TO_MS: f64 = 1_000.0;
+    percentiles
+        .iter()
+        .map(|p| {
+            let value = histogram.value_at_quantile(p / 100.0) as f64 / MICROS_TO_MS;
+            (format!("p{}", p), value)
+        })
+        .collect()
+}
+
+// the lines of the human summary with the round-trip times of a histogram
+fn rtt_print_summary(histogram: &Histogram<u64>, percentiles: &[f64], indent: &str) -> String {
+    let RttSummary {
+        min,
+        max,
+        mean,
+        stddev,
+        ..
+    } = RttSummary::new(histogram);
+    let mut print_string = String::new();
+    if !percentiles.is_empty() {
+        let values = rtt_percentiles(histogram, percentiles)
+            .into_iter()
+            .map(|(name, value)| format!("{}: {}ms", name, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        print_string = format!("{}{}\n", indent, values);
+    }
+    let piece = format!(
+        "{}min: {}ms, max: {}ms, avg: {}ms, std. dev: {}ms\n",
+        indent, min, max, mean, stddev
+    );
+    print_string.push_str(&piece);
+    print_string
+}
+
+// the round-trip times of a histogram for the JSON summary. The percentiles are keyed such as
+// `p99_9`
+fn rtt_json(histogram: &Histogram<u64>, percentiles: &[f64]) -> json::Map<String, json::Value> {
+    let RttSummary {
+        min,
+        max,
+        mean,
+        stddev,
+        ..
+    } = RttSummary::new(histogram);
+    let mut output: json::Map<_, _> = rtt_percentiles(histogram, percentiles)
+        .into_iter()
+        .map(|(name, value)| (name.replace('.', "_"), value.into()))
+        .collect();
+    output.insert("min".into(), min.into());
+    output.insert("max".into(), max.into());
+    output.insert("mean".into(), mean.into());
+    output.insert("stddev".into(), stddev.into());
+    output
+}
+
+// the sizes of the request or response bodies for a bucket group. `wire` is the size as sent over
+// the network (after any content-encoding is applied) and `decoded` is the size without any
+// content-encoding. The histogram tracks the wire size of each body
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+struct ByteTotals {
+    decoded: u64,
+    #[serde(with = "histogram_serde")]
+    histogram: Histogram<u64>,
+    wire: u64,
+}
+
+impl Default for ByteTotals {
+    fn default() -> Self {
+        ByteTotals::new(config::default_histogram_significant_digits())
+    }
+}
+
+impl ByteTotals {
+    // the histogram of body sizes has the given number of significant digits
+    fn new(significant_digits: u8) -> Self {
+        ByteTotals {
+            decoded: 0,
+            histogram: Histogram::new(significant_digits).expect("could not create histogram"),
+            wire: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    fn append(&mut self, counts: ByteCounts) {
+        self.decoded += counts.decoded;
+        self.wire += counts.wire;
+        self.histogram += counts.wire;
+    }
+
+    fn combine(&mut self, rhs: &ByteTotals) {
+        self.decoded += rhs.decoded;
+        self.wire += rhs.wire;
+        let _ = self.histogram.add(&rhs.histogram);
+    }
+
+    // the body sizes at each of the percentiles, named such as `p99.9`
+    fn percentiles(&self, percentiles: &[f64]) -> Vec<(String, u64)> {
+        percentiles
+            .iter()
+            .map(|p| {
+                (
+                    format!("p{}", p),
+                    self.histogram.value_at_quantile(p / 100.0),
+                )
+            })
+            .collect()
+    }
+
+    fn create_print_summary(&self, percentiles: &[f64]) -> String {
+        let values = self
+            .percentiles(percentiles)
+            .into_iter()
+            .map(|(name, value)| format!("{}: {}, ", name, value))
+            .collect::<String>();
+        format!(
+            "{} ({} decoded), avg: {}, {}max: {}",
+            self.wire,
+            self.decoded,
+            self.histogram.mean().round(),
+            values,
+            self.histogram.max()
+        )
+    }
+
+    // the percentiles are keyed such as `p99_9`
+    fn as_json(&self, percentiles: &[f64]) -> json::Value {
+        let mut output = json::json!({
+            "wire": self.wire,
+            "decoded": self.decoded,
+            "mean": self.histogram.mean().round(),
+            "max": self.histogram.max(),
+        });
+        if let Some(output) = output.as_object_mut() {
+            let values = self
+                .percentiles(percentiles)
+                .into_iter()
+                .map(|(name, value)| (name.replace('.', "_"), value.into()));
+            output.extend(values);
+        }
+        output
+    }
+}
+
+// a limit of an endpoint's SLA which was breached. Times are in milliseconds
+#[derive(Clone, Deserialize, Serialize)]
+struct SlaBreach {
+    limit: f64,
+    name: String,
+    value: f64,
+}
+
+// how many times a check passed and failed
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+struct CheckCounts {
+    failed: u64,
+    passed: u64,
+}
+
+// the values recorded for a custom metric
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum MetricStats {
+    // the sum of the values
+    Counter {
+        value: f64,
+    },
+    // the most recent value, along with the smallest and largest
+    Gauge {
+        last: f64,
+        max: f64,
+        min: f64,
+    },
+    // the values are recorded in thousandths (see `HISTOGRAM_METRIC_SCALE`). Negative values are
+    // test errors, so they never get here
+    Histogram {
+        #[serde(with = "histogram_serde")]
+        histogram: Histogram<u64>,
+    },
+}
+
+// histogram metrics are recorded as whole numbers, so their values are scaled up to keep three
+// decimal places
+const HISTOGRAM_METRIC_SCALE: f64 = 1_000.0;
+
+impl MetricStats {
+    // a histogram metric keeps the given number of significant digits
+    fn new(kind: MetricKind, significant_digits: u8) -> Self {
+        match kind {
+            MetricKind::Counter => MetricStats::Counter { value: 0.0 },
+            MetricKind::Gauge => MetricStats::Gauge {
+                last: 0.0,
+                max: f64::MIN,
+                min: f64::MAX,
+            },
+            MetricKind::Histogram => MetricStats::Histogram {
+                histogram: Histogram::new(significant_digits).expect("could not create histogram"),
+            },
+        }
+    }
+
+    // a histogram can fail to record a value which is too big for it
+    fn append(&mut self, v: f64) -> Result<(), RecordError> {
+        match self {
+            MetricStats::Counter { value } => *value += v,
+            MetricStats::Gauge { last, max, min } => {
+                *last = v;
+                *max = max.max(v);
+                *min = min.min(v);
+            }
+            MetricStats::Histogram { histogram } => {
+                histogram.record((v * HISTOGRAM_METRIC_SCALE).round() as u64)?;
+            }
+        }
+        Ok(())
+    }
+
+    // combine with the values of a later bucket. A metric which has been recorded as a different
+    // kind is left as is
+    fn combine(&mut self, rhs: &MetricStats) {
+        match (self, rhs) {
+            (MetricStats::Counter { value }, MetricStats::Counter { value: v }) => *value += v,
+            (
+                MetricStats::Gauge { last, max, min },
+                MetricStats::Gauge {
+                    last: l,
+                    max: mx,
+                    min: mn,
+                },
+            ) => {
+                *last = *l;
+                *max = max.max(*mx);
+                *min = min.min(*mn);
+            }
+            (MetricStats::Histogram { histogram }, MetricStats::Histogram { histogram: h }) => {
+                let _ = histogram.add(h);
+            }
+            _ => (),
+        }
+    }
+
+    // the values of a histogram metric at each of the percentiles, named such as `p99.9`
+    fn histogram_percentiles(
+        histogram: &Histogram<u64>,
+        percentiles: &[f64],
+    ) -> Vec<(String, f64)> {
+        percentiles
+            .iter()
+            .map(|p| {
+                let value = histogram.value_at_quantile(p / 100.0) as f64 / HISTOGRAM_METRIC_SCALE;
+                (format!("p{}", p), value)
+            })
+            .collect()
+    }
+
+    fn create_print_summary(&self, percentiles: &[f64]) -> String {
+        let round = |v: f64| (v * 100.0).round() / 100.0;
+        match self {
+            MetricStats::Counter { value } => round(*value).to_string(),
+            MetricStats::Gauge { last, max, min } => {
+                format!(
+                    "{} (min: {}, max: {})",
+                    round(*last),
+                    round(*min),
+                    round(*max)
+                )
+            }
+            MetricStats::Histogram { histogram } => {
+                let unscale = |v: u64| v as f64 / HISTOGRAM_METRIC_SCALE;
+                let values = MetricStats::histogram_percentiles(histogram, percentiles)
+                    .into_iter()
+                    .map(|(name, value)| format!("{}: {}, ", name, value))
+                    .collect::<String>();
+                format!(
+                    "count: {}, {}min: {}, max: {}, avg: {}",
+                    histogram.len(),
+                    values,
+                    unscale(histogram.min()),
+                    unscale(histogram.max()),
+                    round(histogram.mean() / HISTOGRAM_METRIC_SCALE)
+                )
+            }
+        }
+    }
+
+    // the percentiles of a histogram metric are keyed such as `p99_9`
+    fn as_json(&self, percentiles: &[f64]) -> json::Value {
+        match self {
+            MetricStats::Counter { value } => json::json!({ "kind": "counter", "value": value }),
+            MetricStats::Gauge { last, max, min } => json::json!({
+                "kind": "gauge",
+                "last": last,
+                "min": min,
+                "max": max,
+            }),
+            MetricStats::Histogram { histogram } => {
+                let unscale = |v: u64| v as f64 / HISTOGRAM_METRIC_SCALE;
+                let mut output = json::json!({
+                    "kind": "histogram",
+                    "count": histogram.len(),
+                    "min": unscale(histogram.min()),
+                    "max": unscale(histogram.max()),
+                    "mean": histogram.mean() / HISTOGRAM_METRIC_SCALE,
+                });
+                if let Some(output) = output.as_object_mut() {
+                    let values = MetricStats::histogram_percentiles(histogram, percentiles)
+                        .into_iter()
+                        .map(|(name, value)| (name.replace('.', "_"), value.into()));
+                    output.extend(values);
+                }
+                output
+            }
+        }
+    }
 }
 
 // helper function used by serde
@@ -355,17 +1150,56 @@ fn is_zero(n: &u64) -> bool {
 // A struct to manage different time buckets
 struct Stats {
     bucket_size: u64,
+    compression: Option<StatsFileCompression>,
     current: TimeBucket,
     console: FCSender<MsgType>,
+    // the live view shown instead of the bucket summaries with `--tui`
+    dashboard: Option<Dashboard>,
     duration: u64,
     file: FCSender<MsgType>,
+    // when everything sent to the stats file has been written
+    file_done: oneshot::Receiver<()>,
+    file_path: PathBuf,
     format: RunOutputFormat,
+    // the header of the stats file, which is written again to each rotated file
+    header: Option<FileHeader>,
+    // where to send the JUnit test cases when the test completes
+    junit: Option<TestCasesSender>,
+    // when the load pattern started, in seconds since the unix epoch. Used for the hits each
+    // endpoint should have made
+    load_pattern_start: Option<f64>,
+    log_provider_stats: bool,
+    // the percentiles of the response times in the summaries, and the precision they're kept with
+    percentiles: Vec<f64>,
+    significant_digits: u8,
     previous: Option<TimeBucket>,
     providers: Vec<ChannelStatsReader<json::Value>>,
+    // the stats so far when the stats file is an HTML report, and the config to include in it. Every
+    // bucket is kept until the test ends, so this grows with the length of the test
+    report: Option<MergedStats>,
+    report_config: Option<String>,
+    rotation: Rotation,
+    sinks: StatsSinks,
+    slas: BTreeMap<String, Sla>,
+    target_rates: Vec<TargetRate>,
     tags: BTreeMap<Tags, usize>,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    thresholds: Vec<Threshold>,
+    thresholds_failed: Arc<AtomicBool>,
     totals: TimeBucket,
 }
 
+// the load an endpoint with a load pattern should be generating
+#[derive(Clone)]
+pub struct TargetRate {
+    // the `_id` tag of the endpoint
+    id: String,
+    load_pattern: LinearBuilder,
+    max_parallel_requests: bool,
+    // in hits per second
+    peak_load: f64,
+}
+
 // round the current time to the nearest bucket
 fn rounded_epoch(bucket_size: u64) -> u64 {
     round_time(get_epoch(), bucket_size)
@@ -377,29 +1211,49 @@ fn round_time(time: u64, bucket_size: u64) -> u64 {
 }
 
 impl Stats {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         file_name: &Path,
+        compression: Option<StatsFileCompression>,
         bucket_size: u64,
         format: RunOutputFormat,
         console: FCSender<MsgType>,
         providers: Vec<ChannelStatsReader<json::Value>>,
         test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+        slas: BTreeMap<String, Sla>,
+        thresholds: Vec<Threshold>,
+        thresholds_failed: Arc<AtomicBool>,
     ) -> Result<Self, io::Error> {
-        let (file, _) = blocking_writer(
-            File::create(file_name)?,
-            test_killer,
-            file_name.to_string_lossy().to_string(),
-        );
+        let (file, file_done) = create_file_writer(file_name, compression, test_killer.clone())?;
         Ok(Stats {
             bucket_size,
+            compression,
             current: TimeBucket::new(rounded_epoch(bucket_size)),
             console,
+            dashboard: None,
             duration: 0,
             file,
+            file_done,
+            file_path: file_name.into(),
             format,
+            header: None,
+            junit: None,
+            load_pattern_start: None,
+            log_provider_stats: false,
+            percentiles: config::default_percentiles(),
+            significant_digits: config::default_histogram_significant_digits(),
             previous: None,
             providers,
+            report: None,
+            report_config: None,
+            rotation: Rotation::new(None, None),
+            sinks: StatsSinks::default(),
+            slas,
+            target_rates: Vec::new(),
             tags: BTreeMap::new(),
+            test_killer,
+            thresholds,
+            thresholds_failed,
             totals: TimeBucket::new(get_epoch()),
         })
     }
@@ -452,29 +1306,113 @@ impl Stats {
                 i
             }
         };
-        self.current.append(stat, index);
+        self.sinks.push_stat(&stat);
+        if let Some(dashboard) = &mut self.dashboard {
+            dashboard.append(stat.clone(), index, self.significant_digits);
+        }
+        self.current.append(stat, index, self.significant_digits);
         if let Some(new_tag) = new_tag {
             self.write_file_message(FileMessage::Tags(new_tag)).await;
         }
     }
 
-    // Write to the stats file the given message
+    // Write to the stats file the given message, as a line of JSON
     // this fn returns an impl future instead of being async, so as not to capture a reference to `self`
-    fn write_file_message(&self, msg: FileMessage) -> impl Future<Output = ()> {
+    // When the stats file is an HTML report the messages are kept until the end of the test
+    fn write_file_message(&mut self, msg: FileMessage) -> impl Future<Output = ()> {
         let mut file = self.file.clone();
+        let msg = match &mut self.report {
+            Some(report) => {
+                report.add_message(msg);
+                None
+            }
+            None => {
+                let is_bucket = matches!(msg, FileMessage::Buckets(_));
+                serde_json::to_string(&msg).ok().map(|mut s| {
+                    s.push('\n');
+                    self.rotation.add_message(s.len(), is_bucket);
+                    (s, is_bucket)
+                })
+            }
+        };
 
         async move {
-            let msg = match serde_json::to_string(&msg) {
-                Ok(m) => m,
-                Err(_) => return,
+            if let Some((msg, is_bucket)) = msg {
+                let _ = file.send(MsgType::Other(msg)).await;
+                // a compressed file is flushed once a bucket is written so it can be read while the
+                // test is running
+                if is_bucket {
+                    let _ = file.send(MsgType::Flush).await;
+                }
+            }
+        }
+    }
+
+    // start a new stats file, if it's time to, with the header and tags written to it first
+    async fn rotate_file(&mut self) {
+        if self.report.is_some() || !self.rotation.is_due() {
+            return;
+        }
+        let path = self.rotation.next_file(&self.file_path, self.compression);
+        let (file, file_done) =
+            match create_file_writer(&path, self.compression, self.test_killer.clone()) {
+                Ok(writer) => writer,
+                Err(e) => {
+                    let e = TestError::CannotCreateStatsFile(
+                        path.to_string_lossy().into_owned(),
+                        e.into(),
+                    );
+                    let _ = self.test_killer.send(Err(e));
+                    return;
+                }
             };
+        // wait for the previous file to be finished
+        drop(mem::replace(&mut self.file, file));
+        let _ = mem::replace(&mut self.file_done, file_done).await;
+        if let Some(header) = self.header.clone() {
+            self.write_file_message(FileMessage::Header(header)).await;
+        }
+        let tags: Vec<_> = self
+            .tags
+            .iter()
+            .map(|(tags, index)| FileTags {
+                index: *index,
+                tags: tags.clone(),
+            })
+            .collect();
+        for tags in tags {
+            self.write_file_message(FileMessage::Tags(tags)).await;
+        }
+    }
+
+    // wait for everything to be written to the stats file
+    async fn close_file(self) {
+        let Stats {
+            file, file_done, ..
+        } = self;
+        drop(file);
+        let _ = file_done.await;
+    }
+
+    // Write the HTML report to the stats file, if that's its format
+    fn write_html_report(&self) -> impl Future<Output = ()> {
+        let mut file = self.file.clone();
+        let html = self.report.as_ref().map(|report| {
+            report::create_html_report(report, self.report_config.as_deref(), &self.thresholds)
+        });
 
-            let _ = file.send(MsgType::Other(msg)).await;
+        async move {
+            if let Some(html) = html {
+                let _ = file.send(MsgType::Other(html)).await;
+            }
         }
     }
 
     // Create the provider stats summary
     fn create_provider_stats_summary(&self, time: u64) -> String {
+        if !self.log_provider_stats {
+            return String::new();
+        }
         let is_human_format = self.format.is_human();
         let mut string_to_print = if is_human_format && !self.providers.is_empty() {
             format!("{}", Paint::new("\nProvider Stats\n").bold())
@@ -486,12 +1424,15 @@ impl Stats {
             let piece = if is_human_format {
                 format!(
                     "\n- {}:\n  length: {}\n  limit: {}\n  \
-                     number of receivers: {}\n  number of senders: {}\n",
+                     number of receivers: {} ({} waiting)\n  \
+                     number of senders: {} ({} waiting)\n",
                     Paint::yellow(stats.provider).dimmed(),
                     stats.len,
                     stats.limit,
                     stats.receiver_count,
+                    stats.waiting_receiver_count,
                     stats.sender_count,
+                    stats.waiting_sender_count,
                 )
             } else {
                 let mut s = json::to_string(&stats).expect("could not serialize provider stats");
@@ -504,59 +1445,585 @@ impl Stats {
         string_to_print
     }
 
-    // Close out the bucket. This happens when the test has completed or when it's time for a new bucket
-    // When a bucket is closed out stats are written to the console and to the stats file
-    async fn close_out_bucket(&mut self, remaining_seconds: Option<u64>) {
-        let test_complete = remaining_seconds.is_none();
-        let mut is_new_bucket = false;
-        let time = rounded_epoch(self.bucket_size) - self.bucket_size;
-        let bucket = match self.get_previous_bucket(test_complete) {
-            Some(b) => b,
-            None => {
-                is_new_bucket = true;
-                TimeBucket::new(time)
-            }
-        };
-        let mut print_string = if test_complete {
-            String::new()
-        } else {
-            self.create_provider_stats_summary(time)
+    // draw the next frame of the dashboard, if there is one. `elapsed` is how far into the load
+    // pattern the test is
+    fn render_dashboard(
+        &mut self,
+        test_name: &str,
+        elapsed: Duration,
+        remaining: Option<Duration>,
+    ) -> Option<String> {
+        let dashboard = self.dashboard.as_mut()?;
+        let time = get_epoch();
+        let view = DashboardView {
+            elapsed,
+            percentiles: &self.percentiles,
+            providers: self.providers.iter().map(|p| p.get_stats(time)).collect(),
+            remaining,
+            tags: &self.tags,
+            target_rates: &self.target_rates,
+            test_name,
         };
-        let piece = bucket.create_print_summary(
-            &self.tags,
-            self.format,
-            self.bucket_size,
-            remaining_seconds.or(Some(0)),
-        );
-        print_string.push_str(&piece);
+        Some(dashboard.render(&view))
+    }
 
-        let mut futures = Vec::new();
-        if !is_new_bucket {
-            let file_message = FileMessage::Buckets(bucket);
-            futures.push(Either::B(self.write_file_message(file_message)))
+    // the stats so far in the OpenMetrics format. `elapsed` is how far into the load pattern the
+    // test is, which is used for the target rate of each endpoint
+    fn create_open_metrics(&self, elapsed: Option<Duration>) -> String {
+        const MICROS_TO_SECONDS: f64 = 1_000_000.0;
+        let mut totals = self.totals.clone();
+        totals.combine(&self.current);
+        let groups: Vec<_> = self
+            .tags
+            .iter()
+            .filter_map(|(tags, index)| {
+                let labels: Vec<_> = tags.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+                totals.entries.get(index).map(|stats| (labels, stats))
+            })
+            .collect();
+        let mut writer = MetricsWriter::default();
+
+        writer.family("pewpew_calls", "counter", "Calls made");
+        for (labels, stats) in &groups {
+            writer.sample("pewpew_calls_total", labels, stats.rtt_histogram.len());
         }
-        let msg = if test_complete {
-            let blank = TimeBucket::new(0);
-            let bucket = std::mem::replace(&mut self.totals, blank);
-            let print_string2 = bucket.create_print_summary(
-                &self.tags,
-                self.format,
-                self.duration,
-                remaining_seconds,
+        writer.family(
+            "pewpew_responses",
+            "counter",
+            "Responses received by status code",
+        );
+        for (labels, stats) in &groups {
+            for (status, count) in &stats.status_counts {
+                let status = status.to_string();
+                let mut labels = labels.clone();
+                labels.push(("status", &status));
+                writer.sample("pewpew_responses_total", &labels, count);
+            }
+        }
+        writer.family(
+            "pewpew_request_timeouts",
+            "counter",
+            "Requests which timed out",
+        );
+        for (labels, stats) in &groups {
+            writer.sample(
+                "pewpew_request_timeouts_total",
+                labels,
+                stats.request_timeouts,
+            );
+        }
+        writer.family("pewpew_test_errors", "counter", "Test errors by error");
+        for (labels, stats) in &groups {
+            for (error, count) in &stats.test_errors {
+                let mut labels = labels.clone();
+                labels.push(("error", error));
+                writer.sample("pewpew_test_errors_total", &labels, count);
+            }
+        }
+        writer.family(
+            "pewpew_rtt_seconds",
+            "histogram",
+            "Round-trip time of calls",
+        );
+        for (labels, stats) in &groups {
+            let histogram = &stats.rtt_histogram;
+            for (bound, count) in stats.rtt_buckets() {
+                let le = (bound as f64 / MICROS_TO_SECONDS).to_string();
+                let mut labels = labels.clone();
+                labels.push(("le", &le));
+                writer.sample("pewpew_rtt_seconds_bucket", &labels, count);
+            }
+            let mut inf_labels = labels.clone();
+            inf_labels.push(("le", "+Inf"));
+            writer.sample("pewpew_rtt_seconds_bucket", &inf_labels, histogram.len());
+            writer.sample("pewpew_rtt_seconds_count", labels, histogram.len());
+            let sum = histogram.mean() * histogram.len() as f64 / MICROS_TO_SECONDS;
+            writer.sample("pewpew_rtt_seconds_sum", labels, sum);
+        }
+        writer.family("pewpew_provider_length", "gauge", "Values in a provider");
+        let provider_stats: Vec<_> = self.providers.iter().map(|p| p.get_stats(0)).collect();
+        for stats in &provider_stats {
+            writer.sample(
+                "pewpew_provider_length",
+                &[("provider", stats.provider)],
+                stats.len,
+            );
+        }
+        writer.family("pewpew_provider_limit", "gauge", "The limit of a provider");
+        for stats in &provider_stats {
+            writer.sample(
+                "pewpew_provider_limit",
+                &[("provider", stats.provider)],
+                stats.limit,
+            );
+        }
+        writer.family(
+            "pewpew_target_rate",
+            "gauge",
+            "Hits per second the load pattern calls for",
+        );
+        if let Some(elapsed) = elapsed {
+            for rate in &self.target_rates {
+                let percent = rate.load_pattern.percent_at(elapsed).unwrap_or_default();
+                writer.sample(
+                    "pewpew_target_rate",
+                    &[("_id", &rate.id)],
+                    percent * rate.peak_load,
+                );
+            }
+        }
+        writer.finish()
+    }
+
+    // count a request sent by the endpoint with the given `_id` tag
+    fn record_sent(&mut self, id: &str) {
+        if let Some(dashboard) = &mut self.dashboard {
+            dashboard.record_sent(id);
+        }
+        match self.current.throughput.get_mut(id) {
+            Some(throughput) => throughput.sent += 1,
+            None => {
+                let throughput = Throughput {
+                    target: 0.0,
+                    sent: 1,
+                };
+                self.current.throughput.insert(id.into(), throughput);
+            }
+        }
+    }
+
+    // record the hits each endpoint's load pattern called for over a bucket, up to `end` seconds
+    // since the unix epoch. The totals get them as well because the bucket was already combined
+    // into them
+    fn add_targets(&mut self, bucket: &mut TimeBucket, end: f64) {
+        let start = match self.load_pattern_start {
+            Some(start) => start,
+            None => return,
+        };
+        let from = Duration::from_secs_f64((bucket.time as f64 - start).max(0.0));
+        let to = Duration::from_secs_f64((end - start).max(0.0));
+        for rate in &self.target_rates {
+            let target = rate.load_pattern.percent_seconds_between(from, to) * rate.peak_load;
+            bucket.throughput.entry(rate.id.clone()).or_default().target += target;
+            self.totals
+                .throughput
+                .entry(rate.id.clone())
+                .or_default()
+                .target += target;
+        }
+    }
+
+    // compare the requests each endpoint with a load pattern sent in a bucket against what its
+    // load pattern called for, flagging those which fell short
+    fn create_throughput_summary(&self, bucket: &TimeBucket, test_complete: bool) -> String {
+        let mut endpoints = Vec::new();
+        for rate in &self.target_rates {
+            let throughput = bucket.throughput.get(&rate.id).cloned().unwrap_or_default();
+            if throughput.target == 0.0 && throughput.sent == 0 {
+                continue;
+            }
+            let groups: Vec<_> = self
+                .tags
+                .iter()
+                .filter(|(tags, _)| tags.get("_id") == Some(&rate.id))
+                .collect();
+            let mut completed = 0;
+            let mut provider_delayed = false;
+            for (_, index) in &groups {
+                if let Some(entry) = bucket.entries.get(index) {
+                    completed += entry.rtt_histogram.len();
+                    provider_delayed |= entry.provider_delays > 0;
+                }
+            }
+            let (method, url) = groups
+                .first()
+                .map(|(tags, _)| {
+                    let get = |k| tags.get(k).map(String::as_str).unwrap_or_default();
+                    (get("method"), get("url"))
+                })
+                .unwrap_or_default();
+            let shortfall =
+                throughput_shortfall(&throughput, provider_delayed, rate.max_parallel_requests);
+            endpoints.push((rate, method, url, throughput, completed, shortfall));
+        }
+        if endpoints.is_empty() {
+            return String::new();
+        }
+        match self.format {
+            RunOutputFormat::Human => {
+                let mut summary = format!("{}", Paint::new("\nThroughput\n").bold());
+                for (rate, method, url, throughput, completed, shortfall) in &endpoints {
+                    let percent = if throughput.target > 0.0 {
+                        format!(
+                            " ({}% of target)",
+                            (throughput.sent as f64 / throughput.target * 100.0).round()
+                        )
+                    } else {
+                        String::new()
+                    };
+                    let piece = format!(
+                        "\n{}\n  target hits: {:.0}\n  requests sent: {}{}\n  responses: {}\n",
+                        Paint::yellow(format!("- {} {} (_id={}):", method, url, rate.id)).dimmed(),
+                        throughput.target,
+                        throughput.sent,
+                        percent,
+                        completed
+                    );
+                    summary.push_str(&piece);
+                    if let Some(reason) = shortfall {
+                        let piece = format!("  {} {}\n", Paint::red("shortfall:"), reason);
+                        summary.push_str(&piece);
+                    }
+                }
+                summary
+            }
+            RunOutputFormat::Json => {
+                let summary_type = if test_complete { "test" } else { "bucket" };
+                let output = json::json!({
+                    "type": "throughput",
+                    "summaryType": summary_type,
+                    "endpoints":
+                        endpoints.iter()
+                            .map(|(rate, method, url, throughput, completed, shortfall)| json::json!({
+                                "id": rate.id,
+                                "method": method,
+                                "url": url,
+                                "target": (throughput.target * 100.0).round() / 100.0,
+                                "sent": throughput.sent,
+                                "responses": completed,
+                                "shortfall": shortfall,
+                            }))
+                            .collect::<Vec<_>>(),
+                });
+                format!("{}\n", output)
+            }
+        }
+    }
+
+    // record the SLA breaches for each bucket group in a bucket
+    fn check_slas(&self, bucket: &mut TimeBucket) {
+        for (tags, index) in &self.tags {
+            let sla = tags.get("_id").and_then(|id| self.slas.get(id));
+            if let (Some(sla), Some(entry)) = (sla, bucket.entries.get_mut(index)) {
+                entry.check_sla(sla);
+            }
+        }
+    }
+
+    // send the JUnit test cases for the totals of a completed test
+    fn send_junit_test_cases(&mut self, totals: &TimeBucket) {
+        let junit = match self.junit.take() {
+            Some(junit) => junit,
+            None => return,
+        };
+        let mut test_cases: Vec<_> = self
+            .tags
+            .iter()
+            .filter_map(|(tags, index)| {
+                let entry = totals.entries.get(index)?;
+                Some(entry.junit_test_case(&junit.class_name, tags, &self.percentiles))
+            })
+            .collect();
+        let results = threshold_results(
+            &self.thresholds,
+            &self.tags,
+            totals,
+            self.significant_digits,
+            true,
+        );
+        test_cases.extend(threshold_test_cases(&junit.class_name, &results));
+        let _ = junit.tx.send(test_cases);
+    }
+
+    // Check the thresholds against a bucket, returning a report of the results and whether any
+    // failed
+    fn check_thresholds(&self, bucket: &TimeBucket, test_complete: bool) -> (String, bool) {
+        let results = threshold_results(
+            &self.thresholds,
+            &self.tags,
+            bucket,
+            self.significant_digits,
+            test_complete,
+        );
+        let failed = results.iter().any(|(.., passed)| !passed);
+        if results.is_empty() || (!test_complete && !failed) {
+            return (String::new(), failed);
+        }
+        let report = match self.format {
+            RunOutputFormat::Human => {
+                let mut report = format!("{}", Paint::new("\nThresholds\n").bold());
+                for (threshold, measured, passed) in &results {
+                    let condition = threshold_name(threshold);
+                    let measured = match measured {
+                        Some(m) => {
+                            let m = (m * 100.0).round() / 100.0;
+                            format!("{}{}", m, threshold.metric.unit())
+                        }
+                        None => "no data".into(),
+                    };
+                    let result = if *passed {
+                        Paint::green("passed")
+                    } else {
+                        Paint::red("FAILED")
+                    };
+                    let piece = format!("- {}: {} ({})\n", condition, result, measured);
+                    report.push_str(&piece);
+                }
+                report
+            }
+            RunOutputFormat::Json => {
+                let summary_type = if test_complete { "test" } else { "bucket" };
+                let output = json::json!({
+                    "type": "thresholds",
+                    "summaryType": summary_type,
+                    "passed": !failed,
+                    "results":
+                        results.iter()
+                            .map(|(threshold, measured, passed)| json::json!({
+                                "condition": threshold.condition,
+                                "tags": threshold.tags,
+                                "value": measured,
+                                "passed": passed,
+                            }))
+                            .collect::<Vec<_>>(),
+                });
+                format!("{}\n", output)
+            }
+        };
+        (report, failed)
+    }
+
+    // Close out the bucket. This happens when the test has completed or when it's time for a new bucket
+    // When a bucket is closed out stats are written to the console and to the stats file
+    async fn close_out_bucket(&mut self, remaining_seconds: Option<u64>) {
+        let test_complete = remaining_seconds.is_none();
+        let mut is_new_bucket = false;
+        let time = rounded_epoch(self.bucket_size) - self.bucket_size;
+        let mut bucket = match self.get_previous_bucket(test_complete) {
+            Some(b) => b,
+            None => {
+                is_new_bucket = true;
+                TimeBucket::new(time)
+            }
+        };
+        if !is_new_bucket {
+            let end = if test_complete {
+                get_epoch_secs_f64()
+            } else {
+                (bucket.time + self.bucket_size) as f64
+            };
+            self.add_targets(&mut bucket, end);
+        }
+        self.check_slas(&mut bucket);
+        bucket.providers = self
+            .providers
+            .iter()
+            .map(|p| {
+                let stats = p.get_stats(time);
+                (stats.provider.to_string(), stats.into())
+            })
+            .collect();
+        let mut print_string = if test_complete {
+            String::new()
+        } else {
+            self.create_provider_stats_summary(time)
+        };
+        let piece = bucket.create_print_summary(
+            &self.tags,
+            self.format,
+            &self.percentiles,
+            self.bucket_size,
+            remaining_seconds.or(Some(0)),
+        );
+        print_string.push_str(&piece);
+        let piece = self.create_throughput_summary(&bucket, false);
+        print_string.push_str(&piece);
+
+        if !test_complete {
+            let (report, failed) = self.check_thresholds(&bucket, false);
+            print_string.push_str(&report);
+            if failed {
+                self.thresholds_failed.store(true, Ordering::Release);
+                let _ = self.test_killer.send(Ok(TestEndReason::ThresholdFailed));
+            }
+        }
+
+        let mut futures = Vec::new();
+        if !is_new_bucket {
+            self.sinks
+                .push_bucket(&bucket, &self.tags, self.bucket_size);
+            if test_complete {
+                self.sinks.close().await;
+            }
+            self.rotate_file().await;
+            let file_message = FileMessage::Buckets(bucket);
+            futures.push(Either::B(
+                self.write_file_message(file_message).left_future(),
+            ));
+            if test_complete {
+                futures.push(Either::B(self.write_html_report().right_future()));
+            }
+        }
+        let msg = if test_complete {
+            let blank = TimeBucket::new(0);
+            let mut bucket = std::mem::replace(&mut self.totals, blank);
+            self.check_slas(&mut bucket);
+            let print_string2 = bucket.create_print_summary(
+                &self.tags,
+                self.format,
+                &self.percentiles,
+                self.duration,
+                remaining_seconds,
             );
             print_string.push_str(&print_string2);
-            MsgType::Final(print_string)
+            let piece = self.create_throughput_summary(&bucket, true);
+            print_string.push_str(&piece);
+            let (report, failed) = self.check_thresholds(&bucket, true);
+            print_string.push_str(&report);
+            if failed {
+                self.thresholds_failed.store(true, Ordering::Release);
+            }
+            self.send_junit_test_cases(&bucket);
+            // leave the dashboard right away, so the test summary and anything else printed as
+            // the test ends isn't written to the dashboard's screen
+            let restore_terminal = self.dashboard.as_mut().and_then(Dashboard::finish);
+            if let Some(restore_terminal) = restore_terminal {
+                let _ = self
+                    .console
+                    .send(MsgType::Other(restore_terminal.into()))
+                    .await;
+            }
+            Some(MsgType::Final(print_string))
+        } else if self.dashboard.is_some() {
+            // the dashboard takes the place of the bucket summaries on the console
+            None
         } else {
-            MsgType::Other(print_string)
+            Some(MsgType::Other(print_string))
         };
-        let console_output = self.console.send(msg).map(|_| ());
-        futures.push(Either::A(console_output));
+        if let Some(msg) = msg {
+            let console_output = self.console.send(msg).map(|_| ());
+            futures.push(Either::A(console_output));
+        }
         join_all(futures).await;
     }
 }
 
 type Tags = BTreeMap<String, String>;
 
+// a name for a bucket group made up of its method, url and any other tags
+fn group_name(tags: &Tags) -> String {
+    let method = tags.get("method").map(String::as_str).unwrap_or_default();
+    let url = tags.get("url").map(String::as_str).unwrap_or_default();
+    let other_tags = join_tags(other_tags(tags), ", ");
+    if other_tags.is_empty() {
+        format!("{} {}", method, url)
+    } else {
+        format!("{} {} ({})", method, url, other_tags)
+    }
+}
+
+// the tags other than the method and url, which are usually shown on their own
+fn other_tags(tags: &Tags) -> impl Iterator<Item = (&String, &String)> {
+    tags.iter()
+        .filter(|(k, _)| k.as_str() != "method" && k.as_str() != "url")
+}
+
+// tags as `key=value` pairs joined by the separator
+fn join_tags<'a>(
+    tags: impl IntoIterator<Item = (&'a String, &'a String)>,
+    separator: &str,
+) -> String {
+    tags.into_iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+// a threshold's condition along with the tags it is limited to
+fn threshold_name(threshold: &Threshold) -> String {
+    if threshold.tags.is_empty() {
+        threshold.condition.clone()
+    } else {
+        format!(
+            "{} ({})",
+            threshold.condition,
+            join_tags(&threshold.tags, ", ")
+        )
+    }
+}
+
+// the value measured for each threshold against a bucket and whether it passed. Before the test is
+// complete only the thresholds which abort the test are checked, and those without any data yet are
+// skipped
+fn threshold_results<'a>(
+    thresholds: &'a [Threshold],
+    tags: &BTreeMap<Tags, usize>,
+    bucket: &TimeBucket,
+    significant_digits: u8,
+    test_complete: bool,
+) -> Vec<(&'a Threshold, Option<f64>, bool)> {
+    thresholds
+        .iter()
+        .filter(|t| test_complete || t.abort_on_fail)
+        .filter_map(|threshold| {
+            let mut stats = BucketGroupStats::new(significant_digits);
+            for (tags, index) in tags {
+                match bucket.entries.get(index) {
+                    Some(entry) if threshold.matches_tags(tags) => stats.combine(entry),
+                    _ => (),
+                }
+            }
+            let measured = stats.threshold_value(&threshold.metric);
+            if measured.is_none() && !test_complete {
+                return None;
+            }
+            let passed = measured.map(|m| threshold.passes(m)).unwrap_or_default();
+            Some((threshold, measured, passed))
+        })
+        .collect()
+}
+
+// a JUnit test case for each threshold, which fails when the threshold does
+fn threshold_test_cases(
+    class_name: &str,
+    results: &[(&Threshold, Option<f64>, bool)],
+) -> Vec<TestCase> {
+    let class_name = format!("{}.thresholds", class_name);
+    results
+        .iter()
+        .map(|(threshold, measured, passed)| {
+            let mut test_case = TestCase::new(class_name.clone(), threshold_name(threshold));
+            if !passed {
+                let details = match measured {
+                    Some(m) => format!("measured {}{}", m, threshold.metric.unit()),
+                    None => "no data".into(),
+                };
+                test_case.failures.push(Failure {
+                    details,
+                    kind: "threshold",
+                    message: format!("threshold `{}` failed", threshold.condition),
+                });
+            }
+            test_case
+        })
+        .collect()
+}
+
+// create the stats file and the writer for it
+fn create_file_writer(
+    path: &Path,
+    compression: Option<StatsFileCompression>,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+) -> Result<(FCSender<MsgType>, oneshot::Receiver<()>), io::Error> {
+    let writer = FileWriter::new(File::create(path)?, compression)?;
+    Ok(blocking_writer_with_finish(
+        writer,
+        FileWriter::finish,
+        test_killer,
+        path.to_string_lossy().into_owned(),
+    ))
+}
+
 // get the current time as a unix epoch
 fn get_epoch() -> u64 {
     UNIX_EPOCH
@@ -565,6 +2032,35 @@ fn get_epoch() -> u64 {
         .unwrap_or_default()
 }
 
+// get the current time as a unix epoch, with fractional seconds
+fn get_epoch_secs_f64() -> f64 {
+    UNIX_EPOCH
+        .elapsed()
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+// why an endpoint sent fewer requests than its load pattern called for, if it fell short by more
+// than 10%. Because hits are whole, a hit at either edge of a bucket can land in its neighbor so a
+// shortfall of less than two is ignored. Waiting on providers is the likeliest cause, then
+// `max_parallel_requests`, otherwise pewpew itself couldn't keep up
+fn throughput_shortfall(
+    throughput: &Throughput,
+    provider_delayed: bool,
+    max_parallel_requests: bool,
+) -> Option<&'static str> {
+    let missing = throughput.target - throughput.sent as f64;
+    if missing < 2.0 || (throughput.sent as f64) >= throughput.target * 0.9 {
+        None
+    } else if provider_delayed {
+        Some("waiting on providers")
+    } else if max_parallel_requests {
+        Some("limited by max_parallel_requests")
+    } else {
+        Some("the client could not keep up")
+    }
+}
+
 // create a pretty string representing the difference between two epochs
 fn create_date_diff(start: u64, end: u64) -> String {
     let start = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(start as i64, 0), Utc)
@@ -586,9 +2082,13 @@ pub enum StatsMessage {
     ResponseStat(ResponseStat),
     // sent at the beginning of the test
     Start(Duration),
+    // a request for the stats so far in the OpenMetrics format
+    Metrics(oneshot::Sender<String>),
+    // an endpoint, identified by its `_id` tag, sent a request
+    Sent(Arc<str>),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ResponseStat {
     pub kind: StatKind,
     pub rtt: Option<u64>,
@@ -596,12 +2096,33 @@ pub struct ResponseStat {
     pub tags: Arc<Tags>,
 }
 
-// A `ResponseStat` is sent when a `RecoverableError` happens, or when an HTTP response is
-// received
-#[derive(Debug)]
+// A `ResponseStat` is sent when a `RecoverableError` happens, when an HTTP response is
+// received, when a request is retried, when a check is evaluated, or with the sizes of a request
+// and response
+#[derive(Clone, Debug)]
 pub enum StatKind {
     RecoverableError(RecoverableError),
     Response(u16), // u16 represents the HTTP response status code
+    // a request is being retried. The number of the retry (starting at 1) and what the
+    // previous attempt failed with
+    Retry(usize, String),
+    // a check was evaluated against a response. The name of the check and whether it passed
+    Check(String, bool),
+    // the size of the request body and the response body
+    Bytes(ByteCounts, ByteCounts),
+    // the round-trip time in microseconds from when a request should have been sent according to
+    // its endpoint's load pattern
+    ScheduledRtt(u64),
+    // a value for one of an endpoint's custom metrics. The name of the metric, its kind and the value
+    Metric(String, MetricKind, f64),
+}
+
+// the size of a request or response body, as sent over the network and without any
+// content-encoding
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ByteCounts {
+    pub decoded: u64,
+    pub wire: u64,
 }
 
 impl From<ResponseStat> for StatsMessage {
@@ -672,14 +2193,101 @@ fn duration_to_pretty_long_form(duration: Duration) -> String {
     format!("in approximately {}", long_time)
 }
 
+// write an HTML report of one or more stats files, for the `report` subcommand. When a config file
+// is given it is included in the report and its thresholds are checked
+pub fn write_html_report(report_config: ReportConfig) -> Result<(), TestError> {
+    let stats = MergedStats::read(&report_config.files)?;
+    let (config, thresholds) = match &report_config.config_file {
+        Some(path) => {
+            let bytes = std::fs::read(path)
+                .map_err(|e| TestError::CannotOpenFile(path.clone(), e.into()))?;
+            let env_vars = std::env::vars_os()
+                .map(|(k, v)| (k.to_string_lossy().into(), v.to_string_lossy().into()))
+                .collect();
+            let config = config::LoadTest::from_config(&bytes, path, &env_vars)?;
+            let config_text = String::from_utf8_lossy(&bytes).into_owned();
+            (Some(config_text), config.thresholds)
+        }
+        None => (None, Vec::new()),
+    };
+    let html = report::create_html_report(&stats, config.as_deref(), &thresholds);
+    std::fs::write(&report_config.output, html).map_err(|e| {
+        TestError::WritingToFile(
+            report_config.output.to_string_lossy().into_owned(),
+            e.into(),
+        )
+    })
+}
+
+// summarize the stats from one or more stats files, for the `stats` subcommand
+pub fn summarize_stats_files(stats_config: StatsConfig) -> Result<String, TestError> {
+    let mut stats = MergedStats::read(&stats_config.files)?;
+    let tag_filter = create_tag_filter(stats_config.filters);
+    stats.filter(tag_filter, stats_config.from, stats_config.to);
+    let totals = stats.totals();
+    let duration = stats.end.saturating_sub(totals.time);
+    let summary = match stats_config.format {
+        StatsSummaryFormat::Csv => totals.create_csv_summary(&stats.tags, &stats.percentiles),
+        StatsSummaryFormat::Human => {
+            let tests = stats.tests.iter().cloned().collect::<Vec<_>>().join(", ");
+            let mut summary = format!(
+                "{}\n",
+                Paint::new(format!(
+                    "Stats for {} from {} file(s)",
+                    tests,
+                    stats_config.files.len()
+                ))
+                .bold()
+            );
+            let piece = totals.create_print_summary(
+                &stats.tags,
+                RunOutputFormat::Human,
+                &stats.percentiles,
+                duration,
+                None,
+            );
+            summary.push_str(&piece);
+            summary
+        }
+        StatsSummaryFormat::Json => totals.create_print_summary(
+            &stats.tags,
+            RunOutputFormat::Json,
+            &stats.percentiles,
+            duration,
+            None,
+        ),
+    };
+    Ok(summary)
+}
+
+// compare the stats files of a candidate run against those of a baseline, for the `compare`
+// subcommand. Returns the comparison and whether there were any regressions
+pub fn compare_stats_files(compare_config: CompareConfig) -> Result<(String, bool), TestError> {
+    let mut baseline = MergedStats::read(&[compare_config.baseline])?;
+    let mut candidate = MergedStats::read(&[compare_config.candidate])?;
+    let tag_filter = create_tag_filter(compare_config.filters);
+    baseline.filter(&tag_filter, None, None);
+    candidate.filter(&tag_filter, None, None);
+    Ok(compare::compare(
+        &baseline,
+        &candidate,
+        &compare_config.tolerances,
+        compare_config.format,
+    ))
+}
+
 // create the stats channel for a try run
 pub fn create_try_run_stats_channel(
     mut test_complete: BroadcastStream<Result<TestEndReason, TestError>>,
     mut console: FCSender<MsgType>,
+    junit: Option<TestCasesSender>,
+    percentiles: Vec<f64>,
 ) -> futures_channel::UnboundedSender<StatsMessage> {
     let (tx, mut rx) = futures_channel::unbounded::<StatsMessage>();
 
     let stats_receiver_task = async move {
+        // the stats of each endpoint are only kept separately for the JUnit report
+        let mut groups: BTreeMap<Tags, BucketGroupStats> = BTreeMap::new();
         let mut stats = BucketGroupStats::default();
 
         // continue pulling values from the rx channel until it ends or the test_complete future fires
@@ -693,9 +2301,15 @@ pub fn create_try_run_stats_channel(
 
         while let Some(s) = stream.next().await {
             if let StatsMessage::ResponseStat(rs) = s {
-                stats.append(rs);
+                match &junit {
+                    Some(_) => groups.entry((*rs.tags).clone()).or_default().append(rs),
+                    None => stats.append(rs),
+                }
             }
         }
+        for group in groups.values() {
+            stats.combine(group);
+        }
 
         let mut output = format!(
             "{}\n  calls made: {}\n  status counts: {:?}",
@@ -711,8 +2325,45 @@ pub fn create_try_run_stats_channel(
             let piece = format!("\n  test errors: {:?}", stats.test_errors);
             output.push_str(&piece);
         }
+        if stats.retries > 0 {
+            let piece = format!(
+                "\n  retries: {}\n  first attempt failures: {:?}",
+                stats.retries, stats.first_attempt_failures
+            );
+            output.push_str(&piece);
+        }
+        if !stats.request_bytes.is_empty() {
+            let piece = format!(
+                "\n  bytes sent: {}\n  bytes received: {}",
+                stats.request_bytes.create_print_summary(&percentiles),
+                stats.response_bytes.create_print_summary(&percentiles)
+            );
+            output.push_str(&piece);
+        }
+        for (name, counts) in &stats.checks {
+            let piece = format!(
+                "\n  check {}: {} passed, {} failed",
+                name, counts.passed, counts.failed
+            );
+            output.push_str(&piece);
+        }
+        for (name, metric) in &stats.metrics {
+            let piece = format!(
+                "\n  metric {}: {}",
+                name,
+                metric.create_print_summary(&percentiles)
+            );
+            output.push_str(&piece);
+        }
         output.push('\n');
 
+        if let Some(junit) = junit {
+            let test_cases = groups
+                .iter()
+                .map(|(tags, group)| group.junit_test_case(&junit.class_name, tags, &percentiles))
+                .collect();
+            let _ = junit.tx.send(test_cases);
+        }
         let _ = console.send(MsgType::Final(output)).await;
     };
 
@@ -721,14 +2372,44 @@ pub fn create_try_run_stats_channel(
     tx
 }
 
+// the target rates for the endpoints with a load pattern, rebuilt when the config file is updated
+pub fn target_rates(config: &config::LoadTest) -> Vec<TargetRate> {
+    config
+        .endpoints
+        .iter()
+        .enumerate()
+        .filter_map(|(i, e)| match (&e.peak_load, &e.load_pattern) {
+            (Some(peak_load), Some(load_pattern)) => Some(TargetRate {
+                id: i.to_string(),
+                load_pattern: load_pattern.clone().builder(),
+                max_parallel_requests: e.max_parallel_requests.is_some(),
+                peak_load: peak_load.per_second(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
 // create the stats channel for a full test
 pub fn create_stats_channel(
     test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
-    config: &config::GeneralConfig,
+    config: &config::LoadTest,
+    thresholds_failed: Arc<AtomicBool>,
     providers: &BTreeMap<String, providers::Provider>,
     mut console: FCSender<MsgType>,
     run_config: &RunConfig,
+    junit: Option<TestCasesSender>,
 ) -> Result<futures_channel::UnboundedSender<StatsMessage>, TestError> {
+    let thresholds = config.thresholds.clone();
+    // the SLAs by the `_id` tag of their endpoint
+    let slas = config
+        .endpoints
+        .iter()
+        .enumerate()
+        .filter_map(|(i, e)| e.sla.clone().map(|sla| (i.to_string(), sla)))
+        .collect();
+    let target_rates = target_rates(config);
+    let config = &config.config.general;
     let (tx, mut rx) = futures_channel::unbounded::<StatsMessage>();
     let now = Instant::now();
     let start_sec = get_epoch();
@@ -746,28 +2427,46 @@ pub fn create_stats_channel(
     let output_format = run_config.output_format;
 
     let log_provider_stats = config.log_provider_stats.is_some();
-    let providers: Vec<_> = if log_provider_stats {
-        providers
-            .iter()
-            .map(|(name, kind)| channel::ChannelStatsReader::new(name.clone(), &kind.rx))
-            .collect()
-    } else {
-        Vec::new()
-    };
+    let start_at = run_config.start_at.unwrap_or_default();
+    let providers: Vec<_> = providers
+        .iter()
+        .map(|(name, kind)| channel::ChannelStatsReader::new(name.clone(), &kind.rx))
+        .collect();
 
     let mut test_complete = BroadcastStream::new(test_killer.subscribe());
 
     let mut stats = Stats::new(
         &file_path,
+        run_config.stats_file_compression,
         bucket_size_secs,
         output_format,
         console.clone(),
         providers,
         test_killer,
+        slas,
+        thresholds,
+        thresholds_failed,
     )
     .map_err(|e| {
         TestError::CannotCreateStatsFile(file_path.to_string_lossy().into_owned(), e.into())
     })?;
+    stats.junit = junit;
+    stats.log_provider_stats = log_provider_stats;
+    stats.target_rates = target_rates;
+    stats.percentiles = config.percentiles.clone();
+    stats.rotation = Rotation::new(
+        run_config.stats_file_rotate_size,
+        run_config.stats_file_rotate_interval,
+    );
+    stats.significant_digits = config.histogram_significant_digits;
+    if run_config.tui {
+        stats.dashboard = Some(Dashboard::new());
+    }
+    stats.sinks = StatsSinks::new(&config.stats_sinks, &config.percentiles)?;
+    if let StatsFileFormat::Html = run_config.stats_file_format {
+        stats.report = Some(MergedStats::new());
+        stats.report_config = std::fs::read_to_string(&run_config.config_file).ok();
+    }
 
     let mut test_start_time: Option<Instant> = None;
 
@@ -775,44 +2474,52 @@ pub fn create_stats_channel(
     let stats_receiver_task = async move {
         let mut print_stats_interval =
             IntervalStream::new(time::interval_at(now + next_bucket, bucket_size));
+        let mut redraw_interval = if stats.dashboard.is_some() {
+            let second = Duration::from_secs(1);
+            Some(IntervalStream::new(time::interval_at(now + second, second)))
+        } else {
+            None
+        };
         // create a stream which combines getting incoming messages, printing stats on an interval
         // and checking if the test has ended
         enum StreamItem {
             TestComplete,
             NewBucket,
+            Redraw,
             StatsMessage(StatsMessage),
-            UpdateProviders(Vec<ChannelStatsReader<json::Value>>),
+            UpdateProviders(Vec<ChannelStatsReader<json::Value>>, Vec<TargetRate>),
         }
 
         // manually create a stream that polls between:
         // 1) The `Receiver` which indicates when the test is complete (this also indicates when the
         //      config file has been updated during a test)
         // 2) The stream that triggers when new stats should be dumped out
-        // 3) The `Receiver` which receives incoming stats
+        // 3) The stream that triggers when the dashboard should be redrawn, if there is one
+        // 4) The `Receiver` which receives incoming stats
         let mut stream = stream::poll_fn(move |cx| {
             match test_complete.poll_next_unpin(cx) {
                 // test is not complete
                 Poll::Pending => match print_stats_interval.poll_next_unpin(cx) {
                     Poll::Ready(Some(_)) => Poll::Ready(Some(StreamItem::NewBucket)),
-                    _ => match rx.poll_next_unpin(cx) {
-                        Poll::Ready(Some(s)) => Poll::Ready(Some(StreamItem::StatsMessage(s))),
-                        Poll::Ready(None) => Poll::Ready(None),
-                        Poll::Pending => Poll::Pending,
+                    _ => match redraw_interval.as_mut().map(|r| r.poll_next_unpin(cx)) {
+                        Some(Poll::Ready(Some(_))) => Poll::Ready(Some(StreamItem::Redraw)),
+                        _ => match rx.poll_next_unpin(cx) {
+                            Poll::Ready(Some(s)) => Poll::Ready(Some(StreamItem::StatsMessage(s))),
+                            Poll::Ready(None) => Poll::Ready(None),
+                            Poll::Pending => Poll::Pending,
+                        },
                     },
                 },
                 // test config is updated and there's a new set of providers
-                Poll::Ready(Some(Ok(Ok(TestEndReason::ConfigUpdate(providers))))) => {
-                    if log_provider_stats {
-                        let providers = providers
-                            .iter()
-                            .map(|(name, kind)| {
-                                channel::ChannelStatsReader::new(name.clone(), &kind.rx)
-                            })
-                            .collect();
-                        Poll::Ready(Some(StreamItem::UpdateProviders(providers)))
-                    } else {
-                        Poll::Pending
-                    }
+                Poll::Ready(Some(Ok(Ok(TestEndReason::ConfigUpdate(providers, target_rates))))) => {
+                    let providers = providers
+                        .iter()
+                        .map(|(name, kind)| {
+                            channel::ChannelStatsReader::new(name.clone(), &kind.rx)
+                        })
+                        .collect();
+                    let target_rates = target_rates.to_vec();
+                    Poll::Ready(Some(StreamItem::UpdateProviders(providers, target_rates)))
                 }
                 // test is complete
                 Poll::Ready(_) => Poll::Ready(Some(StreamItem::TestComplete)),
@@ -830,8 +2537,20 @@ pub fn create_stats_channel(
                         test_start_time.map(|start| stats.duration - start.elapsed().as_secs());
                     stats.close_out_bucket(test_end_time).await;
                 }
-                StreamItem::UpdateProviders(providers) => {
+                StreamItem::Redraw => {
+                    let elapsed = test_start_time
+                        .map(|start| start.elapsed() + start_at)
+                        .unwrap_or(start_at);
+                    let remaining = test_start_time.map(|start| {
+                        Duration::from_secs(stats.duration).saturating_sub(start.elapsed())
+                    });
+                    if let Some(frame) = stats.render_dashboard(&test_name, elapsed, remaining) {
+                        let _ = console.send(MsgType::Other(frame)).await;
+                    }
+                }
+                StreamItem::UpdateProviders(providers, target_rates) => {
                     stats.providers = providers;
+                    stats.target_rates = target_rates;
                 }
                 StreamItem::StatsMessage(StatsMessage::Start(d)) => {
                     let mut futures = Vec::new();
@@ -843,6 +2562,8 @@ pub fn create_stats_channel(
                             stats.duration = duration.as_secs();
                             let test_end_message = duration_till_end_to_pretty_string(d);
                             match output_format {
+                                // the dashboard shows the time remaining instead
+                                _ if stats.dashboard.is_some() => String::new(),
                                 RunOutputFormat::Human => {
                                     format!("Test duration updated. {}\n", test_end_message)
                                 }
@@ -855,6 +2576,8 @@ pub fn create_stats_channel(
                         (start_time, msg)
                     } else {
                         stats.duration = d.as_secs();
+                        stats.load_pattern_start =
+                            Some(get_epoch_secs_f64() - start_at.as_secs_f64());
                         let now = Instant::now();
                         let test_end_message = duration_till_end_to_pretty_string(d);
                         let bin_version = clap::crate_version!().into();
@@ -871,7 +2594,10 @@ pub fn create_stats_channel(
                             test: test_name.clone(),
                             bin: bin_version,
                             bucket_size: bucket_size_secs,
+                            histogram_significant_digits: stats.significant_digits,
+                            percentiles: stats.percentiles.clone(),
                         };
+                        stats.header = Some(header.clone());
                         let left = stats
                             .write_file_message(FileMessage::Header(header))
                             .map(|_| ());
@@ -884,11 +2610,532 @@ pub fn create_stats_channel(
                     join_all(futures).await;
                 }
                 StreamItem::StatsMessage(StatsMessage::ResponseStat(rs)) => stats.append(rs).await,
+                StreamItem::StatsMessage(StatsMessage::Sent(id)) => stats.record_sent(&id),
+                StreamItem::StatsMessage(StatsMessage::Metrics(tx)) => {
+                    let elapsed = test_start_time.map(|start| start.elapsed() + start_at);
+                    let _ = tx.send(stats.create_open_metrics(elapsed));
+                }
             }
         }
+        stats.close_file().await;
     };
 
     tokio::spawn(stats_receiver_task);
 
     Ok(tx)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use config::LoadTest;
+
+    // the tags of a bucket group for an endpoint
+    fn create_tags(id: &str, url: &str) -> Tags {
+        vec![
+            ("_id".to_string(), id.to_string()),
+            ("method".to_string(), "GET".to_string()),
+            ("url".to_string(), url.to_string()),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn create_stat(kind: StatKind, rtt: Option<u64>, tags: &Tags) -> ResponseStat {
+        ResponseStat {
+            kind,
+            rtt,
+            time: UNIX_EPOCH,
+            tags: Arc::new(tags.clone()),
+        }
+    }
+
+    fn create_thresholds(thresholds: &str) -> Vec<Threshold> {
+        let yaml = format!(
+            "endpoints:\n  - url: http://localhost\nthresholds:\n{}",
+            thresholds
+        );
+        LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &BTreeMap::new())
+            .unwrap()
+            .thresholds
+    }
+
+    // a bucket with a group for each of the tags, with a 200 response for each of their round-trip
+    // times
+    fn create_bucket(groups: &[(&Tags, &[u64])]) -> (TimeBucket, BTreeMap<Tags, usize>) {
+        let mut bucket = TimeBucket::new(0);
+        let mut tags = BTreeMap::new();
+        for (index, (group_tags, rtts)) in groups.iter().enumerate() {
+            tags.insert((*group_tags).clone(), index);
+            for rtt in *rtts {
+                let stat = create_stat(StatKind::Response(200), Some(*rtt), group_tags);
+                bucket.append(stat, index, 3);
+            }
+        }
+        (bucket, tags)
+    }
+
+    // run a function with the `Stats` of a test with the given thresholds, which writes to a
+    // temporary stats file
+    fn with_stats(name: &str, thresholds: Vec<Threshold>, f: impl FnOnce(&mut Stats)) {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _guard = rt.enter();
+        let path =
+            std::env::temp_dir().join(format!("pewpew-{}-{}.json", name, std::process::id()));
+        let (console, _console_rx) = futures_channel::channel(5);
+        let (test_killer, _) = broadcast::channel(1);
+        let mut stats = Stats::new(
+            &path,
+            None,
+            60,
+            RunOutputFormat::Json,
+            console,
+            Vec::new(),
+            test_killer,
+            BTreeMap::new(),
+            thresholds,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        f(&mut stats);
+        drop(stats);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn summaries_use_the_percentiles() {
+        let tags = create_tags("0", "http://localhost/");
+        let (bucket, group_tags) = create_bucket(&[(&tags, &[1_000, 1_500, 2_000])]);
+        let percentiles = [50.0, 75.0, 99.99];
+
+        let csv = bucket.create_csv_summary(&group_tags, &percentiles);
+        let expected = "method,url,tags,calls,status_counts,request_timeouts,test_errors,retries,\
+                        request_bytes,response_bytes,p50,p75,p99_99,min,max,mean,stddev\n\
+                        GET,http://localhost/,_id=0,3,200:3,0,0,0,0,0,1.5,2,2,1,2,1.5,0.408\n";
+        assert_eq!(csv, expected);
+
+        let test_case = bucket.entries[&0].junit_test_case("test", &tags, &percentiles);
+        assert!(
+            test_case
+                .system_out
+                .ends_with("p50: 1.5ms, p75: 2ms, p99.99: 2ms, max: 2ms"),
+            "{}",
+            test_case.system_out
+        );
+    }
+
+    #[test]
+    fn combines_metrics() {
+        let metric = |kind, values: &[f64]| {
+            let mut metric = MetricStats::new(kind, 3);
+            for value in values {
+                metric.append(*value).unwrap();
+            }
+            metric
+        };
+
+        // counters are summed
+        let mut counter = metric(MetricKind::Counter, &[1.0, 2.5]);
+        counter.combine(&metric(MetricKind::Counter, &[3.0]));
+        assert_eq!(counter.as_json(&[])["value"], 6.5);
+
+        // gauges keep the last value of the later bucket and the extremes of both
+        let mut gauge = metric(MetricKind::Gauge, &[5.0, -1.0, 3.0]);
+        assert_eq!(gauge.create_print_summary(&[]), "3 (min: -1, max: 5)");
+        gauge.combine(&metric(MetricKind::Gauge, &[7.0, 2.0]));
+        let output = gauge.as_json(&[]);
+        assert_eq!(
+            (&output["last"], &output["min"], &output["max"]),
+            (&json::json!(2.0), &json::json!(-1.0), &json::json!(7.0))
+        );
+
+        // histograms keep three decimal places
+        let mut histogram = metric(MetricKind::Histogram, &[0.0004, 1.25]);
+        histogram.combine(&metric(MetricKind::Histogram, &[2.0]));
+        let output = histogram.as_json(&[50.0]);
+        assert_eq!(output["count"], 3);
+        assert_eq!(output["min"], 0.0);
+        assert_eq!(output["p50"], 1.25);
+        assert_eq!(output["max"], 2.0);
+
+        // a metric recorded as a different kind in a later bucket is left as is
+        let mut mismatched = metric(MetricKind::Counter, &[1.0]);
+        mismatched.combine(&metric(MetricKind::Gauge, &[10.0]));
+        assert_eq!(mismatched.as_json(&[])["kind"], "counter");
+        assert_eq!(mismatched.as_json(&[])["value"], 1.0);
+    }
+
+    #[test]
+    fn combines_bytes() {
+        let tags = create_tags("0", "http://localhost/");
+        let bytes = |stats: &mut BucketGroupStats, request: (u64, u64), response: (u64, u64)| {
+            let request = ByteCounts {
+                wire: request.0,
+                decoded: request.1,
+            };
+            let response = ByteCounts {
+                wire: response.0,
+                decoded: response.1,
+            };
+            stats.append(create_stat(StatKind::Bytes(request, response), None, &tags));
+        };
+
+        // the byte histograms keep the same precision as the round-trip times
+        let mut stats = BucketGroupStats::new(5);
+        bytes(&mut stats, (100, 100), (1_000, 1_500));
+        bytes(&mut stats, (200, 200), (500, 1_000));
+        let mut rhs = BucketGroupStats::new(5);
+        bytes(&mut rhs, (300, 300), (1_500, 2_000));
+        stats.combine(&rhs);
+
+        let request = &stats.request_bytes;
+        assert_eq!((request.wire, request.decoded), (600, 600));
+        assert_eq!(request.histogram.len(), 3);
+        assert_eq!(request.histogram.sigfig(), 5);
+        let response = &stats.response_bytes;
+        assert_eq!((response.wire, response.decoded), (3_000, 4_500));
+        assert_eq!(response.histogram.len(), 3);
+
+        assert_eq!(
+            response.as_json(&[50.0, 99.9]),
+            json::json!({
+                "wire": 3_000,
+                "decoded": 4_500,
+                "mean": 1_000.0,
+                "p50": 1_000,
+                "p99_9": 1_500,
+                "max": 1_500,
+            })
+        );
+        assert_eq!(
+            request.create_print_summary(&[75.0]),
+            "600 (600 decoded), avg: 200, p75: 300, max: 300"
+        );
+    }
+
+    #[test]
+    fn combines_metrics_across_buckets() {
+        let tags = create_tags("0", "http://localhost/");
+        let bucket = |values: &[(MetricKind, f64)]| {
+            let mut stats = BucketGroupStats::default();
+            for (kind, value) in values {
+                let metric = StatKind::Metric(format!("{:?}", kind), *kind, *value);
+                stats.append(create_stat(metric, None, &tags));
+            }
+            stats
+        };
+        let mut stats = bucket(&[(MetricKind::Counter, 1.0), (MetricKind::Gauge, 4.0)]);
+        stats.combine(&bucket(&[
+            (MetricKind::Counter, 2.0),
+            (MetricKind::Gauge, 1.0),
+            (MetricKind::Histogram, 2.0),
+        ]));
+        assert_eq!(stats.metrics["Counter"].create_print_summary(&[]), "3");
+        assert_eq!(
+            stats.metrics["Gauge"].create_print_summary(&[]),
+            "1 (min: 1, max: 4)"
+        );
+        assert_eq!(
+            stats.metrics["Histogram"].create_print_summary(&[]),
+            "count: 1, min: 2, max: 2, avg: 2"
+        );
+    }
+
+    #[test]
+    fn histogram_metrics_use_the_precision_and_percentiles() {
+        let tags = create_tags("0", "http://localhost/");
+        let mut stats = BucketGroupStats::new(5);
+        for value in &[1.0, 2.0, 3.0, 4.0] {
+            let metric = StatKind::Metric("items".into(), MetricKind::Histogram, *value);
+            stats.append(create_stat(metric, None, &tags));
+        }
+        let metric = &stats.metrics["items"];
+        match metric {
+            MetricStats::Histogram { histogram } => assert_eq!(histogram.sigfig(), 5),
+            _ => panic!("expected a histogram metric"),
+        }
+
+        let percentiles = [75.0, 99.9];
+        let output = metric.as_json(&percentiles);
+        assert_eq!(output["p75"], 3.0);
+        assert_eq!(output["p99_9"], 4.0);
+        assert!(output.get("p50").is_none());
+        assert_eq!(
+            metric.create_print_summary(&percentiles),
+            "count: 4, p75: 3, p99.9: 4, min: 1, max: 4, avg: 2.5"
+        );
+    }
+
+    #[test]
+    fn checks_sla() {
+        let sla = Sla {
+            limits: vec![
+                ("p50".into(), 50.0, Duration::from_millis(2)),
+                ("p99".into(), 99.0, Duration::from_micros(1_500)),
+            ],
+        };
+        let tags = create_tags("0", "http://localhost/");
+        let mut stats = BucketGroupStats::default();
+
+        // no responses, so nothing to breach
+        stats.check_sla(&sla);
+        assert!(stats.sla_breaches.is_empty());
+
+        for rtt in &[1_000, 1_500, 2_000] {
+            stats.append(create_stat(StatKind::Response(200), Some(*rtt), &tags));
+        }
+        stats.check_sla(&sla);
+        let breaches: Vec<_> = stats
+            .sla_breaches
+            .iter()
+            .map(|b| (b.name.as_str(), b.limit, b.value))
+            .collect();
+        assert_eq!(breaches, vec![("p99", 1.5, 2.0)]);
+
+        // a later check replaces the earlier breaches
+        let sla = Sla {
+            limits: vec![("p99".into(), 99.0, Duration::from_millis(2))],
+        };
+        stats.check_sla(&sla);
+        assert!(stats.sla_breaches.is_empty());
+    }
+
+    #[test]
+    fn threshold_values() {
+        let tags = create_tags("0", "http://localhost/");
+        let mut stats = BucketGroupStats::default();
+        for rtt in &[1_000, 1_500, 2_000] {
+            stats.append(create_stat(StatKind::Response(200), Some(*rtt), &tags));
+        }
+        let error = RecoverableError::InvalidMethod("GE T".into());
+        stats.append(create_stat(StatKind::RecoverableError(error), None, &tags));
+        let timeout = RecoverableError::Timeout(UNIX_EPOCH);
+        stats.append(create_stat(
+            StatKind::RecoverableError(timeout),
+            None,
+            &tags,
+        ));
+        stats.append(create_stat(
+            StatKind::Retry(1, "timeout".into()),
+            None,
+            &tags,
+        ));
+        for passed in &[true, true, true, false] {
+            let check = StatKind::Check("ok".into(), *passed);
+            stats.append(create_stat(check, None, &tags));
+        }
+
+        let value = |metric| stats.threshold_value(&metric);
+        assert_eq!(value(ThresholdMetric::Calls), Some(3.0));
+        assert_eq!(value(ThresholdMetric::Errors), Some(1.0));
+        assert_eq!(value(ThresholdMetric::Timeouts), Some(1.0));
+        assert_eq!(value(ThresholdMetric::Retries), Some(1.0));
+        // errors and timeouts out of the calls, errors and timeouts
+        assert_eq!(value(ThresholdMetric::ErrorRate), Some(40.0));
+        assert_eq!(value(ThresholdMetric::Min), Some(1.0));
+        assert_eq!(value(ThresholdMetric::Max), Some(2.0));
+        assert_eq!(value(ThresholdMetric::Mean), Some(1.5));
+        assert_eq!(value(ThresholdMetric::Percentile(50.0)), Some(1.5));
+        assert_eq!(value(ThresholdMetric::Check("ok".into())), Some(75.0));
+        assert_eq!(value(ThresholdMetric::Check("missing".into())), None);
+
+        // without any responses there are no response times, but there is an error rate
+        let mut errors_only = BucketGroupStats::default();
+        let timeout = RecoverableError::Timeout(UNIX_EPOCH);
+        errors_only.append(create_stat(
+            StatKind::RecoverableError(timeout),
+            None,
+            &tags,
+        ));
+        assert_eq!(
+            errors_only.threshold_value(&ThresholdMetric::ErrorRate),
+            Some(100.0)
+        );
+        assert_eq!(errors_only.threshold_value(&ThresholdMetric::Max), None);
+
+        let empty = BucketGroupStats::default();
+        assert_eq!(empty.threshold_value(&ThresholdMetric::Calls), None);
+        assert_eq!(empty.threshold_value(&ThresholdMetric::ErrorRate), None);
+    }
+
+    #[test]
+    fn threshold_results_by_tags() {
+        let fast = create_tags("0", "http://localhost/fast");
+        let slow = create_tags("1", "http://localhost/slow");
+        let (bucket, tags) = create_bucket(&[(&fast, &[1_000, 1_000]), (&slow, &[2_000])]);
+        let thresholds = create_thresholds(
+            "  - calls == 3\n  \
+               - condition: max < 1.5ms\n    tags: {url: '*/fast'}\n  \
+               - condition: max < 1.5ms\n    tags: {url: '*/slow'}\n  \
+               - condition: calls == 1\n    tags: {_id: '1', method: GET}",
+        );
+
+        let results = threshold_results(&thresholds, &tags, &bucket, 3, true);
+        let results: Vec<_> = results.iter().map(|(_, m, p)| (*m, *p)).collect();
+        assert_eq!(
+            results,
+            vec![
+                (Some(3.0), true),
+                (Some(1.0), true),
+                (Some(2.0), false),
+                (Some(1.0), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn threshold_results_during_and_after_the_test() {
+        let tags = create_tags("0", "http://localhost/");
+        let (bucket, group_tags) = create_bucket(&[(&tags, &[1_000])]);
+        let thresholds = create_thresholds(
+            "  - calls > 5\n  \
+               - condition: calls > 5\n    abort_on_fail: true\n  \
+               - condition: p99 < 1s\n    tags: {_id: '5'}\n    abort_on_fail: true",
+        );
+
+        // as each bucket closes only the thresholds which abort the test are checked, and those
+        // without any data are skipped
+        let results = threshold_results(&thresholds, &group_tags, &bucket, 3, false);
+        assert_eq!(results.len(), 1);
+        let (threshold, measured, passed) = results[0];
+        assert!(threshold.abort_on_fail);
+        assert_eq!(threshold.condition, "calls > 5");
+        assert_eq!((measured, passed), (Some(1.0), false));
+
+        // once the test is complete every threshold is checked, and no data fails
+        let results = threshold_results(&thresholds, &group_tags, &bucket, 3, true);
+        let results: Vec<_> = results.iter().map(|(_, m, p)| (*m, *p)).collect();
+        assert_eq!(
+            results,
+            vec![(Some(1.0), false), (Some(1.0), false), (None, false)]
+        );
+    }
+
+    #[test]
+    fn checks_thresholds() {
+        let thresholds =
+            create_thresholds("  - calls > 0\n  - condition: p50 < 1.5ms\n    abort_on_fail: true");
+        with_stats("thresholds", thresholds, |stats| {
+            let tags = create_tags("0", "http://localhost/");
+            let (passing, group_tags) = create_bucket(&[(&tags, &[1_000])]);
+            let (failing, _) = create_bucket(&[(&tags, &[2_000])]);
+            stats.tags = group_tags;
+
+            // a bucket which passes doesn't need a report
+            assert_eq!(
+                stats.check_thresholds(&passing, false),
+                (String::new(), false)
+            );
+
+            let (report, failed) = stats.check_thresholds(&failing, false);
+            assert!(failed);
+            let report: json::Value = json::from_str(&report).unwrap();
+            assert_eq!(report["summaryType"], "bucket");
+            assert_eq!(report["passed"], false);
+            assert_eq!(report["results"].as_array().unwrap().len(), 1);
+            assert_eq!(report["results"][0]["condition"], "p50 < 1.5ms");
+            assert_eq!(report["results"][0]["value"], 2.0);
+
+            // the test report includes every threshold, even when they all pass
+            let (report, failed) = stats.check_thresholds(&passing, true);
+            assert!(!failed);
+            let report: json::Value = json::from_str(&report).unwrap();
+            assert_eq!(report["summaryType"], "test");
+            assert_eq!(report["passed"], true);
+            assert_eq!(report["results"].as_array().unwrap().len(), 2);
+        });
+    }
+
+    fn create_target_rates() -> Vec<TargetRate> {
+        let yaml = "load_pattern:\n  - linear:\n      from: 0%\n      to: 100%\n      over: 60s\n\
+                    endpoints:\n  - url: http://localhost/\n    peak_load: 10hps";
+        let config =
+            LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &BTreeMap::new()).unwrap();
+        target_rates(&config)
+    }
+
+    #[test]
+    fn throughput_shortfalls() {
+        let throughput = |target, sent| Throughput { target, sent };
+
+        // within 10% of the target, or short by less than two hits
+        assert_eq!(
+            throughput_shortfall(&throughput(100.0, 95), true, true),
+            None
+        );
+        assert_eq!(throughput_shortfall(&throughput(3.0, 2), true, true), None);
+        assert_eq!(throughput_shortfall(&throughput(0.0, 5), true, true), None);
+
+        let short = throughput(100.0, 50);
+        assert_eq!(
+            throughput_shortfall(&short, true, true),
+            Some("waiting on providers")
+        );
+        assert_eq!(
+            throughput_shortfall(&short, false, true),
+            Some("limited by max_parallel_requests")
+        );
+        assert_eq!(
+            throughput_shortfall(&short, false, false),
+            Some("the client could not keep up")
+        );
+    }
+
+    #[test]
+    fn adds_targets() {
+        with_stats("targets", Vec::new(), |stats| {
+            stats.target_rates = create_target_rates();
+
+            // nothing is called for before the load pattern starts
+            let mut bucket = TimeBucket::new(0);
+            stats.add_targets(&mut bucket, 30.0);
+            assert!(bucket.throughput.is_empty());
+
+            // the load pattern ramps from 0 to 10 hits per second over the first minute
+            stats.load_pattern_start = Some(0.0);
+            let mut first = TimeBucket::new(0);
+            stats.add_targets(&mut first, 30.0);
+            assert_eq!(first.throughput["0"].target, 75.0);
+            let mut second = TimeBucket::new(30);
+            stats.add_targets(&mut second, 60.0);
+            assert_eq!(second.throughput["0"].target, 225.0);
+            let mut after = TimeBucket::new(60);
+            stats.add_targets(&mut after, 90.0);
+            assert_eq!(after.throughput["0"].target, 0.0);
+            assert_eq!(stats.totals.throughput["0"].target, 300.0);
+        });
+    }
+
+    #[test]
+    fn flags_throughput_shortfalls() {
+        with_stats("throughput", Vec::new(), |stats| {
+            stats.target_rates = create_target_rates();
+            let tags = create_tags("0", "http://localhost/");
+            let (mut bucket, group_tags) = create_bucket(&[(&tags, &[1_000; 50])]);
+            stats.tags = group_tags;
+            let throughput = Throughput {
+                target: 100.0,
+                sent: 50,
+            };
+            bucket.throughput.insert("0".into(), throughput);
+
+            let shortfall = |bucket: &TimeBucket| {
+                let summary = stats.create_throughput_summary(bucket, false);
+                let summary: json::Value = json::from_str(&summary).unwrap();
+                let endpoint = &summary["endpoints"][0];
+                assert_eq!(endpoint["target"], 100.0);
+                assert_eq!(endpoint["sent"], 50);
+                assert_eq!(endpoint["responses"], 50);
+                endpoint["shortfall"].clone()
+            };
+            assert_eq!(shortfall(&bucket), "the client could not keep up");
+
+            let delay = RecoverableError::ProviderDelay("a".into());
+            let stat = create_stat(StatKind::RecoverableError(delay), None, &tags);
+            bucket.append(stat, 0, 3);
+            assert_eq!(bucket.entries[&0].provider_delays, 1);
+            assert_eq!(shortfall(&bucket), "waiting on providers");
+        });
+    }
+}
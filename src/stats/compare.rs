@@ -0,0 +1,620 @@
+use super::{group_name, reader::MergedStats, BucketGroupStats, Tags};
+use crate::CompareFormat;
+
+use config::ThresholdMetric;
+use serde::Serialize;
+use serde_json as json;
+use yansi::Paint;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+// the metrics which are compared besides the response time percentiles, and the default tolerance
+// for each. The tolerance of `error_rate` is in percentage points, the others are a percent change
+const DEFAULT_TOLERANCES: [(&str, f64); 3] =
+    [("mean", 10.0), ("error_rate", 1.0), ("throughput", 10.0)];
+
+// the default tolerance of each response time percentile, a percent change
+const DEFAULT_PERCENTILE_TOLERANCE: f64 = 10.0;
+
+#[derive(Serialize)]
+struct MetricComparison {
+    name: String,
+    baseline: f64,
+    candidate: f64,
+    // a percent change, or for `error_rate` the change in percentage points
+    change: f64,
+    tolerance: f64,
+    regressed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum GroupStatus {
+    Compared,
+    // only in the baseline
+    Missing,
+    // only in the candidate
+    New,
+}
+
+#[derive(Serialize)]
+struct GroupComparison {
+    tags: Tags,
+    status: GroupStatus,
+    metrics: Vec<MetricComparison>,
+}
+
+impl GroupComparison {
+    fn regressed(&self) -> bool {
+        self.metrics.iter().any(|m| m.regressed)
+    }
+}
+
+// compare the stats of a candidate run against a baseline, returning the comparison in the given
+// format and whether any metric regressed by more than its tolerance
+pub(super) fn compare(
+    baseline: &MergedStats,
+    candidate: &MergedStats,
+    tolerances: &BTreeMap<String, f64>,
+    format: CompareFormat,
+) -> (String, bool) {
+    let baseline_groups = group_totals(baseline);
+    let candidate_groups = group_totals(candidate);
+    let baseline_duration = baseline.end.saturating_sub(baseline.start()).max(1) as f64;
+    let candidate_duration = candidate.end.saturating_sub(candidate.start()).max(1) as f64;
+    let percentiles = compared_percentiles(baseline, candidate, tolerances);
+    let keys = alignment_keys(&baseline_groups, &candidate_groups);
+    let candidate_by_key: BTreeMap<_, _> = candidate_groups
+        .iter()
+        .map(|(tags, c)| (&keys[tags], c))
+        .collect();
+    let baseline_keys: BTreeSet<_> = baseline_groups.keys().map(|tags| &keys[tags]).collect();
+    let mut comparisons = Vec::new();
+    for (tags, b) in &baseline_groups {
+        let comparison = match candidate_by_key.get(&keys[tags]) {
+            Some(c) => GroupComparison {
+                tags: (*tags).clone(),
+                status: GroupStatus::Compared,
+                metrics: compare_group(
+                    (b, baseline_duration),
+                    (c, candidate_duration),
+                    &percentiles,
+                    tolerances,
+                ),
+            },
+            None => GroupComparison {
+                tags: (*tags).clone(),
+                status: GroupStatus::Missing,
+                metrics: Vec::new(),
+            },
+        };
+        comparisons.push(comparison);
+    }
+    for tags in candidate_groups.keys() {
+        if !baseline_keys.contains(&keys[tags]) {
+            comparisons.push(GroupComparison {
+                tags: (*tags).clone(),
+                status: GroupStatus::New,
+                metrics: Vec::new(),
+            });
+        }
+    }
+    let regressions = comparisons.iter().filter(|c| c.regressed()).count();
+    let output = match format {
+        CompareFormat::Human => create_human_output(&comparisons, regressions),
+        CompareFormat::Json => {
+            let output = json::json!({
+                "type": "comparison",
+                "regressed": regressions > 0,
+                "groups": comparisons,
+            });
+            format!("{}\n", output)
+        }
+        CompareFormat::Markdown => create_markdown_output(&comparisons, regressions),
+    };
+    (output, regressions > 0)
+}
+
+// the totals of each bucket group by its tags
+fn group_totals(stats: &MergedStats) -> BTreeMap<&Tags, BucketGroupStats> {
+    let mut totals = stats.totals();
+    stats
+        .tags
+        .iter()
+        .filter_map(|(tags, index)| totals.entries.remove(index).map(|entry| (tags, entry)))
+        .collect()
+}
+
+// the key each group is matched on between the two runs. That is its tags without `_id`, so an
+// endpoint added or removed in the candidate doesn't shift the others, unless those tags are shared
+// by more than one group in either run. Then the full tags are used
+fn alignment_keys<'a>(
+    baseline_groups: &BTreeMap<&'a Tags, BucketGroupStats>,
+    candidate_groups: &BTreeMap<&'a Tags, BucketGroupStats>,
+) -> BTreeMap<&'a Tags, Tags> {
+    let without_id = |tags: &Tags| {
+        let mut tags = tags.clone();
+        tags.remove("_id");
+        tags
+    };
+    let mut ambiguous = BTreeSet::new();
+    for groups in &[baseline_groups, candidate_groups] {
+        let mut seen = BTreeSet::new();
+        for tags in groups.keys() {
+            let key = without_id(tags);
+            if !seen.insert(key.clone()) {
+                ambiguous.insert(key);
+            }
+        }
+    }
+    baseline_groups
+        .keys()
+        .chain(candidate_groups.keys())
+        .map(|tags| {
+            let key = without_id(tags);
+            if ambiguous.contains(&key) {
+                (*tags, (*tags).clone())
+            } else {
+                (*tags, key)
+            }
+        })
+        .collect()
+}
+
+// the response time percentiles which are compared. Those each file was summarized with, and any
+// other percentile given a tolerance. Each is paired with its tolerance
+fn compared_percentiles(
+    baseline: &MergedStats,
+    candidate: &MergedStats,
+    tolerances: &BTreeMap<String, f64>,
+) -> Vec<(f64, f64)> {
+    let mut percentiles: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+    let from_files = baseline.percentiles.iter().chain(&candidate.percentiles);
+    for p in from_files {
+        percentiles.insert(format!("p{}", p), (*p, DEFAULT_PERCENTILE_TOLERANCE));
+    }
+    for (name, tolerance) in tolerances {
+        let p = match name.strip_prefix('p').and_then(|p| p.parse::<f64>().ok()) {
+            Some(p) => p,
+            None => continue,
+        };
+        percentiles.insert(format!("p{}", p), (p, *tolerance));
+    }
+    let mut percentiles: Vec<_> = percentiles.into_values().collect();
+    percentiles.sort_by(|a, b| a.0.total_cmp(&b.0));
+    percentiles
+}
+
+fn compare_group(
+    (baseline, baseline_duration): (&BucketGroupStats, f64),
+    (candidate, candidate_duration): (&BucketGroupStats, f64),
+    percentiles: &[(f64, f64)],
+    tolerances: &BTreeMap<String, f64>,
+) -> Vec<MetricComparison> {
+    let percentiles = percentiles.iter().map(|(p, tolerance)| {
+        let metric = ThresholdMetric::Percentile(*p);
+        (format!("p{}", p), Some(metric), *tolerance)
+    });
+    let others = DEFAULT_TOLERANCES.iter().map(|(name, default_tolerance)| {
+        let tolerance = tolerances.get(*name).copied().unwrap_or(*default_tolerance);
+        // throughput isn't a threshold metric
+        let metric = match *name {
+            "error_rate" => Some(ThresholdMetric::ErrorRate),
+            "mean" => Some(ThresholdMetric::Mean),
+            _ => None,
+        };
+        (name.to_string(), metric, tolerance)
+    });
+    percentiles
+        .chain(others)
+        .filter_map(|(name, metric, tolerance)| {
+            let (b, c) = match metric {
+                Some(metric) => (
+                    baseline.threshold_value(&metric)?,
+                    candidate.threshold_value(&metric)?,
+                ),
+                None => (
+                    baseline.rtt_histogram.len() as f64 / baseline_duration,
+                    candidate.rtt_histogram.len() as f64 / candidate_duration,
+                ),
+            };
+            let (change, regressed) = match name.as_str() {
+                "error_rate" => (c - b, c - b > tolerance),
+                "throughput" => {
+                    let change = percent_change(b, c);
+                    (change, change < -tolerance)
+                }
+                _ => {
+                    let change = percent_change(b, c);
+                    (change, change > tolerance)
+                }
+            };
+            Some(MetricComparison {
+                name,
+                baseline: round(b),
+                candidate: round(c),
+                change: round(change),
+                tolerance,
+                regressed,
+            })
+        })
+        .collect()
+}
+
+fn percent_change(baseline: f64, candidate: f64) -> f64 {
+    if baseline == 0.0 {
+        if candidate == 0.0 {
+            0.0
+        } else {
+            100.0
+        }
+    } else {
+        (candidate - baseline) / baseline * 100.0
+    }
+}
+
+fn round(n: f64) -> f64 {
+    (n * 1000.0).round() / 1000.0
+}
+
+fn unit(name: &str) -> &'static str {
+    match name {
+        "error_rate" => "%",
+        "throughput" => "/s",
+        _ => "ms",
+    }
+}
+
+// how a metric's change and tolerance are shown
+fn describe_change(metric: &MetricComparison) -> (String, String) {
+    if metric.name == "error_rate" {
+        (
+            format!("{:+} points", metric.change),
+            format!("{} points", metric.tolerance),
+        )
+    } else {
+        (
+            format!("{:+}%", metric.change),
+            format!("{}%", metric.tolerance),
+        )
+    }
+}
+
+fn create_human_output(comparisons: &[GroupComparison], regressions: usize) -> String {
+    let mut output = format!("{}", Paint::new("Comparison to baseline\n").bold());
+    for comparison in comparisons {
+        let _ = writeln!(
+            output,
+            "\n{}",
+            Paint::yellow(format!("- {}:", group_name(&comparison.tags))).dimmed()
+        );
+        match comparison.status {
+            GroupStatus::Missing => output.push_str("  not in the candidate\n"),
+            GroupStatus::New => output.push_str("  not in the baseline\n"),
+            GroupStatus::Compared => {
+                for metric in &comparison.metrics {
+                    let (change, tolerance) = describe_change(metric);
+                    let result = if metric.regressed {
+                        Paint::red("REGRESSED").bold()
+                    } else {
+                        Paint::green("ok")
+                    };
+                    let unit = unit(&metric.name);
+                    let _ = writeln!(
+                        output,
+                        "  {}: {}{} -> {}{} ({}, tolerance {}) {}",
+                        metric.name,
+                        metric.baseline,
+                        unit,
+                        metric.candidate,
+                        unit,
+                        change,
+                        tolerance,
+                        result
+                    );
+                }
+            }
+        }
+    }
+    let summary = if regressions > 0 {
+        Paint::red(format!("\n{} endpoint(s) regressed\n", regressions)).bold()
+    } else {
+        Paint::green("\nNo regressions\n".to_string()).bold()
+    };
+    let _ = write!(output, "{}", summary);
+    output
+}
+
+fn create_markdown_output(comparisons: &[GroupComparison], regressions: usize) -> String {
+    let mut output = String::from("## Load test comparison\n\n");
+    if regressions > 0 {
+        let _ = writeln!(
+            output,
+            "**{} endpoint(s) regressed** compared to the baseline.\n",
+            regressions
+        );
+    } else {
+        output.push_str("No regressions compared to the baseline.\n\n");
+    }
+    output.push_str(
+        "| Endpoint | Metric | Baseline | Candidate | Change | Tolerance | Result |\n\
+         | - | - | - | - | - | - | - |\n",
+    );
+    let escape = |s: String| s.replace('|', "\\|");
+    for comparison in comparisons {
+        let name = escape(group_name(&comparison.tags));
+        match comparison.status {
+            GroupStatus::Missing => {
+                let _ = writeln!(output, "| {} | | | | | | not in the candidate |", name);
+            }
+            GroupStatus::New => {
+                let _ = writeln!(output, "| {} | | | | | | not in the baseline |", name);
+            }
+            GroupStatus::Compared => {
+                for metric in &comparison.metrics {
+                    let (change, tolerance) = describe_change(metric);
+                    let result = if metric.regressed {
+                        ":x: regressed"
+                    } else {
+                        ":white_check_mark: ok"
+                    };
+                    let unit = unit(&metric.name);
+                    let _ = writeln!(
+                        output,
+                        "| {} | {} | {}{} | {}{} | {} | {} | {} |",
+                        name,
+                        metric.name,
+                        metric.baseline,
+                        unit,
+                        metric.candidate,
+                        unit,
+                        change,
+                        tolerance,
+                        result
+                    );
+                }
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{ResponseStat, StatKind, TimeBucket};
+    use maplit::btreemap;
+
+    use std::{sync::Arc, time::UNIX_EPOCH};
+
+    // stats for endpoints over one 60 second bucket. Each endpoint has an id, a url, a call for each
+    // of the round-trip times and a number of timeouts
+    fn create_groups(groups: &[(&str, &str, &[u64], usize)]) -> MergedStats {
+        let mut bucket = TimeBucket::new(0);
+        let mut merged = MergedStats::new();
+        for (index, (id, url, rtts, timeouts)) in groups.iter().enumerate() {
+            let tags: Tags = vec![
+                ("_id".to_string(), id.to_string()),
+                ("method".to_string(), "GET".to_string()),
+                ("url".to_string(), url.to_string()),
+            ]
+            .into_iter()
+            .collect();
+            let stats = rtts
+                .iter()
+                .map(|rtt| (StatKind::Response(200), Some(*rtt)))
+                .chain((0..*timeouts).map(|_| {
+                    let error = crate::error::RecoverableError::Timeout(UNIX_EPOCH);
+                    (StatKind::RecoverableError(error), None)
+                }));
+            for (kind, rtt) in stats {
+                let stat = ResponseStat {
+                    kind,
+                    rtt,
+                    time: UNIX_EPOCH,
+                    tags: Arc::new(tags.clone()),
+                };
+                bucket.append(stat, index, 3);
+            }
+            merged.tags.insert(tags, index);
+        }
+        merged.bucket_size = 60;
+        merged.end = 60;
+        merged.percentiles = config::default_percentiles();
+        merged.buckets.insert(0, bucket);
+        merged
+    }
+
+    // stats for a single endpoint
+    fn create_stats(id: &str, rtts: &[u64], timeouts: usize) -> MergedStats {
+        create_groups(&[(id, "http://localhost/", rtts, timeouts)])
+    }
+
+    #[test]
+    fn detects_regressions() {
+        let baseline = create_stats("0", &[1_000; 100], 0);
+        let same = create_stats("0", &[1_000; 100], 0);
+        let (_, regressed) = compare(&baseline, &same, &BTreeMap::new(), CompareFormat::Json);
+        assert!(!regressed);
+
+        // slower responses
+        let slower = create_stats("0", &[1_500; 100], 0);
+        let (output, regressed) =
+            compare(&baseline, &slower, &BTreeMap::new(), CompareFormat::Json);
+        assert!(regressed);
+        let output: json::Value = json::from_str(&output).unwrap();
+        let p50 = &output["groups"][0]["metrics"][0];
+        assert_eq!(p50["name"], "p50");
+        assert_eq!(p50["change"], 50.0);
+        assert_eq!(p50["regressed"], true);
+
+        // within a looser tolerance
+        let tolerances: BTreeMap<_, _> = vec!["p50", "p90", "p95", "p99", "p99.9", "mean"]
+            .into_iter()
+            .map(|name| (name.to_string(), 60.0))
+            .collect();
+        let (_, regressed) = compare(&baseline, &slower, &tolerances, CompareFormat::Json);
+        assert!(!regressed);
+
+        // fewer calls and more errors
+        let errors = create_stats("0", &[1_000; 80], 20);
+        let (output, regressed) = compare(
+            &baseline,
+            &errors,
+            &BTreeMap::new(),
+            CompareFormat::Markdown,
+        );
+        assert!(regressed);
+        assert!(output.contains("| GET http://localhost/ (_id=0) | error_rate | 0% | 20% | +20 points | 1 points | :x: regressed |"));
+        assert!(output.contains("| throughput | 1.667/s | 1.333/s | -20% | 10% | :x: regressed |"));
+    }
+
+    #[test]
+    fn compares_the_configured_percentiles() {
+        // 98 fast calls and 2 slow ones, which only show in the highest percentiles
+        let rtts = |slow| {
+            let mut rtts = vec![1_000; 98];
+            rtts.extend_from_slice(&[slow; 2]);
+            rtts
+        };
+        let mut baseline = create_stats("0", &rtts(1_000), 0);
+        baseline.percentiles = vec![50.0, 99.99];
+        let mut candidate = create_stats("0", &rtts(2_000), 0);
+        candidate.percentiles = vec![50.0, 99.99];
+
+        let (output, regressed) =
+            compare(&baseline, &candidate, &BTreeMap::new(), CompareFormat::Json);
+        assert!(regressed);
+        let output: json::Value = json::from_str(&output).unwrap();
+        let metrics: Vec<_> = output["groups"][0]["metrics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| {
+                (
+                    m["name"].as_str().unwrap(),
+                    m["regressed"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        let expected = vec![
+            ("p50", false),
+            ("p99.99", true),
+            ("mean", false),
+            ("error_rate", false),
+            ("throughput", false),
+        ];
+        assert_eq!(metrics, expected);
+
+        // a percentile given a tolerance is compared even when the files weren't summarized with it
+        baseline.percentiles = vec![50.0];
+        candidate.percentiles = vec![50.0];
+        let tolerances = btreemap! {
+            "p99.99".to_string() => 150.0,
+            "p99".to_string() => 5.0,
+        };
+        let (output, regressed) = compare(&baseline, &candidate, &tolerances, CompareFormat::Json);
+        assert!(regressed);
+        let output: json::Value = json::from_str(&output).unwrap();
+        let metrics: Vec<_> = output["groups"][0]["metrics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| {
+                (
+                    m["name"].as_str().unwrap(),
+                    m["tolerance"].as_f64().unwrap(),
+                )
+            })
+            .take(3)
+            .collect();
+        assert_eq!(
+            metrics,
+            vec![("p50", 10.0), ("p99", 5.0), ("p99.99", 150.0)]
+        );
+        let p99 = &output["groups"][0]["metrics"][1];
+        assert_eq!(p99["regressed"], true);
+        let p99_99 = &output["groups"][0]["metrics"][2];
+        assert_eq!(p99_99["regressed"], false);
+    }
+
+    #[test]
+    fn reports_unmatched_groups() {
+        let baseline = create_groups(&[("0", "http://localhost/a", &[1_000], 0)]);
+        let candidate = create_groups(&[("0", "http://localhost/b", &[1_000], 0)]);
+        let (output, regressed) =
+            compare(&baseline, &candidate, &BTreeMap::new(), CompareFormat::Json);
+        assert!(!regressed);
+        let output: json::Value = json::from_str(&output).unwrap();
+        assert_eq!(output["groups"][0]["status"], "missing");
+        assert_eq!(output["groups"][1]["status"], "new");
+    }
+
+    #[test]
+    fn aligns_groups_without_ids() {
+        let baseline = create_groups(&[
+            ("0", "http://localhost/a", &[1_000], 0),
+            ("1", "http://localhost/b", &[2_000], 0),
+        ]);
+        // an endpoint inserted before the others shifts their ids
+        let candidate = create_groups(&[
+            ("0", "http://localhost/new", &[1_500], 0),
+            ("1", "http://localhost/a", &[1_000], 0),
+            ("2", "http://localhost/b", &[2_000], 0),
+        ]);
+        let (output, regressed) =
+            compare(&baseline, &candidate, &BTreeMap::new(), CompareFormat::Json);
+        assert!(!regressed);
+        let output: json::Value = json::from_str(&output).unwrap();
+        let groups: Vec<_> = output["groups"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|g| {
+                (
+                    g["tags"]["url"].as_str().unwrap(),
+                    g["status"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        let expected = vec![
+            ("http://localhost/a", "compared"),
+            ("http://localhost/b", "compared"),
+            ("http://localhost/new", "new"),
+        ];
+        assert_eq!(groups, expected);
+
+        // endpoints with the same tags are told apart by their ids
+        let baseline = create_groups(&[
+            ("0", "http://localhost/", &[1_000], 0),
+            ("1", "http://localhost/", &[2_000], 0),
+        ]);
+        let candidate = create_groups(&[
+            ("0", "http://localhost/", &[1_000], 0),
+            ("1", "http://localhost/", &[2_000], 0),
+            ("2", "http://localhost/", &[1_500], 0),
+        ]);
+        let (output, regressed) =
+            compare(&baseline, &candidate, &BTreeMap::new(), CompareFormat::Json);
+        assert!(!regressed);
+        let output: json::Value = json::from_str(&output).unwrap();
+        let groups: Vec<_> = output["groups"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|g| {
+                (
+                    g["tags"]["_id"].as_str().unwrap(),
+                    g["status"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            vec![("0", "compared"), ("1", "compared"), ("2", "new")]
+        );
+    }
+}
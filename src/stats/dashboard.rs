@@ -0,0 +1,337 @@
+use super::{
+    group_name, rtt_percentiles, BucketGroupStats, ResponseStat, Tags, TargetRate, TimeBucket,
+};
+
+use channel::ChannelStats;
+use yansi::Paint;
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    time::Duration,
+};
+
+// how many seconds of stats the dashboard shows
+const WINDOW_SECS: usize = 10;
+// the most bucket groups listed in a section, so the dashboard fits on a screen
+const MAX_ROWS: usize = 20;
+
+// switch to the terminal's alternate screen and hide the cursor
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+// show the cursor and switch back to the main screen
+const LEAVE_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+// move the cursor to the top left, so each frame draws over the last
+const HOME: &str = "\x1b[H";
+// clear the rest of the line and the rest of the screen
+const CLEAR_LINE: &str = "\x1b[K";
+const CLEAR_BELOW: &str = "\x1b[J";
+
+// what the dashboard shows besides the stats it keeps
+pub(super) struct DashboardView<'a> {
+    // how far into the load pattern the test is
+    pub(super) elapsed: Duration,
+    // the percentiles of the response times to show
+    pub(super) percentiles: &'a [f64],
+    pub(super) providers: Vec<ChannelStats<'a>>,
+    pub(super) remaining: Option<Duration>,
+    pub(super) tags: &'a BTreeMap<Tags, usize>,
+    pub(super) target_rates: &'a [TargetRate],
+    pub(super) test_name: &'a str,
+}
+
+// the live view of a test shown with `--tui`. It keeps the stats of the last few seconds, one bucket
+// per second with the newest last
+pub(super) struct Dashboard {
+    seconds: VecDeque<TimeBucket>,
+    started: bool,
+}
+
+impl Dashboard {
+    pub(super) fn new() -> Self {
+        let mut seconds = VecDeque::with_capacity(WINDOW_SECS + 1);
+        seconds.push_back(TimeBucket::new(0));
+        Dashboard {
+            seconds,
+            started: false,
+        }
+    }
+
+    fn newest(&mut self) -> &mut TimeBucket {
+        self.seconds
+            .back_mut()
+            .expect("dashboard should always have a bucket")
+    }
+
+    pub(super) fn append(&mut self, stat: ResponseStat, index: usize, significant_digits: u8) {
+        self.newest().append(stat, index, significant_digits);
+    }
+
+    pub(super) fn record_sent(&mut self, id: &str) {
+        self.newest().throughput.entry(id.into()).or_default().sent += 1;
+    }
+
+    // draw a frame with the stats of the last few seconds, then start the next second. This is
+    // called once a second
+    pub(super) fn render(&mut self, view: &DashboardView<'_>) -> String {
+        let mut window = TimeBucket::new(0);
+        for second in &self.seconds {
+            window.combine(second);
+        }
+        let window_secs = self.seconds.len() as f64;
+        self.seconds.push_back(TimeBucket::new(0));
+        if self.seconds.len() > WINDOW_SECS {
+            self.seconds.pop_front();
+        }
+
+        let mut frame = String::new();
+        if !self.started {
+            self.started = true;
+            frame.push_str(ENTER_SCREEN);
+        }
+        frame.push_str(HOME);
+        let mut lines = create_lines(&window, window_secs, view);
+        lines.push(String::new());
+        for line in lines {
+            let _ = writeln!(frame, "{}{}", line, CLEAR_LINE);
+        }
+        frame.push_str(CLEAR_BELOW);
+        frame
+    }
+
+    // restore the terminal, if the dashboard was ever drawn
+    pub(super) fn finish(&mut self) -> Option<&'static str> {
+        if self.started {
+            self.started = false;
+            Some(LEAVE_SCREEN)
+        } else {
+            None
+        }
+    }
+}
+
+fn create_lines(window: &TimeBucket, window_secs: f64, view: &DashboardView<'_>) -> Vec<String> {
+    let per_sec = |n: u64| n as f64 / window_secs;
+    let mut lines = Vec::new();
+    let remaining = view
+        .remaining
+        .map(|r| format!(", {} remaining", clock(r)))
+        .unwrap_or_default();
+    lines.push(format!(
+        "{} {}",
+        Paint::new(format!("pewpew {}", view.test_name)).bold(),
+        Paint::new(format!("elapsed {}{}", clock(view.elapsed), remaining)).dimmed()
+    ));
+
+    let groups: Vec<_> = view
+        .tags
+        .iter()
+        .filter_map(|(tags, index)| window.entries.get(index).map(|s| (tags, s)))
+        .collect();
+
+    if !view.target_rates.is_empty() {
+        lines.push(String::new());
+        lines.push(section(&format!("Rate (last {}s)", window_secs)));
+        for rate in view.target_rates {
+            let target = rate
+                .load_pattern
+                .percent_at(view.elapsed)
+                .unwrap_or_default()
+                * rate.peak_load;
+            let throughput = window.throughput.get(&rate.id).cloned().unwrap_or_default();
+            let responses: u64 = groups
+                .iter()
+                .filter(|(tags, _)| tags.get("_id") == Some(&rate.id))
+                .map(|(_, stats)| stats.rtt_histogram.len())
+                .sum();
+            let sent = per_sec(throughput.sent);
+            let sent = if sent < target * 0.9 {
+                Paint::red(format!("{:.1}/s", sent))
+            } else {
+                Paint::green(format!("{:.1}/s", sent))
+            };
+            lines.push(format!(
+                "  {}  target {:.1}/s  sent {}  responses {:.1}/s",
+                Paint::yellow(endpoint_name(view.tags, &rate.id)).dimmed(),
+                target,
+                sent,
+                per_sec(responses),
+            ));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push(section(&format!("Response times (last {}s)", window_secs)));
+    if groups.is_empty() {
+        lines.push("  no responses yet".into());
+    }
+    for (tags, stats) in groups.iter().take(MAX_ROWS) {
+        let rtt_times = rtt_percentiles(&stats.rtt_histogram, view.percentiles)
+            .into_iter()
+            .map(|(name, value)| format!("  {} {}ms", name, value))
+            .collect::<String>();
+        lines.push(format!(
+            "  {}  {:.1} calls/s{}",
+            Paint::yellow(group_name(tags)).dimmed(),
+            per_sec(stats.rtt_histogram.len()),
+            rtt_times
+        ));
+    }
+    if groups.len() > MAX_ROWS {
+        lines.push(format!("  and {} more", groups.len() - MAX_ROWS));
+    }
+
+    lines.push(String::new());
+    lines.push(section(&format!("Errors (last {}s)", window_secs)));
+    let errors: Vec<_> = groups
+        .iter()
+        .filter_map(|(tags, stats)| {
+            let errors = error_breakdown(stats);
+            if errors.is_empty() {
+                None
+            } else {
+                Some((tags, errors))
+            }
+        })
+        .collect();
+    if errors.is_empty() {
+        lines.push(format!("  {}", Paint::green("none")));
+    }
+    for (tags, errors) in errors.iter().take(MAX_ROWS) {
+        lines.push(format!(
+            "  {}  {}",
+            Paint::yellow(group_name(tags)).dimmed(),
+            Paint::red(errors.join(", "))
+        ));
+    }
+    if errors.len() > MAX_ROWS {
+        lines.push(format!("  and {} more", errors.len() - MAX_ROWS));
+    }
+
+    if !view.providers.is_empty() {
+        lines.push(String::new());
+        lines.push(section("Providers"));
+        for stats in &view.providers {
+            lines.push(format!(
+                "  {}  {} {}/{}",
+                Paint::yellow(stats.provider).dimmed(),
+                bar(stats.len, stats.limit),
+                stats.len,
+                stats.limit
+            ));
+        }
+    }
+    lines
+}
+
+fn section(title: &str) -> String {
+    format!("{}", Paint::new(title).bold())
+}
+
+// the method and url of an endpoint, from the tags of its first bucket group
+fn endpoint_name(tags: &BTreeMap<Tags, usize>, id: &str) -> String {
+    tags.keys()
+        .find(|tags| tags.get("_id").map(String::as_str) == Some(id))
+        .map(|tags| {
+            let get = |k| tags.get(k).map(String::as_str).unwrap_or_default();
+            format!("{} {} (_id={})", get("method"), get("url"), id)
+        })
+        .unwrap_or_else(|| format!("_id={}", id))
+}
+
+// the error responses, timeouts and test errors of a bucket group
+fn error_breakdown(stats: &BucketGroupStats) -> Vec<String> {
+    let mut errors: Vec<_> = stats
+        .status_counts
+        .iter()
+        .filter(|(status, _)| **status >= 400)
+        .map(|(status, count)| format!("{}: {}", status, count))
+        .collect();
+    if stats.request_timeouts > 0 {
+        errors.push(format!("timeouts: {}", stats.request_timeouts));
+    }
+    for (error, count) in &stats.test_errors {
+        errors.push(format!("{}: {}", error, count));
+    }
+    errors
+}
+
+// how full a provider is
+fn bar(len: usize, limit: usize) -> String {
+    const WIDTH: usize = 20;
+    let filled = (len * WIDTH)
+        .checked_div(limit)
+        .unwrap_or_default()
+        .min(WIDTH);
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(WIDTH - filled))
+}
+
+fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatKind;
+
+    use std::{sync::Arc, time::UNIX_EPOCH};
+
+    #[test]
+    fn renders_rolling_window() {
+        Paint::disable();
+        let tags: Tags = vec![
+            ("_id".to_string(), "0".to_string()),
+            ("method".to_string(), "GET".to_string()),
+            ("url".to_string(), "http://localhost/".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let tag_indexes = vec![(tags.clone(), 0)].into_iter().collect();
+        let view = DashboardView {
+            elapsed: Duration::from_secs(75),
+            percentiles: &[50.0, 99.9],
+            providers: Vec::new(),
+            remaining: Some(Duration::from_secs(3_605)),
+            tags: &tag_indexes,
+            target_rates: &[],
+            test_name: "test",
+        };
+        let response = |status| ResponseStat {
+            kind: StatKind::Response(status),
+            rtt: Some(20_000),
+            time: UNIX_EPOCH,
+            tags: Arc::new(tags.clone()),
+        };
+
+        let mut dashboard = Dashboard::new();
+        dashboard.append(response(200), 0, 3);
+        dashboard.append(response(503), 0, 3);
+        let frame = dashboard.render(&view);
+        assert!(frame.starts_with(ENTER_SCREEN), "{}", frame);
+        assert!(frame.contains("elapsed 00:01:15, 01:00:05 remaining"));
+        assert!(
+            frame.contains(
+                "GET http://localhost/ (_id=0)  2.0 calls/s  p50 20.015ms  p99.9 20.015ms"
+            ),
+            "{}",
+            frame
+        );
+        assert!(frame.contains("503: 1"));
+
+        // the stats of each second stay on the dashboard for the whole window
+        for i in 1..WINDOW_SECS {
+            dashboard.append(response(200), 0, 3);
+            let frame = dashboard.render(&view);
+            assert!(frame.starts_with(HOME), "{}", frame);
+            let expected = format!("{:.1} calls/s", (i + 2) as f64 / (i + 1) as f64);
+            assert!(frame.contains(&expected), "{}", frame);
+            assert!(frame.contains("503: 1"), "{}", frame);
+        }
+        let frame = dashboard.render(&view);
+        assert!(!frame.contains("503"), "{}", frame);
+
+        assert_eq!(dashboard.finish(), Some(LEAVE_SCREEN));
+        assert_eq!(dashboard.finish(), None);
+    }
+}
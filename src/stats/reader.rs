@@ -0,0 +1,423 @@
+use super::{FileMessage, Tags, TimeBucket};
+use crate::error::TestError;
+
+use libflate::gzip;
+use serde_json as json;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    time::Duration,
+};
+
+// the contents of one or more stats files. Bucket groups from different files with the same tags
+// are merged together, as are buckets with the same time. A stats file has a JSON message on each
+// line, or one after another in older versions, and may be gzip or zstd compressed
+pub(super) struct MergedStats {
+    // the smallest bucket size of the files
+    pub(super) bucket_size: u64,
+    pub(super) buckets: BTreeMap<u64, TimeBucket>,
+    // the end of the last bucket
+    pub(super) end: u64,
+    // the percentiles of the response times the files were summarized with, smallest first
+    pub(super) percentiles: Vec<f64>,
+    // the most significant digits the files' histograms were kept with
+    pub(super) significant_digits: u8,
+    pub(super) tags: BTreeMap<Tags, usize>,
+    pub(super) tests: BTreeSet<String>,
+    file: FileState,
+}
+
+// what is known about the file currently being merged
+#[derive(Default)]
+struct FileState {
+    bucket_size: u64,
+    // the index of a bucket group in the file to its index in the merged stats
+    indexes: BTreeMap<usize, usize>,
+}
+
+impl MergedStats {
+    pub(super) fn new() -> Self {
+        MergedStats {
+            bucket_size: 0,
+            buckets: BTreeMap::new(),
+            end: 0,
+            file: FileState::default(),
+            percentiles: Vec::new(),
+            significant_digits: config::default_histogram_significant_digits(),
+            tags: BTreeMap::new(),
+            tests: BTreeSet::new(),
+        }
+    }
+
+    pub(super) fn read<P: AsRef<Path>>(files: &[P]) -> Result<Self, TestError> {
+        let mut merged = MergedStats::new();
+        for path in files {
+            merged.read_file(path.as_ref())?;
+        }
+        Ok(merged)
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<(), TestError> {
+        let file =
+            File::open(path).map_err(|e| TestError::CannotOpenFile(path.into(), e.into()))?;
+        decompress(BufReader::new(file))
+            .map_err(json::Error::io)
+            .and_then(|reader| self.merge(reader))
+            .map_err(|e| TestError::InvalidStatsFile(path.into(), e.into()))
+    }
+
+    // merge in the messages of a stats file. A file which is still being written, or whose test
+    // was killed, can end partway through a message, so a truncated last message is left out as
+    // long as there were complete messages before it
+    fn merge<R: Read>(&mut self, reader: R) -> Result<(), json::Error> {
+        self.file = FileState::default();
+        let mut any_read = false;
+        for message in json::Deserializer::from_reader(reader).into_iter() {
+            let message = match message {
+                Err(e) if any_read && is_truncated(&e) => break,
+                m => m.and_then(parse_message)?,
+            };
+            self.add_message(message);
+            any_read = true;
+        }
+        Ok(())
+    }
+
+    // add a message from the file currently being merged
+    pub(super) fn add_message(&mut self, message: FileMessage) {
+        match message {
+            FileMessage::Header(header) => {
+                self.tests.insert(header.test);
+                self.file.bucket_size = header.bucket_size;
+                if self.bucket_size == 0 || header.bucket_size < self.bucket_size {
+                    self.bucket_size = header.bucket_size;
+                }
+                for percentile in header.percentiles {
+                    if !self.percentiles.contains(&percentile) {
+                        self.percentiles.push(percentile);
+                    }
+                }
+                self.percentiles.sort_by(f64::total_cmp);
+                self.significant_digits = self
+                    .significant_digits
+                    .max(header.histogram_significant_digits);
+            }
+            FileMessage::Tags(file_tags) => {
+                let next_index = self.tags.len();
+                let index = *self.tags.entry(file_tags.tags).or_insert(next_index);
+                self.file.indexes.insert(file_tags.index, index);
+            }
+            FileMessage::Buckets(bucket) => {
+                self.end = self.end.max(bucket.time + self.file.bucket_size);
+                let merged = self
+                    .buckets
+                    .entry(bucket.time)
+                    .or_insert_with(|| TimeBucket::new(bucket.time));
+                merged.providers.extend(bucket.providers);
+                for (index, entry) in bucket.entries {
+                    // a bucket group should always have its tags written first
+                    if let Some(index) = self.file.indexes.get(&index) {
+                        match merged.entries.get_mut(index) {
+                            Some(stats) => stats.combine(&entry),
+                            None => {
+                                merged.entries.insert(*index, entry);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // the time of the first bucket
+    pub(super) fn start(&self) -> u64 {
+        self.buckets.keys().next().copied().unwrap_or(self.end)
+    }
+
+    // only keep the bucket groups whose tags pass the filter, and the buckets between `from` and
+    // `to`, which are relative to the start of the stats
+    pub(super) fn filter(
+        &mut self,
+        tag_filter: impl Fn(&Tags) -> bool,
+        from: Option<Duration>,
+        to: Option<Duration>,
+    ) {
+        let removed: BTreeSet<_> = self
+            .tags
+            .iter()
+            .filter(|(tags, _)| !tag_filter(tags))
+            .map(|(_, index)| *index)
+            .collect();
+        self.tags.retain(|_, index| !removed.contains(index));
+        let start = self.start();
+        let from = from.map(|d| start + d.as_secs()).unwrap_or(start);
+        let to = to.map(|d| start + d.as_secs()).unwrap_or(self.end);
+        let bucket_size = self.bucket_size;
+        self.buckets
+            .retain(|time, _| *time >= from && *time + bucket_size <= to);
+        for bucket in self.buckets.values_mut() {
+            bucket.entries.retain(|index, _| !removed.contains(index));
+        }
+        self.buckets.retain(|_, bucket| !bucket.entries.is_empty());
+        self.end = self
+            .buckets
+            .keys()
+            .last()
+            .map(|time| (time + self.bucket_size).min(self.end).max(from))
+            .unwrap_or(from);
+    }
+
+    // all the buckets combined into one
+    pub(super) fn totals(&self) -> TimeBucket {
+        let mut totals = TimeBucket::new(self.start());
+        for bucket in self.buckets.values() {
+            totals.combine(bucket);
+        }
+        totals
+    }
+}
+
+// a reader of a stats file which decompresses it, if it was compressed
+fn decompress<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn Read>> {
+    const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+    const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+    let start = reader.fill_buf()?;
+    let reader: Box<dyn Read> = if start.starts_with(GZIP_MAGIC) {
+        Box::new(gzip::MultiDecoder::new(reader)?)
+    } else if start.starts_with(ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else {
+        Box::new(reader)
+    };
+    Ok(reader)
+}
+
+// whether the error is from the file ending partway through a message or a compressed block
+fn is_truncated(e: &json::Error) -> bool {
+    e.is_eof() || e.io_error_kind() == Some(io::ErrorKind::UnexpectedEof)
+}
+
+// `FileMessage` is untagged, and serde can't deserialize the numeric map keys of a bucket through an
+// untagged enum, so which kind of message it is is decided here instead
+fn parse_message(value: json::Value) -> Result<FileMessage, json::Error> {
+    if value.get("entries").is_some() {
+        json::from_value(value).map(FileMessage::Buckets)
+    } else if value.get("index").is_some() {
+        json::from_value(value).map(FileMessage::Tags)
+    } else {
+        json::from_value(value).map(FileMessage::Header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{FileHeader, FileTags, ResponseStat, StatKind};
+
+    use std::{sync::Arc, time::UNIX_EPOCH};
+
+    fn create_tags(id: &str) -> Tags {
+        vec![
+            ("_id".to_string(), id.to_string()),
+            ("method".to_string(), "GET".to_string()),
+            ("url".to_string(), "http://localhost/".to_string()),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    // a stats file with a bucket group for each of the ids, where the bucket at each time has a
+    // response with the given status
+    fn create_file(ids: &[&str], buckets: &[(u64, u16)]) -> Vec<u8> {
+        let mut messages = vec![FileMessage::Header(FileHeader {
+            test: "test".into(),
+            bin: "0.0.0".into(),
+            bucket_size: 60,
+            histogram_significant_digits: 3,
+            percentiles: vec![50.0, 99.99],
+        })];
+        for (index, id) in ids.iter().enumerate() {
+            messages.push(FileMessage::Tags(FileTags {
+                index,
+                tags: create_tags(id),
+            }));
+        }
+        for (time, status) in buckets {
+            let mut bucket = TimeBucket::new(*time);
+            for (index, id) in ids.iter().enumerate() {
+                let stat = ResponseStat {
+                    kind: StatKind::Response(*status),
+                    rtt: Some(1_000),
+                    time: UNIX_EPOCH,
+                    tags: Arc::new(create_tags(id)),
+                };
+                bucket.append(stat, index, 3);
+            }
+            messages.push(FileMessage::Buckets(bucket));
+        }
+        messages
+            .iter()
+            .map(|m| json::to_string(m).unwrap())
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    #[test]
+    fn merges_stats_files() {
+        let mut stats = MergedStats::new();
+        let file1 = create_file(&["0", "1"], &[(60, 200), (120, 200)]);
+        let file2 = create_file(&["1"], &[(120, 500), (180, 500)]);
+        stats.merge(file1.as_slice()).unwrap();
+        stats.merge(file2.as_slice()).unwrap();
+
+        assert_eq!(stats.tests.len(), 1);
+        assert_eq!(stats.bucket_size, 60);
+        assert_eq!(stats.percentiles, vec![50.0, 99.99]);
+        assert_eq!(stats.start(), 60);
+        assert_eq!(stats.end, 240);
+        assert_eq!(stats.tags.len(), 2);
+        let index = stats.tags[&create_tags("1")];
+        let totals = stats.totals();
+        let entry = &totals.entries[&index];
+        assert_eq!(entry.rtt_histogram.len(), 4);
+        let expected: BTreeMap<_, _> = vec![(200, 2), (500, 2)].into_iter().collect();
+        assert_eq!(entry.status_counts, expected);
+
+        stats.filter(
+            |tags| tags.get("_id").map(String::as_str) == Some("0"),
+            Some(Duration::from_secs(60)),
+            None,
+        );
+        assert_eq!(stats.tags.len(), 1);
+        assert_eq!(stats.buckets.len(), 1);
+        assert_eq!(stats.start(), 120);
+        assert_eq!(stats.end, 180);
+        let index = stats.tags[&create_tags("0")];
+        assert_eq!(stats.totals().entries[&index].rtt_histogram.len(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let mut stats = MergedStats::new();
+        assert!(stats.merge(&b"{\"time\":5"[..]).is_err());
+        assert!(stats.merge(&b"{\"time\":5,\"entries\":[]}"[..]).is_err());
+    }
+
+    #[test]
+    fn keeps_provider_stats() {
+        let mut file = create_file(&["0"], &[]);
+        for (time, len) in [(60, 0), (120, 8)] {
+            let bucket = json::json!({
+                "time": time,
+                "entries": {},
+                "providers": {
+                    "ids": {
+                        "len": len,
+                        "limit": 10,
+                        "receiverCount": 2,
+                        "senderCount": 1,
+                        "onDemandCount": 0,
+                        "waitingReceiverCount": if len == 0 { 2 } else { 0 },
+                        "waitingSenderCount": if len == 0 { 0 } else { 1 }
+                    }
+                }
+            });
+            file.extend(bucket.to_string().into_bytes());
+        }
+        // a stats file from an older version has no waiting counts
+        let bucket = json::json!({
+            "time": 180,
+            "entries": {},
+            "providers": {
+                "ids": {
+                    "len": 8,
+                    "limit": 10,
+                    "receiverCount": 2,
+                    "senderCount": 1,
+                    "onDemandCount": 0
+                }
+            }
+        });
+        file.extend(bucket.to_string().into_bytes());
+        let mut stats = MergedStats::new();
+        stats.merge(file.as_slice()).unwrap();
+
+        assert_eq!(stats.buckets[&60].providers["ids"].len, 0);
+        assert_eq!(
+            stats.buckets[&60].providers["ids"].waiting_receiver_count,
+            2
+        );
+        assert_eq!(stats.buckets[&120].providers["ids"].receiver_count, 2);
+        assert_eq!(stats.buckets[&120].providers["ids"].waiting_sender_count, 1);
+        assert_eq!(
+            stats.buckets[&180].providers["ids"].waiting_receiver_count,
+            0
+        );
+        // the totals have the latest state of each provider
+        assert_eq!(stats.totals().providers["ids"].len, 8);
+    }
+
+    #[test]
+    fn reads_a_stats_file_being_written() {
+        use crate::{line_writer::FileWriter, StatsFileCompression};
+        use std::io::Write;
+
+        let file = create_file(&["0"], &[(60, 200), (120, 200)]);
+        let lines = String::from_utf8(file).unwrap().replace("}{\"", "}\n{\"") + "\n";
+        let compressions = [
+            None,
+            Some(StatsFileCompression::Gzip),
+            Some(StatsFileCompression::Zstd),
+        ];
+        for (i, compression) in compressions.iter().copied().enumerate() {
+            let path = std::env::temp_dir().join(format!(
+                "pewpew-truncated-{}-{}.json",
+                std::process::id(),
+                i
+            ));
+            let mut writer = FileWriter::new(File::create(&path).unwrap(), compression).unwrap();
+            writer.write_all(lines.as_bytes()).unwrap();
+            writer.flush().unwrap();
+            // the start of another bucket, with the file left unfinished as if the test was killed
+            writer.write_all(b"{\"time\":180,\"entr").unwrap();
+            std::mem::forget(writer);
+
+            let stats = MergedStats::read(&[&path]);
+            let _ = std::fs::remove_file(&path);
+            let stats = stats.unwrap();
+            assert_eq!(stats.buckets.len(), 2, "{:?}", compression);
+            let index = stats.tags[&create_tags("0")];
+            assert_eq!(stats.totals().entries[&index].rtt_histogram.len(), 2);
+        }
+    }
+
+    #[test]
+    fn reads_each_file_format() {
+        use std::io::{Cursor, Write};
+
+        let legacy = create_file(&["0"], &[(60, 200), (120, 200)]);
+        // the same messages with each on its own line
+        let lines = String::from_utf8(legacy.clone())
+            .unwrap()
+            .replace("}{\"", "}\n{\"")
+            + "\n";
+        let lines = lines.into_bytes();
+        let mut gzip = gzip::Encoder::new(Vec::new()).unwrap();
+        gzip.write_all(&lines).unwrap();
+        let gzip = gzip.finish().into_result().unwrap();
+        let zstd = zstd::encode_all(lines.as_slice(), 0).unwrap();
+
+        for file in [legacy, lines, gzip, zstd] {
+            let reader = decompress(BufReader::new(Cursor::new(file))).unwrap();
+            let mut stats = MergedStats::new();
+            stats.merge(reader).unwrap();
+            assert_eq!(stats.tests.len(), 1);
+            assert_eq!(stats.buckets.len(), 2);
+            let index = stats.tags[&create_tags("0")];
+            assert_eq!(stats.totals().entries[&index].rtt_histogram.len(), 2);
+        }
+    }
+}
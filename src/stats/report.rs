@@ -0,0 +1,473 @@
+use super::{
+    create_date_diff, join_tags, other_tags, reader::MergedStats, rtt_percentiles,
+    threshold_results, BucketGroupStats, ProviderStats, Tags,
+};
+use crate::util::xml_escape;
+
+use chrono::{Local, TimeZone};
+use config::Threshold;
+
+use std::{collections::BTreeSet, fmt::Write};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 960px; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.2em; margin-top: 2em; border-bottom: 1px solid #ccc; }
+h3 { font-size: 1em; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }
+.passed { color: #2ca02c; }
+.failed { color: #d62728; font-weight: bold; }
+.legend span { margin-right: 1em; }
+.swatch { display: inline-block; width: 0.8em; height: 0.8em; margin-right: 0.3em; }
+svg text { font-size: 11px; fill: #555; }
+pre { background: #f5f5f5; padding: 1em; overflow: auto; }
+";
+
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+// create a single, self-contained HTML page for the stats. The charts are inline SVGs so the page
+// works offline
+pub(super) fn create_html_report(
+    stats: &MergedStats,
+    config: Option<&str>,
+    thresholds: &[Threshold],
+) -> String {
+    let tests = stats.tests.iter().cloned().collect::<Vec<_>>().join(", ");
+    let totals = stats.totals();
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{} - pewpew report</title>\n<style>{}</style>\n</head>\n<body>\n\
+         <h1>Load test report: {}</h1>\n<p>{}, bucket size {}s, pewpew {}</p>\n",
+        xml_escape(&tests),
+        STYLE,
+        xml_escape(&tests),
+        xml_escape(&create_date_diff(stats.start(), stats.end)),
+        stats.bucket_size,
+        clap::crate_version!()
+    );
+
+    let results = threshold_results(
+        thresholds,
+        &stats.tags,
+        &totals,
+        stats.significant_digits,
+        true,
+    );
+    if !results.is_empty() {
+        html.push_str(
+            "<h2>Thresholds</h2>\n<table>\n\
+             <tr><th>Condition</th><th>Tags</th><th>Value</th><th>Result</th></tr>\n",
+        );
+        for (threshold, measured, passed) in results {
+            let tags = join_tags(&threshold.tags, ", ");
+            let measured = match measured {
+                Some(m) => format!("{}{}", (m * 100.0).round() / 100.0, threshold.metric.unit()),
+                None => "no data".into(),
+            };
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td>{}</tr>",
+                xml_escape(&threshold.condition),
+                xml_escape(&tags),
+                measured,
+                result_cell(passed)
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    for (tags, index) in &stats.tags {
+        if let Some(group) = totals.entries.get(index) {
+            html.push_str(&create_group_section(stats, tags, *index, group));
+        }
+    }
+    if stats.tags.is_empty() || totals.entries.is_empty() {
+        html.push_str("<p>no data</p>\n");
+    }
+    html.push_str(&create_providers_section(stats));
+
+    if let Some(config) = config {
+        let _ = write!(
+            html,
+            "<h2>Test config</h2>\n<pre>{}</pre>\n",
+            xml_escape(config)
+        );
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// the summary, checks and charts for a bucket group
+fn create_group_section(
+    stats: &MergedStats,
+    tags: &Tags,
+    index: usize,
+    group: &BucketGroupStats,
+) -> String {
+    let method = tags.get("method").map(String::as_str).unwrap_or_default();
+    let url = tags.get("url").map(String::as_str).unwrap_or_default();
+    let other_tags = join_tags(other_tags(tags), ", ");
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<h2>{} {}</h2>\n<p>{}</p>\n",
+        xml_escape(method),
+        xml_escape(url),
+        xml_escape(&other_tags)
+    );
+
+    let rtt = group.rtt_summary();
+    let status_counts = group
+        .status_counts
+        .iter()
+        .map(|(status, count)| format!("{}: {}", status, count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let _ = write!(
+        html,
+        "<table>\n\
+         <tr><th>Calls</th><th>Status counts</th><th>Timeouts</th><th>Test errors</th>\
+         <th>Retries</th></tr>\n\
+         <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n</table>\n",
+        group.rtt_histogram.len(),
+        status_counts,
+        group.request_timeouts,
+        group.test_errors.values().sum::<u64>(),
+        group.retries,
+    );
+    // the percentiles the stats files were summarized with
+    let percentiles = rtt_percentiles(&group.rtt_histogram, &stats.percentiles);
+    let (percentile_names, percentile_values): (String, String) = percentiles
+        .into_iter()
+        .map(|(name, value)| {
+            (
+                format!("<th>{}</th>", name),
+                format!("<td>{}ms</td>", value),
+            )
+        })
+        .unzip();
+    let _ = write!(
+        html,
+        "<table>\n\
+         <tr>{}<th>min</th><th>max</th><th>avg</th><th>std. dev</th></tr>\n\
+         <tr>{}<td>{}ms</td><td>{}ms</td><td>{}ms</td><td>{}ms</td></tr>\n</table>\n",
+        percentile_names, percentile_values, rtt.min, rtt.max, rtt.mean, rtt.stddev
+    );
+    if !group.test_errors.is_empty() {
+        html.push_str("<table>\n<tr><th>Test error</th><th>Count</th></tr>\n");
+        for (error, count) in &group.test_errors {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                xml_escape(error),
+                count
+            );
+        }
+        html.push_str("</table>\n");
+    }
+    if !group.checks.is_empty() {
+        html.push_str(
+            "<h3>Checks</h3>\n<table>\n\
+             <tr><th>Check</th><th>Passed</th><th>Failed</th><th>Result</th></tr>\n",
+        );
+        for (name, counts) in &group.checks {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td>{}</tr>",
+                xml_escape(name),
+                counts.passed,
+                counts.failed,
+                result_cell(counts.failed == 0)
+            );
+        }
+        html.push_str("</table>\n");
+    }
+    let sla_breaches = stats
+        .buckets
+        .values()
+        .filter(|bucket| {
+            bucket
+                .entries
+                .get(&index)
+                .map(|entry| !entry.sla_breaches.is_empty())
+                .unwrap_or_default()
+        })
+        .count();
+    if sla_breaches > 0 {
+        let _ = writeln!(
+            html,
+            "<p class=\"failed\">SLA breached in {} of {} buckets</p>",
+            sla_breaches,
+            stats.buckets.len()
+        );
+    }
+
+    // the charts have a point for every bucket, including those without any data for this group
+    let bucket_size = stats.bucket_size.max(1) as f64;
+    let empty = BucketGroupStats::default();
+    let entries: Vec<_> = stats
+        .buckets
+        .values()
+        .map(|bucket| (bucket.time, bucket.entries.get(&index).unwrap_or(&empty)))
+        .collect();
+    let times: Vec<_> = entries.iter().map(|(time, _)| *time).collect();
+    let per_second = |f: &dyn Fn(&BucketGroupStats) -> u64| -> Vec<f64> {
+        entries
+            .iter()
+            .map(|(_, entry)| f(entry) as f64 / bucket_size)
+            .collect()
+    };
+    let throughput = vec![
+        (
+            "calls".to_string(),
+            per_second(&|entry| entry.rtt_histogram.len()),
+        ),
+        (
+            "errors".to_string(),
+            per_second(&|entry| entry.request_timeouts + entry.test_errors.values().sum::<u64>()),
+        ),
+    ];
+    html.push_str(&line_chart("Throughput", "/s", &times, &throughput));
+
+    let latency: Vec<_> = stats
+        .percentiles
+        .iter()
+        .map(|p| {
+            let values = entries
+                .iter()
+                .map(|(_, entry)| entry.rtt_histogram.value_at_quantile(p / 100.0) as f64 / 1_000.0)
+                .collect();
+            (format!("p{}", p), values)
+        })
+        .collect();
+    html.push_str(&line_chart("Latency", "ms", &times, &latency));
+
+    // every status code which isn't a success or redirect, along with timeouts and test errors
+    let mut errors: Vec<_> = group
+        .status_counts
+        .keys()
+        .filter(|status| **status >= 400)
+        .map(|status| {
+            let counts = entries
+                .iter()
+                .map(|(_, entry)| entry.status_counts.get(status).copied().unwrap_or(0) as f64)
+                .collect();
+            (status.to_string(), counts)
+        })
+        .collect();
+    if group.request_timeouts > 0 {
+        let counts = entries
+            .iter()
+            .map(|(_, entry)| entry.request_timeouts as f64)
+            .collect();
+        errors.push(("timeouts".into(), counts));
+    }
+    if !group.test_errors.is_empty() {
+        let counts = entries
+            .iter()
+            .map(|(_, entry)| entry.test_errors.values().sum::<u64>() as f64)
+            .collect();
+        errors.push(("test errors".into(), counts));
+    }
+    if !errors.is_empty() {
+        html.push_str(&line_chart("Errors", "", &times, &errors));
+    }
+    html
+}
+
+// how full each provider was over the test. An empty provider delays the endpoints which use it
+fn create_providers_section(stats: &MergedStats) -> String {
+    let names: BTreeSet<_> = stats
+        .buckets
+        .values()
+        .flat_map(|bucket| bucket.providers.keys())
+        .collect();
+    if names.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from(
+        "<h2>Providers</h2>\n<table>\n\
+         <tr><th>Provider</th><th>Limit</th><th>Min length</th><th>Empty when a bucket closed</th>\
+         <th>Max senders</th><th>Max waiting senders</th><th>Max receivers</th>\
+         <th>Max waiting receivers</th></tr>\n",
+    );
+    let times: Vec<_> = stats.buckets.keys().copied().collect();
+    let mut lengths = Vec::new();
+    for name in names {
+        let snapshots: Vec<_> = stats
+            .buckets
+            .values()
+            .filter_map(|bucket| bucket.providers.get(name))
+            .collect();
+        let max = |f: fn(&ProviderStats) -> usize| snapshots.iter().map(|s| f(s)).max();
+        let min_len = snapshots.iter().map(|s| s.len).min().unwrap_or_default();
+        let empty = snapshots.iter().filter(|s| s.len == 0).count();
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{} of {}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{}</td></tr>",
+            xml_escape(name),
+            max(|s| s.limit).unwrap_or_default(),
+            min_len,
+            empty,
+            snapshots.len(),
+            max(|s| s.sender_count).unwrap_or_default(),
+            max(|s| s.waiting_sender_count).unwrap_or_default(),
+            max(|s| s.receiver_count).unwrap_or_default(),
+            max(|s| s.waiting_receiver_count).unwrap_or_default()
+        );
+        let series = stats
+            .buckets
+            .values()
+            .map(|bucket| bucket.providers.get(name).map_or(0.0, |s| s.len as f64))
+            .collect();
+        lengths.push((name.clone(), series));
+    }
+    html.push_str("</table>\n");
+    html.push_str(&line_chart("Length", "", &times, &lengths));
+    html
+}
+
+fn result_cell(passed: bool) -> &'static str {
+    if passed {
+        "<td class=\"passed\">passed</td>"
+    } else {
+        "<td class=\"failed\">FAILED</td>"
+    }
+}
+
+// an SVG line chart of each series over time, with a legend
+fn line_chart(title: &str, unit: &str, times: &[u64], series: &[(String, Vec<f64>)]) -> String {
+    const WIDTH: f64 = 720.0;
+    const HEIGHT: f64 = 200.0;
+    const LEFT: f64 = 60.0;
+    const TOP: f64 = 10.0;
+    const BOTTOM: f64 = 20.0;
+    const RIGHT: f64 = 10.0;
+    let plot_width = WIDTH - LEFT - RIGHT;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let max = series
+        .iter()
+        .flat_map(|(_, values)| values.iter().copied())
+        .fold(0.0, f64::max);
+    let max = if max > 0.0 { max } else { 1.0 };
+    let (first, last) = match (times.first(), times.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => (0, 0),
+    };
+    let x = |time: u64| {
+        if last > first {
+            LEFT + (time - first) as f64 / (last - first) as f64 * plot_width
+        } else {
+            LEFT + plot_width / 2.0
+        }
+    };
+    let y = |value: f64| TOP + plot_height - value / max * plot_height;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<h3>{}</h3>\n<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         viewBox=\"0 0 {} {}\">\n",
+        xml_escape(title),
+        WIDTH,
+        HEIGHT,
+        WIDTH,
+        HEIGHT
+    );
+    for i in 0..=4 {
+        let value = max * i as f64 / 4.0;
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\" stroke=\"#ddd\"/>\
+             <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}{}</text>",
+            LEFT,
+            WIDTH - RIGHT,
+            LEFT - 4.0,
+            y(value) + 4.0,
+            (value * 100.0).round() / 100.0,
+            unit,
+            y = y(value)
+        );
+    }
+    let _ = writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{}\">{}</text><text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+        LEFT,
+        HEIGHT - 4.0,
+        format_time(first),
+        WIDTH - RIGHT,
+        HEIGHT - 4.0,
+        format_time(last)
+    );
+    for (i, (_, values)) in series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let points = times
+            .iter()
+            .zip(values)
+            .map(|(time, value)| format!("{:.1},{:.1}", x(*time), y(*value)))
+            .collect::<Vec<_>>();
+        if points.len() == 1 {
+            let _ = writeln!(
+                svg,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"{}\"/>",
+                x(first),
+                y(values[0]),
+                color
+            );
+        } else {
+            let _ = writeln!(
+                svg,
+                "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>",
+                color,
+                points.join(" ")
+            );
+        }
+    }
+    svg.push_str("</svg>\n<div class=\"legend\">");
+    for (i, (name, _)) in series.iter().enumerate() {
+        let _ = write!(
+            svg,
+            "<span><span class=\"swatch\" style=\"background: {}\"></span>{}</span>",
+            COLORS[i % COLORS.len()],
+            xml_escape(name)
+        );
+    }
+    svg.push_str("</div>\n");
+    svg
+}
+
+fn format_time(time: u64) -> String {
+    Local
+        .timestamp_opt(time as i64, 0)
+        .single()
+        .map(|t| t.format("%T").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charts_each_series() {
+        let series = vec![
+            ("a".to_string(), vec![0.0, 2.0]),
+            ("<b>".to_string(), vec![1.0, 1.0]),
+        ];
+        let svg = line_chart("Chart", "ms", &[10, 20], &series);
+        assert!(svg.contains("<h3>Chart</h3>"));
+        assert!(svg.contains("points=\"60.0,180.0 710.0,10.0\""));
+        assert!(svg.contains("points=\"60.0,95.0 710.0,95.0\""));
+        assert!(svg.contains(">2ms</text>"));
+        assert!(svg.contains("&lt;b&gt;"));
+
+        let svg = line_chart("Chart", "", &[10], &series[..1]);
+        assert!(svg.contains("<circle cx=\"385.0\" cy=\"180.0\""));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::StatsFileCompression;
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+// when to start a new stats file and how much has been written to the current one. Each file
+// starts with the header and tags so it can be read on its own
+pub(super) struct Rotation {
+    interval: Option<Duration>,
+    size: Option<u64>,
+    // how many times the stats file has been rotated
+    part: usize,
+    buckets: usize,
+    bytes: u64,
+    started: Instant,
+}
+
+impl Rotation {
+    pub(super) fn new(size: Option<u64>, interval: Option<Duration>) -> Self {
+        Rotation {
+            interval,
+            size,
+            part: 0,
+            buckets: 0,
+            bytes: 0,
+            started: Instant::now(),
+        }
+    }
+
+    // count a message written to the current file
+    pub(super) fn add_message(&mut self, bytes: usize, is_bucket: bool) {
+        self.bytes += bytes as u64;
+        if is_bucket {
+            self.buckets += 1;
+        }
+    }
+
+    // whether a new file should be started before the next bucket is written. A file always has
+    // at least one bucket
+    pub(super) fn is_due(&self) -> bool {
+        self.buckets > 0
+            && (self.size.is_some_and(|size| self.bytes >= size)
+                || self
+                    .interval
+                    .is_some_and(|interval| self.started.elapsed() >= interval))
+    }
+
+    // start counting for the next file, returning its path
+    pub(super) fn next_file(
+        &mut self,
+        path: &Path,
+        compression: Option<StatsFileCompression>,
+    ) -> PathBuf {
+        self.part += 1;
+        self.buckets = 0;
+        self.bytes = 0;
+        self.started = Instant::now();
+        rotated_path(path, self.part, compression)
+    }
+}
+
+// the path of the stats file after it has been rotated `part` times. The number goes before the
+// extension, such as `stats-test-123.2.json.gz`
+fn rotated_path(path: &Path, part: usize, compression: Option<StatsFileCompression>) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let compressed_extension = compression
+        .map(|c| format!(".{}", c.extension()))
+        .filter(|e| file_name.ends_with(e.as_str()))
+        .unwrap_or_default();
+    let name = &file_name[..file_name.len() - compressed_extension.len()];
+    let name_path = Path::new(name);
+    let rotated = match (name_path.file_stem(), name_path.extension()) {
+        (Some(stem), Some(extension)) => format!(
+            "{}.{}.{}{}",
+            stem.to_string_lossy(),
+            part,
+            extension.to_string_lossy(),
+            compressed_extension
+        ),
+        _ => format!("{}.{}{}", name, part, compressed_extension),
+    };
+    path.with_file_name(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated_paths() {
+        let values = vec![
+            ("stats-test-123.json", None, "stats-test-123.2.json"),
+            (
+                "results/stats-test-123.json.gz",
+                Some(StatsFileCompression::Gzip),
+                "results/stats-test-123.2.json.gz",
+            ),
+            (
+                "stats.json.zst",
+                Some(StatsFileCompression::Zstd),
+                "stats.2.json.zst",
+            ),
+            ("stats", Some(StatsFileCompression::Gzip), "stats.2"),
+            ("my.test.json", None, "my.test.2.json"),
+        ];
+        for (path, compression, expected) in values {
+            let rotated = rotated_path(Path::new(path), 2, compression);
+            assert_eq!(rotated, PathBuf::from(expected), "{}", path);
+        }
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let mut rotation = Rotation::new(Some(100), None);
+        rotation.add_message(150, false);
+        // a file always gets at least one bucket
+        assert!(!rotation.is_due());
+        rotation.add_message(10, true);
+        assert!(rotation.is_due());
+
+        let path = rotation.next_file(Path::new("stats.json"), None);
+        assert_eq!(path, PathBuf::from("stats.1.json"));
+        rotation.add_message(50, true);
+        assert!(!rotation.is_due());
+    }
+
+    #[test]
+    fn rotates_by_interval() {
+        let mut rotation = Rotation::new(None, Some(Duration::from_secs(0)));
+        assert!(!rotation.is_due());
+        rotation.add_message(10, true);
+        assert!(rotation.is_due());
+
+        let mut rotation = Rotation::new(None, Some(Duration::from_secs(3_600)));
+        rotation.add_message(10, true);
+        assert!(!rotation.is_due());
+    }
+}
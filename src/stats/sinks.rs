@@ -0,0 +1,755 @@
+use super::{rtt_percentiles, BucketGroupStats, ResponseStat, StatKind, Tags, TimeBucket};
+use crate::error::{RecoverableError, TestError};
+
+use config::{MetricKind, StatsSink, StatsSinkFormat, StatsSinkProtocol};
+use futures::{
+    channel::mpsc::{self as futures_channel, UnboundedReceiver, UnboundedSender},
+    future::join_all,
+    StreamExt,
+};
+use hyper::{header::CONTENT_TYPE, Body, Client, Method, Request, Uri};
+use hyper_tls::HttpsConnector;
+use serde_json as json;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket},
+    task::JoinHandle,
+    time::{self, Duration},
+};
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    mem,
+    net::{SocketAddr, ToSocketAddrs},
+    time::UNIX_EPOCH,
+};
+
+const MICROS_TO_MS: f64 = 1_000.0;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+// keep datagrams small enough to not be fragmented
+const MAX_DATAGRAM_SIZE: usize = 1_400;
+// how long to wait at the end of a test for the last stats to be pushed
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the destinations stats are pushed to while a test runs. Pushing is best effort, a sink which
+// can't be reached does not affect the test
+#[derive(Default)]
+pub(super) struct StatsSinks {
+    // the percentiles of the response times pushed with each bucket
+    percentiles: Vec<f64>,
+    sinks: Vec<Sink>,
+}
+
+struct Sink {
+    format: StatsSinkFormat,
+    prefix: String,
+    sample_rate: Option<f64>,
+    task: JoinHandle<()>,
+    // each message is sent in as few writes as possible
+    tx: UnboundedSender<Vec<String>>,
+}
+
+impl StatsSinks {
+    pub(super) fn new(sinks: &[StatsSink], percentiles: &[f64]) -> Result<Self, TestError> {
+        let sinks = sinks
+            .iter()
+            .map(|sink| {
+                let (tx, rx) = futures_channel::unbounded();
+                let task = match (sink.format, sink.protocol) {
+                    (StatsSinkFormat::Otlp, _) => {
+                        let uri: Uri = sink
+                            .to
+                            .parse()
+                            .map_err(|_| TestError::InvalidUrl(sink.to.clone()))?;
+                        tokio::spawn(send_otlp(uri, rx))
+                    }
+                    (_, protocol) => {
+                        let addr = sink
+                            .to
+                            .to_socket_addrs()
+                            .and_then(|mut addrs| {
+                                addrs.next().ok_or_else(|| {
+                                    std::io::Error::new(
+                                        std::io::ErrorKind::NotFound,
+                                        "address did not resolve",
+                                    )
+                                })
+                            })
+                            .map_err(|e| TestError::InvalidStatsSink(sink.to.clone(), e.into()))?;
+                        match protocol {
+                            StatsSinkProtocol::Tcp => tokio::spawn(send_tcp(addr, rx)),
+                            StatsSinkProtocol::Udp => tokio::spawn(send_udp(addr, rx)),
+                        }
+                    }
+                };
+                Ok(Sink {
+                    format: sink.format,
+                    prefix: sink.prefix.clone(),
+                    sample_rate: sink.sample_rate,
+                    task,
+                    tx,
+                })
+            })
+            .collect::<Result<_, TestError>>()?;
+        Ok(StatsSinks {
+            percentiles: percentiles.to_vec(),
+            sinks,
+        })
+    }
+
+    // stop taking stats and give what has already been pushed a chance to be sent
+    pub(super) async fn close(&mut self) {
+        let tasks = mem::take(&mut self.sinks).into_iter().map(|sink| sink.task);
+        let _ = time::timeout(FLUSH_TIMEOUT, join_all(tasks)).await;
+    }
+
+    // push a closed bucket to the sinks which aren't sampling individual responses
+    pub(super) fn push_bucket(
+        &self,
+        bucket: &TimeBucket,
+        tags: &BTreeMap<Tags, usize>,
+        bucket_size: u64,
+    ) {
+        let groups: Vec<_> = tags
+            .iter()
+            .filter_map(|(tags, index)| bucket.entries.get(index).map(|stats| (tags, stats)))
+            .collect();
+        if groups.is_empty() {
+            return;
+        }
+        for sink in self.sinks.iter().filter(|s| s.sample_rate.is_none()) {
+            let lines = match sink.format {
+                StatsSinkFormat::Influx => influx_bucket(
+                    &sink.prefix,
+                    &groups,
+                    &self.percentiles,
+                    bucket.time * NANOS_PER_SEC,
+                ),
+                StatsSinkFormat::Otlp => {
+                    otlp_bucket(&sink.prefix, &groups, bucket.time, bucket_size)
+                }
+                StatsSinkFormat::StatsD => statsd_bucket(&sink.prefix, &groups, &self.percentiles),
+            };
+            let _ = sink.tx.unbounded_send(lines);
+        }
+    }
+
+    // push a single response stat to the sinks which sample responses
+    pub(super) fn push_stat(&self, stat: &ResponseStat) {
+        for sink in &self.sinks {
+            let rate = match sink.sample_rate {
+                Some(rate) if rand::random::<f64>() < rate => rate,
+                _ => continue,
+            };
+            let lines = match sink.format {
+                StatsSinkFormat::Influx => influx_stat(&sink.prefix, stat, rate),
+                StatsSinkFormat::StatsD => statsd_stat(&sink.prefix, stat, rate),
+                // sampling is not allowed with OTLP
+                StatsSinkFormat::Otlp => continue,
+            };
+            if !lines.is_empty() {
+                let _ = sink.tx.unbounded_send(lines);
+            }
+        }
+    }
+}
+
+async fn send_udp(addr: SocketAddr, mut rx: UnboundedReceiver<Vec<String>>) {
+    let bind_addr = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = match UdpSocket::bind(bind_addr).await {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    while let Some(lines) = rx.next().await {
+        let mut datagram = String::new();
+        for line in lines {
+            if !datagram.is_empty() && datagram.len() + line.len() + 1 > MAX_DATAGRAM_SIZE {
+                let _ = socket.send_to(datagram.as_bytes(), addr).await;
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(&line);
+        }
+        if !datagram.is_empty() {
+            let _ = socket.send_to(datagram.as_bytes(), addr).await;
+        }
+    }
+}
+
+async fn send_tcp(addr: SocketAddr, mut rx: UnboundedReceiver<Vec<String>>) {
+    let mut stream = None;
+    while let Some(lines) = rx.next().await {
+        let mut msg = lines.join("\n");
+        msg.push('\n');
+        // connect on first use, and reconnect after an error
+        if stream.is_none() {
+            stream = TcpStream::connect(addr).await.ok();
+        }
+        if let Some(s) = &mut stream {
+            if s.write_all(msg.as_bytes()).await.is_err() {
+                stream = None;
+            }
+        }
+    }
+}
+
+async fn send_otlp(uri: Uri, mut rx: UnboundedReceiver<Vec<String>>) {
+    let client = Client::builder().build::<_, Body>(HttpsConnector::new());
+    while let Some(bodies) = rx.next().await {
+        for body in bodies {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(uri.clone())
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.into());
+            if let Ok(request) = request {
+                let _ = client.request(request).await;
+            }
+        }
+    }
+}
+
+// the round-trip times at each of the percentiles, named such as `p99_9`, plus the min, max and
+// mean, in milliseconds
+fn rtt_values(stats: &BucketGroupStats, percentiles: &[f64]) -> Vec<(String, f64)> {
+    let histogram = &stats.rtt_histogram;
+    if histogram.is_empty() {
+        return Vec::new();
+    }
+    let mut values: Vec<_> = rtt_percentiles(histogram, percentiles)
+        .into_iter()
+        .map(|(name, value)| (name.replace('.', "_"), value))
+        .collect();
+    values.push(("min".into(), histogram.min() as f64 / MICROS_TO_MS));
+    values.push(("max".into(), histogram.max() as f64 / MICROS_TO_MS));
+    values.push(("mean".into(), histogram.mean().round() / MICROS_TO_MS));
+    values
+}
+
+fn test_error_count(stats: &BucketGroupStats) -> u64 {
+    stats.test_errors.values().sum()
+}
+
+// StatsD, with the DogStatsD extension for tags
+fn statsd_tags(tags: &Tags, extra: Option<(&str, &str)>) -> String {
+    let sanitize = |s: &str| s.replace(&['|', ',', '#', '\n'][..], "_");
+    let tags: Vec<_> = tags
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .chain(extra)
+        .map(|(k, v)| format!("{}:{}", sanitize(k), sanitize(v)))
+        .collect();
+    if tags.is_empty() {
+        String::new()
+    } else {
+        format!("|#{}", tags.join(","))
+    }
+}
+
+fn statsd_bucket(
+    prefix: &str,
+    groups: &[(&Tags, &BucketGroupStats)],
+    percentiles: &[f64],
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (tags, stats) in groups {
+        let t = statsd_tags(tags, None);
+        lines.push(format!(
+            "{}.calls:{}|c{}",
+            prefix,
+            stats.rtt_histogram.len(),
+            t
+        ));
+        for (status, count) in &stats.status_counts {
+            let status = status.to_string();
+            let t = statsd_tags(tags, Some(("status", &status)));
+            lines.push(format!("{}.responses:{}|c{}", prefix, count, t));
+        }
+        if stats.request_timeouts > 0 {
+            let n = stats.request_timeouts;
+            lines.push(format!("{}.request_timeouts:{}|c{}", prefix, n, t));
+        }
+        let errors = test_error_count(stats);
+        if errors > 0 {
+            lines.push(format!("{}.test_errors:{}|c{}", prefix, errors, t));
+        }
+        if !stats.request_bytes.is_empty() {
+            let n = stats.request_bytes.wire;
+            lines.push(format!("{}.request_bytes:{}|c{}", prefix, n, t));
+        }
+        if !stats.response_bytes.is_empty() {
+            let n = stats.response_bytes.wire;
+            lines.push(format!("{}.response_bytes:{}|c{}", prefix, n, t));
+        }
+        for (name, value) in rtt_values(stats, percentiles) {
+            lines.push(format!("{}.rtt.{}:{}|g{}", prefix, name, value, t));
+        }
+    }
+    lines
+}
+
+fn statsd_stat(prefix: &str, stat: &ResponseStat, rate: f64) -> Vec<String> {
+    let t = statsd_tags(&stat.tags, None);
+    let mut lines = Vec::new();
+    match &stat.kind {
+        StatKind::Response(status) => {
+            let status = status.to_string();
+            let t = statsd_tags(&stat.tags, Some(("status", &status)));
+            lines.push(format!("{}.responses:1|c|@{}{}", prefix, rate, t));
+        }
+        StatKind::RecoverableError(RecoverableError::Timeout(..)) => {
+            lines.push(format!("{}.request_timeouts:1|c|@{}{}", prefix, rate, t));
+        }
+        StatKind::RecoverableError(_) => {
+            lines.push(format!("{}.test_errors:1|c|@{}{}", prefix, rate, t));
+        }
+        StatKind::Bytes(request, response) => {
+            let (request, response) = (request.wire, response.wire);
+            lines.push(format!(
+                "{}.request_bytes:{}|c|@{}{}",
+                prefix, request, rate, t
+            ));
+            lines.push(format!(
+                "{}.response_bytes:{}|c|@{}{}",
+                prefix, response, rate, t
+            ));
+        }
+        StatKind::ScheduledRtt(rtt) => {
+            let rtt = *rtt as f64 / MICROS_TO_MS;
+            lines.push(format!(
+                "{}.scheduled_rtt:{}|ms|@{}{}",
+                prefix, rtt, rate, t
+            ));
+        }
+        StatKind::Metric(name, kind, value) => {
+            let kind = match kind {
+                MetricKind::Counter => "c",
+                MetricKind::Gauge => "g",
+                MetricKind::Histogram => "h",
+            };
+            lines.push(format!(
+                "{}.metrics.{}:{}|{}|@{}{}",
+                prefix, name, value, kind, rate, t
+            ));
+        }
+        StatKind::Check(..) | StatKind::Retry(..) => (),
+    }
+    if let Some(rtt) = stat.rtt {
+        let rtt = rtt as f64 / MICROS_TO_MS;
+        lines.push(format!("{}.rtt:{}|ms|@{}{}", prefix, rtt, rate, t));
+    }
+    lines
+}
+
+// InfluxDB line protocol
+fn influx_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+}
+
+fn influx_series(measurement: &str, tags: &Tags, extra: Option<(&str, &str)>) -> String {
+    let mut series = influx_escape(measurement);
+    let tags = tags
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .chain(extra)
+        // empty tag values aren't allowed
+        .filter(|(_, v)| !v.is_empty());
+    for (k, v) in tags {
+        let _ = write!(series, ",{}={}", influx_escape(k), influx_escape(v));
+    }
+    series
+}
+
+fn influx_bucket(
+    prefix: &str,
+    groups: &[(&Tags, &BucketGroupStats)],
+    percentiles: &[f64],
+    timestamp: u64,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (tags, stats) in groups {
+        let mut fields = format!(
+            "calls={}i,request_timeouts={}i,test_errors={}i,request_bytes={}i,response_bytes={}i",
+            stats.rtt_histogram.len(),
+            stats.request_timeouts,
+            test_error_count(stats),
+            stats.request_bytes.wire,
+            stats.response_bytes.wire,
+        );
+        for (name, value) in rtt_values(stats, percentiles) {
+            let _ = write!(fields, ",rtt_{}={}", name, value);
+        }
+        let series = influx_series(prefix, tags, None);
+        lines.push(format!("{} {} {}", series, fields, timestamp));
+        for (status, count) in &stats.status_counts {
+            let status = status.to_string();
+            let measurement = format!("{}_responses", prefix);
+            let series = influx_series(&measurement, tags, Some(("status", &status)));
+            lines.push(format!("{} count={}i {}", series, count, timestamp));
+        }
+    }
+    lines
+}
+
+fn influx_stat(prefix: &str, stat: &ResponseStat, rate: f64) -> Vec<String> {
+    let measurement = format!("{}_responses", prefix);
+    let status;
+    let series = match &stat.kind {
+        StatKind::Response(s) => {
+            status = s.to_string();
+            influx_series(&measurement, &stat.tags, Some(("status", &status)))
+        }
+        StatKind::RecoverableError(RecoverableError::Timeout(..)) => {
+            influx_series(&measurement, &stat.tags, Some(("error", "timeout")))
+        }
+        StatKind::RecoverableError(_) => {
+            influx_series(&measurement, &stat.tags, Some(("error", "test_error")))
+        }
+        StatKind::Bytes(..)
+        | StatKind::Check(..)
+        | StatKind::Metric(..)
+        | StatKind::Retry(..)
+        | StatKind::ScheduledRtt(_) => return Vec::new(),
+    };
+    let mut fields = format!("count=1i,sample_rate={}", rate);
+    if let Some(rtt) = stat.rtt {
+        let _ = write!(fields, ",rtt={}", rtt as f64 / MICROS_TO_MS);
+    }
+    let timestamp = stat
+        .time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    vec![format!("{} {} {}", series, fields, timestamp)]
+}
+
+// OTLP/HTTP with a JSON body. Each bucket is sent as delta sums and a delta histogram
+fn otlp_attributes(tags: &Tags, extra: Option<(&str, &str)>) -> json::Value {
+    tags.iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .chain(extra)
+        .map(|(k, v)| json::json!({ "key": k, "value": { "stringValue": v } }))
+        .collect()
+}
+
+fn otlp_bucket(
+    prefix: &str,
+    groups: &[(&Tags, &BucketGroupStats)],
+    time: u64,
+    bucket_size: u64,
+) -> Vec<String> {
+    // OpenTelemetry's enum value for delta temporality
+    const DELTA: u8 = 1;
+    let start = (time * NANOS_PER_SEC).to_string();
+    let end = ((time + bucket_size) * NANOS_PER_SEC).to_string();
+    let point = |attributes: json::Value, value: u64| {
+        json::json!({
+            "attributes": attributes,
+            "startTimeUnixNano": start,
+            "timeUnixNano": end,
+            "asInt": value.to_string(),
+        })
+    };
+    let sum = |name: &str, points: Vec<json::Value>| {
+        json::json!({
+            "name": format!("{}.{}", prefix, name),
+            "sum": {
+                "aggregationTemporality": DELTA,
+                "isMonotonic": true,
+                "dataPoints": points,
+            },
+        })
+    };
+    let calls = groups
+        .iter()
+        .map(|(tags, stats)| point(otlp_attributes(tags, None), stats.rtt_histogram.len()))
+        .collect();
+    let responses = groups
+        .iter()
+        .flat_map(|(tags, stats)| {
+            stats.status_counts.iter().map(move |(status, count)| {
+                let status = status.to_string();
+                point(otlp_attributes(tags, Some(("status", &status))), *count)
+            })
+        })
+        .collect();
+    let timeouts = groups
+        .iter()
+        .map(|(tags, stats)| point(otlp_attributes(tags, None), stats.request_timeouts))
+        .collect();
+    let errors = groups
+        .iter()
+        .map(|(tags, stats)| point(otlp_attributes(tags, None), test_error_count(stats)))
+        .collect();
+    let rtt = groups
+        .iter()
+        .filter(|(_, stats)| !stats.rtt_histogram.is_empty())
+        .map(|(tags, stats)| {
+            let histogram = &stats.rtt_histogram;
+            // the buckets are cumulative, but OTLP wants the count within each bucket, with a
+            // final bucket for everything above the last bound
+            let buckets = stats.rtt_buckets();
+            let bounds: Vec<_> = buckets
+                .iter()
+                .map(|(b, _)| *b as f64 / MICROS_TO_MS)
+                .collect();
+            let mut previous = 0;
+            let mut counts: Vec<_> = buckets
+                .iter()
+                .map(|(_, count)| {
+                    let n = count - previous;
+                    previous = *count;
+                    n.to_string()
+                })
+                .collect();
+            counts.push((histogram.len() - previous).to_string());
+            json::json!({
+                "attributes": otlp_attributes(tags, None),
+                "startTimeUnixNano": start,
+                "timeUnixNano": end,
+                "count": histogram.len().to_string(),
+                "sum": histogram.mean() * histogram.len() as f64 / MICROS_TO_MS,
+                "min": histogram.min() as f64 / MICROS_TO_MS,
+                "max": histogram.max() as f64 / MICROS_TO_MS,
+                "explicitBounds": bounds,
+                "bucketCounts": counts,
+            })
+        })
+        .collect::<Vec<_>>();
+    let body = json::json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": "pewpew" } },
+                ],
+            },
+            "scopeMetrics": [{
+                "scope": { "name": "pewpew", "version": clap::crate_version!() },
+                "metrics": [
+                    sum("calls", calls),
+                    sum("responses", responses),
+                    sum("request_timeouts", timeouts),
+                    sum("test_errors", errors),
+                    {
+                        "name": format!("{}.rtt", prefix),
+                        "unit": "ms",
+                        "histogram": {
+                            "aggregationTemporality": DELTA,
+                            "dataPoints": rtt,
+                        },
+                    },
+                ],
+            }],
+        }],
+    });
+    vec![body.to_string()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::ByteCounts;
+
+    use std::{sync::Arc, time::Duration};
+
+    fn create_stats() -> (Tags, BucketGroupStats) {
+        let tags: Tags = vec![
+            ("_id".to_string(), "0".to_string()),
+            ("url".to_string(), "http://localhost/a b".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let mut stats = BucketGroupStats::default();
+        for (status, rtt) in &[(200, 1_000), (200, 1_500), (500, 2_000)] {
+            stats.append(ResponseStat {
+                kind: StatKind::Response(*status),
+                rtt: Some(*rtt),
+                time: UNIX_EPOCH,
+                tags: Arc::new(tags.clone()),
+            });
+        }
+        let bytes = ByteCounts {
+            decoded: 20,
+            wire: 10,
+        };
+        stats.append(ResponseStat {
+            kind: StatKind::Bytes(bytes, bytes),
+            rtt: None,
+            time: UNIX_EPOCH,
+            tags: Arc::new(tags.clone()),
+        });
+        (tags, stats)
+    }
+
+    #[test]
+    fn formats_statsd() {
+        let (tags, stats) = create_stats();
+        let lines = statsd_bucket("lt", &[(&tags, &stats)], &[50.0, 99.9]);
+        let t = "|#_id:0,url:http://localhost/a b";
+        let expected = vec![
+            format!("lt.calls:3|c{}", t),
+            format!("lt.responses:2|c{},status:200", t),
+            format!("lt.responses:1|c{},status:500", t),
+            format!("lt.request_bytes:10|c{}", t),
+            format!("lt.response_bytes:10|c{}", t),
+            format!("lt.rtt.p50:1.5|g{}", t),
+            format!("lt.rtt.p99_9:2|g{}", t),
+            format!("lt.rtt.min:1|g{}", t),
+            format!("lt.rtt.max:2|g{}", t),
+            format!("lt.rtt.mean:1.5|g{}", t),
+        ];
+        assert_eq!(lines, expected);
+
+        let stat = ResponseStat {
+            kind: StatKind::RecoverableError(RecoverableError::Timeout(UNIX_EPOCH)),
+            rtt: None,
+            time: UNIX_EPOCH,
+            tags: Arc::new(tags),
+        };
+        let lines = statsd_stat("lt", &stat, 0.5);
+        assert_eq!(lines, vec![format!("lt.request_timeouts:1|c|@0.5{}", t)]);
+    }
+
+    #[test]
+    fn formats_influx() {
+        let (tags, stats) = create_stats();
+        let lines = influx_bucket("lt", &[(&tags, &stats)], &[75.0], 5);
+        let series = "_id=0,url=http://localhost/a\\ b";
+        let expected = vec![
+            format!(
+                "lt,{} calls=3i,request_timeouts=0i,test_errors=0i,request_bytes=10i,\
+                 response_bytes=10i,rtt_p75=2,rtt_min=1,rtt_max=2,rtt_mean=1.5 5",
+                series
+            ),
+            format!("lt_responses,{},status=200 count=2i 5", series),
+            format!("lt_responses,{},status=500 count=1i 5", series),
+        ];
+        assert_eq!(lines, expected);
+
+        let stat = ResponseStat {
+            kind: StatKind::Response(204),
+            rtt: Some(1_500),
+            time: UNIX_EPOCH + Duration::from_secs(1),
+            tags: Arc::new(tags),
+        };
+        let lines = influx_stat("lt", &stat, 0.1);
+        let expected = format!(
+            "lt_responses,{},status=204 count=1i,sample_rate=0.1,rtt=1.5 1000000000",
+            series
+        );
+        assert_eq!(lines, vec![expected]);
+    }
+
+    #[test]
+    fn formats_otlp() {
+        let (tags, stats) = create_stats();
+        let body = otlp_bucket("lt", &[(&tags, &stats)], 10, 60);
+        let body: json::Value = json::from_str(&body[0]).unwrap();
+        let metrics = &body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["name"], "lt.calls");
+        let point = &metrics[0]["sum"]["dataPoints"][0];
+        assert_eq!(point["asInt"], "3");
+        assert_eq!(point["startTimeUnixNano"], "10000000000");
+        assert_eq!(point["timeUnixNano"], "70000000000");
+        assert_eq!(point["attributes"][0]["key"], "_id");
+        assert_eq!(metrics[1]["sum"]["dataPoints"][1]["asInt"], "1");
+        let rtt = &metrics[4]["histogram"]["dataPoints"][0];
+        assert_eq!(rtt["count"], "3");
+        assert_eq!(rtt["explicitBounds"][1], 2.0);
+        assert_eq!(rtt["bucketCounts"][0], "1");
+        assert_eq!(rtt["bucketCounts"][1], "2");
+        assert_eq!(rtt["bucketCounts"][2], "0");
+    }
+
+    #[test]
+    fn sends_buckets() {
+        use config::{StatsSink, StatsSinkFormat, StatsSinkProtocol};
+        use tokio::{io::AsyncReadExt, net::TcpListener};
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let sink = |format, protocol, to: SocketAddr| StatsSink {
+                format,
+                prefix: "lt".into(),
+                protocol,
+                sample_rate: None,
+                to: to.to_string(),
+            };
+            let sinks = [
+                sink(
+                    StatsSinkFormat::StatsD,
+                    StatsSinkProtocol::Udp,
+                    udp.local_addr().unwrap(),
+                ),
+                sink(
+                    StatsSinkFormat::Influx,
+                    StatsSinkProtocol::Tcp,
+                    tcp.local_addr().unwrap(),
+                ),
+            ];
+            let mut sinks = StatsSinks::new(&sinks, &[50.0]).unwrap();
+
+            // enough endpoints that the StatsD lines don't fit in one datagram
+            let mut bucket = TimeBucket::new(10);
+            let mut tags = BTreeMap::new();
+            for i in 0..30 {
+                let group: Tags = vec![
+                    ("_id".to_string(), i.to_string()),
+                    ("url".to_string(), format!("http://localhost/{}", i)),
+                ]
+                .into_iter()
+                .collect();
+                let stat = ResponseStat {
+                    kind: StatKind::Response(200),
+                    rtt: Some(1_000),
+                    time: UNIX_EPOCH,
+                    tags: Arc::new(group.clone()),
+                };
+                bucket.append(stat, i, 3);
+                tags.insert(group, i);
+            }
+            let groups: Vec<_> = tags
+                .iter()
+                .map(|(tags, index)| (tags, &bucket.entries[index]))
+                .collect();
+            let statsd_lines = statsd_bucket("lt", &groups, &[50.0]);
+            let influx_lines = influx_bucket("lt", &groups, &[50.0], 10 * NANOS_PER_SEC);
+            sinks.push_bucket(&bucket, &tags, 60);
+            sinks.close().await;
+
+            let mut received = Vec::new();
+            let mut datagrams = 0;
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE * 2];
+            while received.len() < statsd_lines.len() {
+                let n = time::timeout(Duration::from_secs(5), udp.recv(&mut buf))
+                    .await
+                    .expect("should receive each datagram")
+                    .unwrap();
+                assert!(n <= MAX_DATAGRAM_SIZE, "datagram of {} bytes", n);
+                let datagram = std::str::from_utf8(&buf[..n]).unwrap();
+                received.extend(datagram.lines().map(String::from));
+                datagrams += 1;
+            }
+            assert!(datagrams > 1);
+            assert_eq!(received, statsd_lines);
+
+            let (mut stream, _) = tcp.accept().await.unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).await.unwrap();
+            assert_eq!(received, influx_lines.join("\n") + "\n");
+        });
+    }
+}
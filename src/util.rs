@@ -25,6 +25,14 @@ pub fn config_limit_to_channel_limit(limit: config::Limit) -> channel::Limit {
     }
 }
 
+// escape the characters which are special in HTML and XML text and attribute values
+pub fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
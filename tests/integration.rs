@@ -1,10 +1,9 @@
 use std::env;
 
-use futures::FutureExt;
 use test_common::{start_test_server, TestWriter};
 use tokio::runtime::Runtime;
 
-fn run_test(path: &str) -> (bool, String, String) {
+fn run_test(path: &str) -> (Result<(), pewpew::RunError>, String, String) {
     let rt = Runtime::new().unwrap();
     rt.block_on(async move {
         let (port, kill_server, _) = start_test_server(None);
@@ -14,11 +13,17 @@ fn run_test(path: &str) -> (bool, String, String) {
 
         let run_config = pewpew::RunConfig {
             config_file: path.into(),
+            junit: None,
+            metrics_listen: None,
             output_format: pewpew::RunOutputFormat::Human,
             results_dir: Some("./".into()),
             stats_file: "integration.json".into(),
+            stats_file_compression: None,
             stats_file_format: pewpew::StatsFileFormat::Json,
+            stats_file_rotate_interval: None,
+            stats_file_rotate_size: None,
             start_at: None,
+            tui: false,
             watch_config_file: true,
         };
         let exec_config = pewpew::ExecConfig::Run(run_config);
@@ -29,21 +34,19 @@ fn run_test(path: &str) -> (bool, String, String) {
         let stdout2 = stdout.clone();
         let stderr2 = stderr.clone();
 
-        let success = pewpew::create_run(exec_config, ctrlc_channel, stdout, stderr)
-            .map(|r| if r.is_ok() { true } else { false })
-            .await;
+        let result = pewpew::create_run(exec_config, ctrlc_channel, stdout, stderr).await;
 
         let _ = kill_server.send(());
 
-        (success, stdout2.get_string(), stderr2.get_string())
+        (result, stdout2.get_string(), stderr2.get_string())
     })
 }
 
 #[test]
 fn int1() {
-    let (success, _stdin, stderr) = run_test("tests/integration.yaml");
+    let (result, _stdin, stderr) = run_test("tests/integration.yaml");
 
-    assert!(success, "test run failed. {}", stderr);
+    assert!(result.is_ok(), "test run failed. {}", stderr);
 
     let left = stderr;
     let right = include_str!("integration.stderr.out");
@@ -52,9 +55,9 @@ fn int1() {
 
 #[test]
 fn int_on_demand() {
-    let (success, _stdin, stderr) = run_test("tests/int_on_demand.yaml");
+    let (result, _stdin, stderr) = run_test("tests/int_on_demand.yaml");
 
-    assert!(success, "test run failed. {}", stderr);
+    assert!(result.is_ok(), "test run failed. {}", stderr);
 
     assert!(
         stderr.len() > 0,
@@ -70,3 +73,17 @@ fn int_on_demand() {
         );
     }
 }
+
+#[test]
+fn int_thresholds() {
+    let (result, stdout, _stderr) = run_test("tests/int_thresholds.yaml");
+
+    let e = result.expect_err("expected the threshold to fail the test");
+    assert_eq!(e, pewpew::RunError::ThresholdFailed);
+    assert_eq!(e.exit_code(), 2);
+    assert!(
+        stdout.contains("calls > 1000"),
+        "expected the threshold report in stdout. Instead saw: {}",
+        stdout
+    );
+}